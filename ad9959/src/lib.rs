@@ -14,11 +14,26 @@ use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
 ///
 /// The chip supports a number of serial interfaces to improve data throughput, including normal,
 /// dual, and quad SPI configurations.
-pub struct Ad9959<INTERFACE> {
+pub struct Ad9959<INTERFACE, IO> {
     interface: INTERFACE,
+    io_update: IO,
+    io_update_mode: IoUpdateMode,
     reference_clock_frequency: f32,
     system_clock_multiplier: u8,
+    clock_configured: bool,
     communication_mode: Mode,
+    enabled_channels: Channel,
+}
+
+/// Specifies how configuration changes are latched into the active DDS registers using the
+/// io_update signal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum IoUpdateMode {
+    /// The driver pulses io_update after every channel configuration change.
+    Pulse,
+    /// The driver never pulses io_update after construction. Latching is left to external
+    /// hardware, e.g. a timer generating io_update synchronized to a stream of profile updates.
+    HrTimer,
 }
 
 /// A trait that allows a HAL to provide a means of communicating with the AD9959.
@@ -57,6 +72,7 @@ bitflags! {
 /// The configuration registers within the AD9959 DDS device. The values of each register are
 /// equivalent to the address.
 #[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone)]
 #[repr(u8)]
 pub enum Register {
     CSR = 0x00,
@@ -86,6 +102,52 @@ pub enum Register {
     CW15 = 0x18,
 }
 
+/// The fraction of the system clock frequency above which output frequencies are considered near
+/// the Nyquist frequency. Above 40% of the system clock, the output is significantly attenuated by
+/// the sinc response of the DAC and its images are difficult to separate by the reconstruction
+/// filter.
+pub const NEAR_NYQUIST_FRACTION: f32 = 0.4;
+
+/// The time in microseconds to wait for the PLL to lock after the system clock configuration has
+/// been latched. Waiting 2 ms covers the lock time of the reference clock multiplier.
+const PLL_LOCK_TIME_US: u16 = 2_000;
+
+/// The width of each register in bytes, indexed by the register address.
+const REGISTER_WIDTHS: [usize; 0x19] = [
+    1, 3, 2, 3, 4, 2, 3, 2, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+];
+
+/// The channel word registers holding the profiles selected by the profile pins, starting at
+/// profile slot 1.
+const CHANNEL_WORDS: [Register; 15] = [
+    Register::CW1,
+    Register::CW2,
+    Register::CW3,
+    Register::CW4,
+    Register::CW5,
+    Register::CW6,
+    Register::CW7,
+    Register::CW8,
+    Register::CW9,
+    Register::CW10,
+    Register::CW11,
+    Register::CW12,
+    Register::CW13,
+    Register::CW14,
+    Register::CW15,
+];
+
+/// The channel parameter selected by the profile pins. The value of this enumeration is
+/// equivalent to the amplitude/frequency/phase select bits of the DDS CFR register.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Modulation {
+    Disabled = 0b00,
+    Amplitude = 0b01,
+    Frequency = 0b10,
+    Phase = 0b11,
+}
+
 /// Possible errors generated by the AD9959 driver.
 #[derive(Debug)]
 pub enum Error {
@@ -94,38 +156,56 @@ pub enum Error {
     Bounds,
     Pin,
     Frequency,
+    ClockNotConfigured,
+    ModulationDisabled,
+    IoUpdateMode,
 }
 
-impl<I: Interface> Ad9959<I> {
+impl<I: Interface, IO: OutputPin> Ad9959<I, IO> {
     /// Construct and initialize the DDS.
     ///
     /// Args:
     /// * `interface` - An interface to the DDS.
-    /// * `reset_pin` - A pin connected to the DDS reset input.
+    /// * `reset_pin` - A pin connected to the DDS reset input. The pin is only borrowed, such that
+    ///   it remains available to reset the DDS again later on.
     /// * `io_update` - A pin connected to the DDS io_update input.
     /// * `delay` - A delay implementation for blocking operation for specific amounts of time.
     /// * `desired_mode` - The desired communication mode of the interface to the DDS.
     /// * `clock_frequency` - The clock frequency of the reference clock input.
     /// * `multiplier` - The desired clock multiplier for the system clock. This multiplies
     ///   `clock_frequency` to generate the system clock.
+    /// * `io_update_mode` - Specifies whether the driver latches channel configuration changes.
+    ///   The initial configuration is always latched by the driver.
+    ///
+    /// Note:
+    /// The reset pulse and the interface handshake rely on `delay` lasting at least 5 us. The
+    /// delay must never be shorter than requested, also at such short intervals. Delays based on
+    /// coarse timers that may expire early are not suitable and lead to failed initialization,
+    /// typically reported as [Error::Check]. A cycle counter based delay is recommended.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         interface: I,
-        mut reset_pin: impl OutputPin,
-        io_update: &mut impl OutputPin,
+        reset_pin: &mut impl OutputPin,
+        mut io_update: IO,
         delay: &mut impl DelayUs<u8>,
         desired_mode: Mode,
         clock_frequency: f32,
         multiplier: u8,
+        io_update_mode: IoUpdateMode,
     ) -> Result<Self, Error> {
+        io_update.set_low().or(Err(Error::Pin))?;
+
         let mut ad9959 = Ad9959 {
             interface,
+            io_update,
+            io_update_mode,
             reference_clock_frequency: clock_frequency,
             system_clock_multiplier: 1,
+            clock_configured: false,
             communication_mode: desired_mode,
+            enabled_channels: Channel::ALL,
         };
 
-        io_update.set_low().or(Err(Error::Pin))?;
-
         // Reset the AD9959
         reset_pin.set_high().or(Err(Error::Pin))?;
 
@@ -142,18 +222,18 @@ impl<I: Interface> Ad9959<I> {
             .or(Err(Error::Interface))?;
 
         // Program the interface configuration in the AD9959. Default to all channels enabled.
-        let csr = [Channel::ALL.bits() | desired_mode as u8];
+        let csr = [ad9959.enabled_channels.bits() | desired_mode as u8];
         ad9959.write(Register::CSR, &csr)?;
 
         // Latch the new interface configuration.
-        io_update.set_high().or(Err(Error::Pin))?;
+        ad9959.io_update.set_high().or(Err(Error::Pin))?;
 
         // Delay for at least 1 SYNC_CLK period for the update to occur. The SYNC_CLK is guaranteed
         // to be at least 250KHz (1/4 of 1MHz minimum REF_CLK). We use 5uS instead of 4uS to
         // guarantee conformance with datasheet requirements.
         delay.delay_us(5);
 
-        io_update.set_low().or(Err(Error::Pin))?;
+        ad9959.io_update.set_low().or(Err(Error::Pin))?;
 
         ad9959
             .interface
@@ -179,18 +259,36 @@ impl<I: Interface> Ad9959<I> {
         ad9959.configure_system_clock(clock_frequency, multiplier)?;
 
         // Latch the new clock configuration.
-        io_update.set_high().or(Err(Error::Pin))?;
+        ad9959.io_update.set_high().or(Err(Error::Pin))?;
 
         // Delay for at least 1 SYNC_CLK period for the update to occur. The SYNC_CLK is guaranteed
         // to be at least 250KHz (1/4 of 1MHz minimum REF_CLK). We use 5uS instead of 4uS to
         // guarantee conformance with datasheet requirements.
         delay.delay_us(5);
 
-        io_update.set_low().or(Err(Error::Pin))?;
+        ad9959.io_update.set_low().or(Err(Error::Pin))?;
 
         Ok(ad9959)
     }
 
+    /// Latch configuration changes into the active registers if the driver is responsible for
+    /// generating io_update.
+    fn latch(&mut self) -> Result<(), Error> {
+        if self.io_update_mode == IoUpdateMode::Pulse {
+            self.io_update.set_high().or(Err(Error::Pin))?;
+
+            // The io_update pulse must last at least 1 SYNC_CLK period. Any serial transaction
+            // with the DDS lasts many SYNC_CLK periods, so a register read is used to time the
+            // pulse without a delay implementation.
+            let mut csr = [0];
+            self.read(Register::CSR, &mut csr)?;
+
+            self.io_update.set_low().or(Err(Error::Pin))?;
+        }
+
+        Ok(())
+    }
+
     fn read(&mut self, reg: Register, data: &mut [u8]) -> Result<(), Error> {
         self.interface
             .read(reg as u8, data)
@@ -203,6 +301,46 @@ impl<I: Interface> Ad9959<I> {
             .or(Err(Error::Interface))
     }
 
+    /// Read a number of consecutive registers.
+    ///
+    /// Args:
+    /// * `start` - The first register to read.
+    /// * `dest` - A location to store the register contents. It must be sized to the total width
+    ///   of the registers to read, starting with the contents of `start`.
+    ///
+    /// Note:
+    /// Channel registers are read from the channels currently enabled in the CSR, see
+    /// [Ad9959::enabled_channels], so a single channel should be enabled. The CSR is not modified,
+    /// which avoids the CSR writes of reading registers individually. The AD9959 serial port
+    /// expects an instruction byte for every register, so each register is still transferred in
+    /// a separate read transaction. Fails with [Error::Bounds] if `dest` does not end on a
+    /// register boundary or extends beyond the last register.
+    pub fn read_sequential(
+        &mut self,
+        start: Register,
+        dest: &mut [u8],
+    ) -> Result<(), Error> {
+        let mut address = start as usize;
+        let mut remaining = dest;
+
+        while !remaining.is_empty() {
+            let width = *REGISTER_WIDTHS.get(address).ok_or(Error::Bounds)?;
+            if remaining.len() < width {
+                return Err(Error::Bounds);
+            }
+
+            let (register, rest) = remaining.split_at_mut(width);
+            self.interface
+                .read(address as u8, register)
+                .or(Err(Error::Interface))?;
+
+            remaining = rest;
+            address += 1;
+        }
+
+        Ok(())
+    }
+
     /// Configure the internal system clock of the chip.
     ///
     /// Arguments:
@@ -211,24 +349,29 @@ impl<I: Interface> Ad9959<I> {
     ///
     /// Returns:
     /// The actual frequency configured for the internal system clock.
+    ///
+    /// Note:
+    /// The AD9959 provides no indication of the PLL lock state, neither in a register nor on a
+    /// pin, so the returned frequency is only nominal. Callers must allow for the PLL lock time
+    /// specified in the datasheet after the new configuration is latched by an IO update.
     fn configure_system_clock(
         &mut self,
         reference_clock_frequency: f32,
         multiplier: u8,
     ) -> Result<f32, Error> {
-        self.reference_clock_frequency = reference_clock_frequency;
-
+        // A rejected configuration leaves the driver state untouched, such that it keeps matching
+        // the clock the device still runs on.
         if multiplier != 1 && !(4..=20).contains(&multiplier) {
             return Err(Error::Bounds);
         }
 
-        let frequency =
-            multiplier as f32 * self.reference_clock_frequency as f32;
+        let frequency = multiplier as f32 * reference_clock_frequency;
         if frequency > 500_000_000.0f32 {
             return Err(Error::Frequency);
         }
 
-        // TODO: Update / disable any enabled channels?
+        // Channel outputs are left untouched. See `configure_system_clock_safe()` to power them
+        // down during the change.
         let mut fr1: [u8; 3] = [0, 0, 0];
         self.read(Register::FR1, &mut fr1)?;
         fr1[0].set_bits(2..=6, multiplier);
@@ -237,11 +380,188 @@ impl<I: Interface> Ad9959<I> {
         fr1[0].set_bit(7, vco_range);
 
         self.write(Register::FR1, &fr1)?;
+        self.reference_clock_frequency = reference_clock_frequency;
         self.system_clock_multiplier = multiplier;
+        self.clock_configured = true;
 
         Ok(self.system_clock_frequency())
     }
 
+    /// Reconfigure the internal system clock while the channel outputs are powered down.
+    ///
+    /// Arguments:
+    /// * `reference_clock_frequency` - The reference clock frequency provided to the AD9959 core.
+    /// * `multiplier` - The frequency multiplier of the system clock. Must be 1 or 4-20.
+    /// * `delay` - A delay implementation used to wait for the PLL to lock.
+    ///
+    /// Returns:
+    /// The actual frequency configured for the internal system clock.
+    ///
+    /// Note:
+    /// The system clock glitches while the PLL locks to the new configuration, which would produce
+    /// spurious output on live channels. The DAC of every channel that is not powered down is
+    /// powered down before the change and powered up again after the PLL lock time has elapsed.
+    /// The channel function registers are restored to their exact prior contents afterwards, also
+    /// if the new configuration is rejected. This requires the driver to generate io_update and
+    /// fails with [Error::IoUpdateMode] otherwise. A rejected configuration keeps the previous
+    /// system clock.
+    ///
+    /// ```
+    /// use ad9959::{Ad9959Builder, Channel, Error, Interface, Mode};
+    /// use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+    /// #
+    /// # const CSR: u8 = 0x00;
+    /// # const CFR: u8 = 0x03;
+    /// #
+    /// # // A mock interface with a channel function register per channel, selected by the CSR.
+    /// # struct Registers {
+    /// #     shared: [[u8; 4]; 0x19],
+    /// #     cfr: [[u8; 3]; 4],
+    /// # }
+    /// #
+    /// # impl Registers {
+    /// #     fn selected(&self) -> impl Iterator<Item = usize> {
+    /// #         let csr = self.shared[CSR as usize][0];
+    /// #         (0..4).filter(move |channel| csr & (0x10 << channel) != 0)
+    /// #     }
+    /// # }
+    /// #
+    /// # impl Interface for Registers {
+    /// #     type Error = ();
+    /// #
+    /// #     fn configure_mode(&mut self, _mode: Mode) -> Result<(), ()> {
+    /// #         Ok(())
+    /// #     }
+    /// #
+    /// #     fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), ()> {
+    /// #         if addr == CFR {
+    /// #             for channel in self.selected().collect::<Vec<_>>() {
+    /// #                 self.cfr[channel].copy_from_slice(data);
+    /// #             }
+    /// #         } else {
+    /// #             self.shared[addr as usize][..data.len()].copy_from_slice(data);
+    /// #         }
+    /// #         Ok(())
+    /// #     }
+    /// #
+    /// #     fn read(&mut self, addr: u8, dest: &mut [u8]) -> Result<(), ()> {
+    /// #         if addr == CFR {
+    /// #             let channel = self.selected().next().unwrap();
+    /// #             dest.copy_from_slice(&self.cfr[channel]);
+    /// #         } else {
+    /// #             dest.copy_from_slice(&self.shared[addr as usize][..dest.len()]);
+    /// #         }
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # struct Pin;
+    /// #
+    /// # impl OutputPin for Pin {
+    /// #     type Error = ();
+    /// #
+    /// #     fn set_low(&mut self) -> Result<(), ()> {
+    /// #         Ok(())
+    /// #     }
+    /// #
+    /// #     fn set_high(&mut self) -> Result<(), ()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # struct Delay;
+    /// #
+    /// # impl DelayUs<u8> for Delay {
+    /// #     fn delay_us(&mut self, _us: u8) {}
+    /// # }
+    /// #
+    /// # impl DelayUs<u16> for Delay {
+    /// #     fn delay_us(&mut self, _us: u16) {}
+    /// # }
+    ///
+    /// // Channels one and three are powered up, channels two and four are powered down.
+    /// let cfrs = [[0x00, 0x03, 0x00], [0x00, 0x03, 0x40], [0x80, 0x03, 0x02], [0x00, 0x13, 0x40]];
+    /// let registers = Registers { shared: [[0; 4]; 0x19], cfr: cfrs };
+    ///
+    /// let mut dds = Ad9959Builder::new(registers, Pin, 100e6)
+    ///     .multiplier(5)
+    ///     .build(&mut Pin, &mut Delay)
+    ///     .unwrap();
+    ///
+    /// // A multiplier of 3 is not supported.
+    /// let result = dds.configure_system_clock_safe(50e6, 3, &mut Delay);
+    /// assert!(matches!(result, Err(Error::Bounds)));
+    ///
+    /// // The previous clock is still in use.
+    /// assert_eq!(dds.get_reference_clock_frequency(), 100e6);
+    /// assert!(matches!(dds.get_reference_clock_multiplier(), Ok(5)));
+    /// let frequency = dds.set_frequency(Channel::ONE, 10e6).unwrap();
+    /// assert!((frequency - 10e6).abs() <= dds.frequency_resolution());
+    ///
+    /// // The channel function registers are restored.
+    /// let (registers, _, _) = dds.freeze();
+    /// assert_eq!(registers.cfr, cfrs);
+    /// ```
+    pub fn configure_system_clock_safe(
+        &mut self,
+        reference_clock_frequency: f32,
+        multiplier: u8,
+        delay: &mut impl DelayUs<u16>,
+    ) -> Result<f32, Error> {
+        if self.io_update_mode != IoUpdateMode::Pulse {
+            return Err(Error::IoUpdateMode);
+        }
+
+        let channels =
+            [Channel::ONE, Channel::TWO, Channel::THREE, Channel::FOUR];
+
+        // Power down the DAC of all channels that are currently powered up.
+        let mut cfrs: [[u8; 3]; 4] = [[0; 3]; 4];
+        let mut powered = Channel::empty();
+        for (channel, cfr) in channels.iter().zip(cfrs.iter_mut()) {
+            self.read_channel(*channel, Register::CFR, cfr)?;
+            if !cfr[2].get_bit(6) {
+                powered |= *channel;
+            }
+        }
+
+        for (channel, cfr) in channels.iter().zip(cfrs.iter()) {
+            if powered.contains(*channel) {
+                let mut cfr = *cfr;
+                cfr[2].set_bit(6, true);
+                self.write(
+                    Register::CSR,
+                    &[self.communication_mode as u8 | channel.bits()],
+                )?;
+                self.write(Register::CFR, &cfr)?;
+            }
+        }
+        self.restore_csr()?;
+        self.latch()?;
+
+        let result =
+            self.configure_system_clock(reference_clock_frequency, multiplier);
+        if result.is_ok() {
+            self.latch()?;
+            delay.delay_us(PLL_LOCK_TIME_US);
+        }
+
+        // Restore the prior channel function registers, including the DAC power-down state.
+        for (channel, cfr) in channels.iter().zip(cfrs.iter()) {
+            if powered.contains(*channel) {
+                self.write(
+                    Register::CSR,
+                    &[self.communication_mode as u8 | channel.bits()],
+                )?;
+                self.write(Register::CFR, cfr)?;
+            }
+        }
+        self.restore_csr()?;
+        self.latch()?;
+
+        result
+    }
+
     /// Get the current reference clock frequency in Hz.
     pub fn get_reference_clock_frequency(&self) -> f32 {
         self.reference_clock_frequency
@@ -252,7 +572,28 @@ impl<I: Interface> Ad9959<I> {
         let mut fr1: [u8; 3] = [0, 0, 0];
         self.read(Register::FR1, &mut fr1)?;
 
-        Ok(fr1[0].get_bits(2..=6) as u8)
+        Ok(fr1[0].get_bits(2..=6))
+    }
+
+    /// Enable or disable the SYNC_CLK output.
+    ///
+    /// Args:
+    /// * `enabled` - Specified true to output SYNC_CLK on the SYNC_CLK pin.
+    ///
+    /// Note:
+    /// SYNC_CLK runs at a quarter of the system clock. It is required to synchronize cascaded
+    /// devices and to time external logic to the DDS. The output is enabled after reset and
+    /// may be disabled to reduce noise when unused. The change is latched like channel
+    /// configuration changes, see [IoUpdateMode].
+    pub fn set_sync_clk_output(&mut self, enabled: bool) -> Result<(), Error> {
+        let mut fr1: [u8; 3] = [0, 0, 0];
+        self.read(Register::FR1, &mut fr1)?;
+
+        // The FR1 bit disables the output when set.
+        fr1[2].set_bit(5, !enabled);
+        self.write(Register::FR1, &fr1)?;
+
+        self.latch()
     }
 
     /// Perform a self-test of the communication interface.
@@ -263,43 +604,118 @@ impl<I: Interface> Ad9959<I> {
     /// Returns:
     /// True if the self test succeeded. False otherwise.
     pub fn self_test(&mut self) -> Result<bool, Error> {
-        let mut csr: [u8; 1] = [0];
-        self.read(Register::CSR, &mut csr)?;
-        let old_csr = csr[0];
-
-        // Enable all channels.
-        csr[0].set_bits(4..8, 0xF);
-        self.write(Register::CSR, &csr)?;
-
-        // Read back the enable.
-        csr[0] = 0;
-        self.read(Register::CSR, &mut csr)?;
-        if csr[0].get_bits(4..8) != 0xF {
-            return Ok(false);
-        }
+        self_test(&mut self.interface)
+    }
 
-        // Clear all channel enables.
-        csr[0].set_bits(4..8, 0x0);
-        self.write(Register::CSR, &csr)?;
+    /// Perform a self-test of the communication interface using multi-byte registers.
+    ///
+    /// Note:
+    /// In contrast to [Ad9959::self_test], this writes known patterns to the multi-byte ramp and
+    /// sweep registers of channel one and reads them back, exercising all data lines of the
+    /// configured communication mode. The registers only take effect when sweeps or modulation
+    /// are enabled. The prior register contents and channel enables are restored upon exit.
+    ///
+    /// Returns:
+    /// True if the self test succeeded. False otherwise.
+    pub fn self_test_full(&mut self) -> Result<bool, Error> {
+        // Only access the registers of channel one.
+        self.write(
+            Register::CSR,
+            &[self.communication_mode as u8 | Channel::ONE.bits()],
+        )?;
 
-        // Read back the enable.
-        csr[0] = 0xFF;
-        self.read(Register::CSR, &mut csr)?;
-        if csr[0].get_bits(4..8) != 0 {
-            return Ok(false);
+        let mut passed = true;
+        for (register, length) in [
+            (Register::LSRR, 2),
+            (Register::RDW, 4),
+            (Register::FDW, 4),
+            (Register::CW1, 4),
+        ] {
+            let register = register as u8;
+
+            let mut saved = [0; 4];
+            self.interface
+                .read(register, &mut saved[..length])
+                .or(Err(Error::Interface))?;
+
+            // Use distinct bytes to catch byte ordering errors and toggle every data line.
+            for pattern in [[0xA5, 0x5A, 0x3C, 0xC3], [0x5A, 0xA5, 0xC3, 0x3C]]
+            {
+                let mut data = [0; 4];
+                self.interface
+                    .write(register, &pattern[..length])
+                    .or(Err(Error::Interface))?;
+                self.interface
+                    .read(register, &mut data[..length])
+                    .or(Err(Error::Interface))?;
+                passed &= data[..length] == pattern[..length];
+            }
+
+            self.interface
+                .write(register, &saved[..length])
+                .or(Err(Error::Interface))?;
         }
 
-        // Restore the CSR.
-        csr[0] = old_csr;
-        self.write(Register::CSR, &csr)?;
+        self.restore_csr()?;
 
-        Ok(true)
+        Ok(passed)
     }
 
     /// Get the current system clock frequency in Hz.
+    ///
+    /// Note:
+    /// The AD9959 has no reference clock divider. FR1 only holds the PLL multiplier, the VCO gain,
+    /// and the charge pump control, so the system clock is fully determined by the reference
+    /// clock frequency and the multiplier. An external divider in front of the REF_CLK input must
+    /// be accounted for in the reference clock frequency provided to [Ad9959::new].
     fn system_clock_frequency(&self) -> f32 {
-        self.system_clock_multiplier as f32
-            * self.reference_clock_frequency as f32
+        self.system_clock_multiplier as f32 * self.reference_clock_frequency
+    }
+
+    /// Get the frequency resolution of the channel outputs in Hz.
+    ///
+    /// Note:
+    /// This is the frequency step of one LSB of the frequency tuning word. The frequency
+    /// programmed by [Ad9959::set_frequency] is truncated to this resolution, so it deviates from
+    /// the requested frequency by less than this value.
+    pub fn frequency_resolution(&self) -> f32 {
+        self.system_clock_frequency() / (1u64 << 32) as f32
+    }
+
+    /// Get the channels currently enabled in the CSR.
+    ///
+    /// Note:
+    /// The CSR channel enables select the channels whose registers are accessed by register
+    /// writes and reads. The driver temporarily changes the enables to access individual channels
+    /// and always restores them afterwards.
+    pub fn enabled_channels(&self) -> Channel {
+        self.enabled_channels
+    }
+
+    /// Enable a number of channels in the CSR in addition to the currently enabled channels.
+    ///
+    /// Args:
+    /// * `channels` - The channels to enable.
+    pub fn enable_channels(&mut self, channels: Channel) -> Result<(), Error> {
+        self.enabled_channels |= channels;
+        self.restore_csr()
+    }
+
+    /// Disable a number of channels in the CSR.
+    ///
+    /// Args:
+    /// * `channels` - The channels to disable.
+    pub fn disable_channels(&mut self, channels: Channel) -> Result<(), Error> {
+        self.enabled_channels &= !channels;
+        self.restore_csr()
+    }
+
+    /// Write the tracked channel enables to the CSR. Note that channel enables become active
+    /// immediately, so the CSR update does not need to be latched.
+    fn restore_csr(&mut self) -> Result<(), Error> {
+        let csr =
+            [self.communication_mode as u8 | self.enabled_channels.bits()];
+        self.write(Register::CSR, &csr)
     }
 
     /// Update an output channel configuration register.
@@ -313,6 +729,22 @@ impl<I: Interface> Ad9959<I> {
         channel: Channel,
         register: Register,
         data: &[u8],
+    ) -> Result<(), Error> {
+        self.write_channel(channel, register, data)?;
+        self.latch()
+    }
+
+    /// Write an output channel configuration register without latching it.
+    ///
+    /// Args:
+    /// * `channel` - The channel to configure.
+    /// * `register` - The register to write.
+    /// * `data` - The contents to write to the provided register.
+    fn write_channel(
+        &mut self,
+        channel: Channel,
+        register: Register,
+        data: &[u8],
     ) -> Result<(), Error> {
         // Disable all other outputs so that we can update the configuration register of only the
         // specified channel.
@@ -320,8 +752,7 @@ impl<I: Interface> Ad9959<I> {
 
         self.write(Register::CSR, &csr)?;
         self.write(register, data)?;
-
-        Ok(())
+        self.restore_csr()
     }
 
     /// Read a configuration register of a specific channel.
@@ -338,25 +769,20 @@ impl<I: Interface> Ad9959<I> {
     ) -> Result<(), Error> {
         // Disable all other channels in the CSR so that we can read the configuration register of
         // only the desired channel.
-        let mut csr = [0];
-        self.read(Register::CSR, &mut csr)?;
-        let new_csr = [self.communication_mode as u8 | channel.bits()];
-
-        self.write(Register::CSR, &new_csr)?;
-        self.read(register, data)?;
+        let csr = [self.communication_mode as u8 | channel.bits()];
 
-        // Restore the previous CSR. Note that the re-enable of the channel happens immediately, so
-        // the CSR update does not need to be latched.
         self.write(Register::CSR, &csr)?;
+        self.read(register, data)?;
 
-        Ok(())
+        self.restore_csr()
     }
 
     /// Configure the phase of a specified channel.
     ///
     /// Arguments:
     /// * `channel` - The channel to configure the frequency of.
-    /// * `phase_turns` - The desired phase offset in turns.
+    /// * `phase_turns` - The desired phase offset in turns. This is wrapped into [0, 1), see
+    ///   [phase_to_pow].
     ///
     /// Returns:
     /// The actual programmed phase offset of the channel in turns.
@@ -365,8 +791,7 @@ impl<I: Interface> Ad9959<I> {
         channel: Channel,
         phase_turns: f32,
     ) -> Result<f32, Error> {
-        let phase_offset: u16 =
-            (phase_turns * (1 << 14) as f32) as u16 & 0x3FFFu16;
+        let phase_offset = phase_to_pow(phase_turns);
 
         self.modify_channel(
             channel,
@@ -401,6 +826,66 @@ impl<I: Interface> Ad9959<I> {
     ///
     /// Returns:
     /// The actual normalized amplitude of the channel relative to full-scale range.
+    ///
+    /// Note:
+    /// Both endpoints are exact and read back unchanged using [Ad9959::get_amplitude]. An
+    /// amplitude of 0 is programmed as a zero scale factor with the amplitude multiplier enabled.
+    /// An amplitude of 1 bypasses the amplitude multiplier.
+    ///
+    /// ```
+    /// use ad9959::{Ad9959Builder, Channel, Interface, Mode};
+    /// use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+    ///
+    /// // A mock interface backed by a register file shared by all channels.
+    /// struct Registers([[u8; 4]; 0x19]);
+    ///
+    /// impl Interface for Registers {
+    ///     type Error = ();
+    ///
+    ///     fn configure_mode(&mut self, _mode: Mode) -> Result<(), ()> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), ()> {
+    ///         self.0[addr as usize][..data.len()].copy_from_slice(data);
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn read(&mut self, addr: u8, dest: &mut [u8]) -> Result<(), ()> {
+    ///         dest.copy_from_slice(&self.0[addr as usize][..dest.len()]);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct Pin;
+    ///
+    /// impl OutputPin for Pin {
+    ///     type Error = ();
+    ///
+    ///     fn set_low(&mut self) -> Result<(), ()> {
+    ///         Ok(())
+    ///     }
+    ///
+    ///     fn set_high(&mut self) -> Result<(), ()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// struct Delay;
+    ///
+    /// impl DelayUs<u8> for Delay {
+    ///     fn delay_us(&mut self, _us: u8) {}
+    /// }
+    ///
+    /// let mut dds = Ad9959Builder::new(Registers([[0; 4]; 0x19]), Pin, 100e6)
+    ///     .build(&mut Pin, &mut Delay)
+    ///     .unwrap();
+    ///
+    /// for amplitude in [0.0, 1.0] {
+    ///     assert_eq!(dds.set_amplitude(Channel::ONE, amplitude).unwrap(), amplitude);
+    ///     assert_eq!(dds.get_amplitude(Channel::ONE).unwrap(), amplitude);
+    /// }
+    /// ```
     pub fn set_amplitude(
         &mut self,
         channel: Channel,
@@ -410,22 +895,78 @@ impl<I: Interface> Ad9959<I> {
             return Err(Error::Bounds);
         }
 
-        let amplitude_control: u16 = (amplitude * (1 << 10) as f32) as u16;
+        let acr = amplitude_to_acr(amplitude);
+        self.modify_channel(channel, Register::ACR, &acr.to_be_bytes()[1..])?;
 
-        let mut acr: [u8; 3] = [0; 3];
+        if acr.get_bit(12) {
+            Ok((acr & 0x3FF) as f32 / (1 << 10) as f32)
+        } else {
+            Ok(1.0)
+        }
+    }
 
-        // Enable the amplitude multiplier for the channel if required. The amplitude control has
-        // full-scale at 0x3FF (amplitude of 1), so the multiplier should be disabled whenever
-        // full-scale is used.
-        if amplitude_control < (1 << 10) {
-            let masked_control = amplitude_control & 0x3FF;
-            acr[1] = masked_control.to_be_bytes()[0];
-            acr[2] = masked_control.to_be_bytes()[1];
+    /// Configure the amplitude of a specified channel in dBFS.
+    ///
+    /// Arguments:
+    /// * `channel` - The channel to configure the amplitude of.
+    /// * `dbfs` - The amplitude relative to full-scale in dB. Must not be positive. Negative
+    ///   infinity disables the output.
+    ///
+    /// Returns:
+    /// The actual amplitude of the channel in dBFS.
+    pub fn set_amplitude_dbfs(
+        &mut self,
+        channel: Channel,
+        dbfs: f32,
+    ) -> Result<f32, Error> {
+        if dbfs.is_nan() || dbfs > 0.0 {
+            return Err(Error::Bounds);
+        }
 
-            // Enable the amplitude multiplier
-            acr[1].set_bit(4, true);
+        let amplitude = self.set_amplitude(channel, dbfs_to_amplitude(dbfs))?;
+        Ok(amplitude_to_dbfs(amplitude))
+    }
+
+    /// Configure the automatic amplitude ramp of a specified channel.
+    ///
+    /// Arguments:
+    /// * `channel` - The channel to configure the amplitude ramp of.
+    /// * `target` - The normalized amplitude [0, 1] at the top of the ramp.
+    /// * `ramp_rate` - The number of SYNC_CLK periods between amplitude steps.
+    /// * `step` - The amplitude step size code. Must be 0-3, corresponding to a step of 1, 2, 4,
+    ///   or 8 LSB.
+    ///
+    /// Returns:
+    /// The actual normalized target amplitude of the channel relative to full-scale range.
+    ///
+    /// Note:
+    /// The ramp-up and ramp-down is triggered by the profile pin associated with the channel. The
+    /// amplitude multiplier must be enabled for ramping, so a target amplitude of 1 is programmed
+    /// as the largest amplitude scale factor below full-scale.
+    pub fn set_amplitude_ramp(
+        &mut self,
+        channel: Channel,
+        target: f32,
+        ramp_rate: u8,
+        step: u8,
+    ) -> Result<f32, Error> {
+        if !(0.0..=1.0).contains(&target) || step > 0b11 {
+            return Err(Error::Bounds);
         }
 
+        let amplitude_control: u16 =
+            ((target * (1 << 10) as f32) as u16).min(0x3FF);
+
+        let mut acr: [u8; 3] = [0; 3];
+        acr[0] = ramp_rate;
+        acr[1] = amplitude_control.to_be_bytes()[0];
+        acr[2] = amplitude_control.to_be_bytes()[1];
+        acr[1].set_bits(6..=7, step);
+
+        // Enable the amplitude multiplier and the automatic ramp-up/ramp-down.
+        acr[1].set_bit(4, true);
+        acr[1].set_bit(3, true);
+
         self.modify_channel(channel, Register::ACR, &acr)?;
 
         Ok(amplitude_control as f32 / (1 << 10) as f32)
@@ -437,7 +978,8 @@ impl<I: Interface> Ad9959<I> {
     /// * `channel` - The channel to get the amplitude of.
     ///
     /// Returns:
-    /// The normalized amplitude of the channel.
+    /// The normalized amplitude of the channel. A channel with the amplitude multiplier bypassed
+    /// is at full-scale, otherwise the amplitude is given by the scale factor, including 0.
     pub fn get_amplitude(&mut self, channel: Channel) -> Result<f32, Error> {
         let mut acr: [u8; 3] = [0; 3];
         self.read_channel(channel, Register::ACR, &mut acr)?;
@@ -451,6 +993,21 @@ impl<I: Interface> Ad9959<I> {
         }
     }
 
+    /// Get the configured amplitude of a channel in dBFS.
+    ///
+    /// Args:
+    /// * `channel` - The channel to get the amplitude of.
+    ///
+    /// Returns:
+    /// The amplitude of the channel relative to full-scale in dB. A zero amplitude is reported as
+    /// negative infinity.
+    pub fn get_amplitude_dbfs(
+        &mut self,
+        channel: Channel,
+    ) -> Result<f32, Error> {
+        self.get_amplitude(channel).map(amplitude_to_dbfs)
+    }
+
     /// Configure the frequency of a specified channel.
     ///
     /// Arguments:
@@ -458,31 +1015,195 @@ impl<I: Interface> Ad9959<I> {
     /// * `frequency` - The desired output frequency in Hz.
     ///
     /// Returns:
-    /// The actual programmed frequency of the channel.
+    /// The actual programmed frequency of the channel. The deviation from the requested frequency
+    /// is bounded by [Ad9959::frequency_resolution]. Fails with [Error::ClockNotConfigured] if
+    /// the system clock has not been successfully configured.
+    ///
+    /// Note:
+    /// A rejected system clock reconfiguration keeps the previous clock configuration, so the
+    /// frequency can still be set afterwards.
+    ///
+    /// ```
+    /// use ad9959::{Ad9959Builder, Channel, Error, Interface, Mode};
+    /// use embedded_hal::{blocking::delay::DelayUs, digital::v2::OutputPin};
+    /// #
+    /// # // A mock interface backed by a register file shared by all channels.
+    /// # struct Registers([[u8; 4]; 0x19]);
+    /// #
+    /// # impl Interface for Registers {
+    /// #     type Error = ();
+    /// #
+    /// #     fn configure_mode(&mut self, _mode: Mode) -> Result<(), ()> {
+    /// #         Ok(())
+    /// #     }
+    /// #
+    /// #     fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), ()> {
+    /// #         self.0[addr as usize][..data.len()].copy_from_slice(data);
+    /// #         Ok(())
+    /// #     }
+    /// #
+    /// #     fn read(&mut self, addr: u8, dest: &mut [u8]) -> Result<(), ()> {
+    /// #         dest.copy_from_slice(&self.0[addr as usize][..dest.len()]);
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # struct Pin;
+    /// #
+    /// # impl OutputPin for Pin {
+    /// #     type Error = ();
+    /// #
+    /// #     fn set_low(&mut self) -> Result<(), ()> {
+    /// #         Ok(())
+    /// #     }
+    /// #
+    /// #     fn set_high(&mut self) -> Result<(), ()> {
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// #
+    /// # struct Delay;
+    /// #
+    /// # impl DelayUs<u8> for Delay {
+    /// #     fn delay_us(&mut self, _us: u8) {}
+    /// # }
+    /// #
+    /// # impl DelayUs<u16> for Delay {
+    /// #     fn delay_us(&mut self, _us: u16) {}
+    /// # }
+    ///
+    /// let mut dds = Ad9959Builder::new(Registers([[0; 4]; 0x19]), Pin, 100e6)
+    ///     .build(&mut Pin, &mut Delay)
+    ///     .unwrap();
+    ///
+    /// // A multiplier of 3 is not supported.
+    /// let result = dds.configure_system_clock_safe(100e6, 3, &mut Delay);
+    /// assert!(matches!(result, Err(Error::Bounds)));
+    ///
+    /// let frequency = dds.set_frequency(Channel::ONE, 10e6).unwrap();
+    /// assert!((frequency - 10e6).abs() <= dds.frequency_resolution());
+    /// ```
     pub fn set_frequency(
         &mut self,
         channel: Channel,
         frequency: f32,
     ) -> Result<f32, Error> {
+        let tuning_word = self.checked_tuning_word(frequency)?;
+
+        self.modify_channel(
+            channel,
+            Register::CFTW0,
+            &tuning_word.to_be_bytes(),
+        )?;
+        Ok((tuning_word as f32 / 1u64.wrapping_shl(32) as f32)
+            * self.system_clock_frequency())
+    }
+
+    /// Convert a frequency to a tuning word after checking that it can be programmed.
+    ///
+    /// Args:
+    /// * `frequency` - The desired output frequency in Hz.
+    fn checked_tuning_word(&self, frequency: f32) -> Result<u32, Error> {
+        if !self.clock_configured {
+            return Err(Error::ClockNotConfigured);
+        }
+
         if frequency < 0.0 || frequency > self.system_clock_frequency() {
             return Err(Error::Bounds);
         }
 
-        // The function for channel frequency is `f_out = FTW * f_s / 2^32`, where FTW is the
-        // frequency tuning word and f_s is the system clock rate.
-        let tuning_word: u32 =
-            ((frequency as f32 / self.system_clock_frequency())
-                * 1u64.wrapping_shl(32) as f32) as u32;
+        Ok(frequency_to_ftw(frequency, self.system_clock_frequency()))
+    }
 
-        self.modify_channel(
-            channel,
+    /// Configure the phases of a group of channels, maintaining their relative phases.
+    ///
+    /// Args:
+    /// * `group` - The channels and their phases.
+    ///
+    /// Note:
+    /// The phase offsets of all channels of the group are latched simultaneously. See
+    /// [PhaseGroup] for the conditions under which the relative phases are maintained.
+    pub fn set_phase_group(&mut self, group: &PhaseGroup) -> Result<(), Error> {
+        self.write_group_phases(group)?;
+        self.latch()
+    }
+
+    /// Configure the common frequency of a group of channels, maintaining their relative phases.
+    ///
+    /// Args:
+    /// * `group` - The channels and their phases.
+    /// * `frequency` - The desired output frequency in Hz.
+    ///
+    /// Returns:
+    /// The actual programmed frequency of the channels. Fails as [Ad9959::set_frequency].
+    ///
+    /// Note:
+    /// The frequency tuning words and the phase offsets of all channels of the group are latched
+    /// simultaneously, such that the phase accumulators of the channels advance identically
+    /// across the retuning.
+    pub fn set_group_frequency(
+        &mut self,
+        group: &PhaseGroup,
+        frequency: f32,
+    ) -> Result<f32, Error> {
+        let tuning_word = self.checked_tuning_word(frequency)?;
+
+        self.write_channel(
+            group.channels,
             Register::CFTW0,
             &tuning_word.to_be_bytes(),
         )?;
+        self.write_group_phases(group)?;
+        self.latch()?;
+
         Ok((tuning_word as f32 / 1u64.wrapping_shl(32) as f32)
             * self.system_clock_frequency())
     }
 
+    /// Write the phase offsets of a group of channels without latching them.
+    ///
+    /// Args:
+    /// * `group` - The channels and their phases.
+    fn write_group_phases(&mut self, group: &PhaseGroup) -> Result<(), Error> {
+        for (channel, differential) in group.members() {
+            let phase_offset = phase_to_pow(group.base_phase + differential);
+            self.write_channel(
+                channel,
+                Register::CPOW0,
+                &phase_offset.to_be_bytes(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Configure the frequency of a specified channel and check whether it is near the Nyquist
+    /// frequency.
+    ///
+    /// Arguments:
+    /// * `channel` - The channel to configure the frequency of.
+    /// * `frequency` - The desired output frequency in Hz.
+    ///
+    /// Returns:
+    /// The actual programmed frequency of the channel and whether it exceeds
+    /// [NEAR_NYQUIST_FRACTION] of the system clock frequency. Fails as [Ad9959::set_frequency].
+    ///
+    /// Note:
+    /// Frequencies near the Nyquist frequency are valid and programmed as requested. The flag is
+    /// advisory only.
+    pub fn set_frequency_checked(
+        &mut self,
+        channel: Channel,
+        frequency: f32,
+    ) -> Result<CheckedFrequency, Error> {
+        let frequency = self.set_frequency(channel, frequency)?;
+        Ok(CheckedFrequency {
+            frequency,
+            near_nyquist: frequency
+                > NEAR_NYQUIST_FRACTION * self.system_clock_frequency(),
+        })
+    }
+
     /// Get the frequency of a channel.
     ///
     /// Arguments:
@@ -501,19 +1222,578 @@ impl<I: Interface> Ad9959<I> {
             / (1u64 << 32) as f32)
     }
 
+    /// Get the frequencies of all channels.
+    ///
+    /// Returns:
+    /// The frequencies of the channels in Hz, indexed by channel (`ONE` at index 0 to `FOUR` at
+    /// index 3).
+    ///
+    /// Note:
+    /// The tuning words of all channels are read in a single sequence and the channel enables
+    /// are restored only once at the end, such that the CSR is not left in an intermediate state
+    /// between the reads.
+    pub fn get_all_frequencies(&mut self) -> Result<[f32; 4], Error> {
+        let mut frequencies = [0.0; 4];
+
+        for (frequency, channel) in frequencies.iter_mut().zip([
+            Channel::ONE,
+            Channel::TWO,
+            Channel::THREE,
+            Channel::FOUR,
+        ]) {
+            // Select only the desired channel to read its tuning word.
+            let csr = [self.communication_mode as u8 | channel.bits()];
+            self.write(Register::CSR, &csr)?;
+
+            let mut tuning_word: [u8; 4] = [0; 4];
+            self.read(Register::CFTW0, &mut tuning_word)?;
+            let tuning_word = u32::from_be_bytes(tuning_word);
+
+            *frequency = (tuning_word as f32 * self.system_clock_frequency())
+                / (1u64 << 32) as f32;
+        }
+
+        self.restore_csr()?;
+
+        Ok(frequencies)
+    }
+
+    /// Select a channel for profile modulation.
+    ///
+    /// Args:
+    /// * `channel` - The single channel to modulate.
+    /// * `modulation` - The channel parameter selected by the profile pins.
+    ///
+    /// Note:
+    /// The DDS is configured for 16-level modulation, where the profile pins P0-P3 of the DDS
+    /// select one of 16 profile slots of the modulated channel. Only a single channel can be
+    /// modulated in this configuration, so the modulation of any previously selected channel
+    /// should be disabled. See [Ad9959::store_profile] for the profile slots.
+    pub fn set_modulation(
+        &mut self,
+        channel: Channel,
+        modulation: Modulation,
+    ) -> Result<(), Error> {
+        let index = match channel {
+            Channel::ONE => 0,
+            Channel::TWO => 1,
+            Channel::THREE => 2,
+            Channel::FOUR => 3,
+            _ => return Err(Error::Bounds),
+        };
+
+        if modulation != Modulation::Disabled {
+            // Use 16-level modulation with the profile pins assigned to the channel.
+            let mut fr1: [u8; 3] = [0; 3];
+            self.read(Register::FR1, &mut fr1)?;
+            fr1[1].set_bits(4..=6, index);
+            fr1[1].set_bits(0..=1, 0b11);
+            self.write(Register::FR1, &fr1)?;
+        }
+
+        let mut cfr: [u8; 3] = [0; 3];
+        self.read_channel(channel, Register::CFR, &mut cfr)?;
+        cfr[0].set_bits(6..=7, modulation as u8);
+        self.modify_channel(channel, Register::CFR, &cfr)
+    }
+
+    /// Store a profile of a channel in a profile slot.
+    ///
+    /// Args:
+    /// * `channel` - The channel to store the profile for.
+    /// * `slot` - The profile slot. Must be 0-15.
+    /// * `profile` - The profile to store.
+    ///
+    /// Note:
+    /// The profile pins P0-P3 select the active slot, with P0 as the least significant bit. Slot
+    /// 0 is held in the frequency, phase, and amplitude registers of the channel and receives the
+    /// full profile. Slots 1-15 are held in the channel word registers CW1-CW15 and only receive
+    /// the parameter selected using [Ad9959::set_modulation], the other parameters are taken from
+    /// slot 0. Storing to slots 1-15 fails with [Error::ModulationDisabled] if the channel is not
+    /// modulated. Switching slots using the profile pins does not require any bus access, but
+    /// slots selected while modulation is disabled have no effect.
+    pub fn store_profile(
+        &mut self,
+        channel: Channel,
+        slot: u8,
+        profile: &Profile,
+    ) -> Result<(), Error> {
+        if slot > 15 {
+            return Err(Error::Bounds);
+        }
+
+        if slot == 0 {
+            self.modify_channel(
+                channel,
+                Register::CFTW0,
+                &profile.ftw.to_be_bytes(),
+            )?;
+            self.modify_channel(
+                channel,
+                Register::CPOW0,
+                &(profile.pow & 0x3FFF).to_be_bytes(),
+            )?;
+            return self.modify_channel(
+                channel,
+                Register::ACR,
+                &profile.acr.to_be_bytes()[1..],
+            );
+        }
+
+        let mut cfr: [u8; 3] = [0; 3];
+        self.read_channel(channel, Register::CFR, &mut cfr)?;
+
+        // The channel words are MSB-aligned.
+        let word = match cfr[0].get_bits(6..=7) {
+            0b01 => (profile.acr & 0x3FF) << 22,
+            0b10 => profile.ftw,
+            0b11 => ((profile.pow & 0x3FFF) as u32) << 18,
+            _ => return Err(Error::ModulationDisabled),
+        };
+
+        self.modify_channel(
+            channel,
+            CHANNEL_WORDS[slot as usize - 1],
+            &word.to_be_bytes(),
+        )
+    }
+
+    /// Get a serializer for profiles in the configured communication mode.
+    ///
+    /// Note:
+    /// The serializer is tied to the communication mode of the driver, which is also the mode
+    /// returned by [Ad9959::freeze]. This avoids serializing profiles for a different mode than
+    /// the one the DDS is configured for.
+    pub fn serializer(&self) -> ProfileSerializer {
+        ProfileSerializer::new(self.communication_mode)
+    }
+
     /// Finalize DDS configuration
     ///
     /// # Note
     /// This is intended for when the DDS profiles will be written as a stream of data to the DDS.
     ///
     /// # Returns
-    /// (i, mode) where `i` is the interface to the DDS and `mode` is the frozen `Mode`.
-    pub fn freeze(self) -> (I, Mode) {
-        (self.interface, self.communication_mode)
+    /// (i, mode, io_update) where `i` is the interface to the DDS, `mode` is the frozen `Mode` and
+    /// `io_update` is the pin connected to the DDS io_update input.
+    pub fn freeze(self) -> (I, Mode, IO) {
+        (self.interface, self.communication_mode, self.io_update)
     }
 }
 
+/// A builder for constructing and initializing an [Ad9959].
+///
+/// The builder defaults to the four-bit serial communication mode, a system clock multiplier of 1
+/// (PLL bypassed), and [IoUpdateMode::Pulse].
+pub struct Ad9959Builder<INTERFACE, IO> {
+    interface: INTERFACE,
+    io_update: IO,
+    reference_clock_frequency: f32,
+    mode: Mode,
+    multiplier: u8,
+    io_update_mode: IoUpdateMode,
+}
+
+impl<I: Interface, IO: OutputPin> Ad9959Builder<I, IO> {
+    /// Construct a new builder.
+    ///
+    /// Args:
+    /// * `interface` - An interface to the DDS.
+    /// * `io_update` - A pin connected to the DDS io_update input.
+    /// * `reference_clock_frequency` - The clock frequency of the reference clock input in Hz.
+    pub fn new(
+        interface: I,
+        io_update: IO,
+        reference_clock_frequency: f32,
+    ) -> Self {
+        Self {
+            interface,
+            io_update,
+            reference_clock_frequency,
+            mode: Mode::FourBitSerial,
+            multiplier: 1,
+            io_update_mode: IoUpdateMode::Pulse,
+        }
+    }
+
+    /// Specify the communication mode of the interface to the DDS.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Specify the clock multiplier for the system clock. Must be 1 or 4-20.
+    pub fn multiplier(mut self, multiplier: u8) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// Specify whether the driver latches channel configuration changes.
+    pub fn io_update_mode(mut self, io_update_mode: IoUpdateMode) -> Self {
+        self.io_update_mode = io_update_mode;
+        self
+    }
+
+    /// Reset and initialize the DDS using the builder configuration.
+    ///
+    /// Args:
+    /// * `reset_pin` - A pin connected to the DDS reset input.
+    /// * `delay` - A delay implementation for blocking operation for specific amounts of time. See
+    ///   [Ad9959::new] for the accuracy requirements.
+    ///
+    /// Returns:
+    /// The initialized DDS.
+    pub fn build(
+        self,
+        reset_pin: &mut impl OutputPin,
+        delay: &mut impl DelayUs<u8>,
+    ) -> Result<Ad9959<I, IO>, Error> {
+        Ad9959::new(
+            self.interface,
+            reset_pin,
+            self.io_update,
+            delay,
+            self.mode,
+            self.reference_clock_frequency,
+            self.multiplier,
+            self.io_update_mode,
+        )
+    }
+}
+
+/// Choose the reference clock multiplier producing a system clock nearest to a desired value.
+///
+/// ```
+/// use ad9959::choose_multiplier;
+///
+/// assert!(matches!(choose_multiplier(100e6, 500e6), Ok(5)));
+/// // 175 MHz to 250 MHz lies between the VCO ranges, so 150 MHz is the nearest option.
+/// assert!(matches!(choose_multiplier(25e6, 200e6), Ok(6)));
+/// assert!(choose_multiplier(600e6, 500e6).is_err());
+/// ```
+///
+/// Args:
+/// * `reference_clock` - The frequency of the reference clock input in Hz.
+/// * `desired_sysclk` - The desired DDS system clock frequency in Hz.
+///
+/// Returns:
+/// The multiplier (1 or 4-20) whose system clock is nearest to the desired frequency. Ties resolve
+/// to the smaller multiplier. Returns `Error::Frequency` if no multiplier is valid.
+///
+/// Note:
+/// The system clock may not exceed 500 MHz. When the PLL is used (multipliers 4-20), the
+/// reference clock must be within 10-125 MHz and the system clock must lie within one of the VCO
+/// ranges of 100-160 MHz or 255-500 MHz.
+pub fn choose_multiplier(
+    reference_clock: f32,
+    desired_sysclk: f32,
+) -> Result<u8, Error> {
+    if reference_clock.is_nan()
+        || reference_clock <= 0.0
+        || !desired_sysclk.is_finite()
+    {
+        return Err(Error::Frequency);
+    }
+
+    let pll_reference = (10e6..=125e6).contains(&reference_clock);
+
+    core::iter::once(1)
+        .chain(4..=20)
+        .filter(|&multiplier| {
+            let sysclk = reference_clock * multiplier as f32;
+            if sysclk > 500e6 {
+                return false;
+            }
+
+            multiplier == 1
+                || (pll_reference
+                    && ((100e6..=160e6).contains(&sysclk)
+                        || (255e6..=500e6).contains(&sysclk)))
+        })
+        .map(|multiplier| {
+            let error = reference_clock * multiplier as f32 - desired_sysclk;
+            (multiplier, if error < 0.0 { -error } else { error })
+        })
+        // `min_by()` returns the first of several equal elements, resolving ties to the smaller
+        // multiplier.
+        .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+        .map(|(multiplier, _)| multiplier)
+        .ok_or(Error::Frequency)
+}
+
+/// Convert a frequency to a frequency tuning word (FTW).
+///
+/// ```
+/// use ad9959::frequency_to_ftw;
+///
+/// assert_eq!(frequency_to_ftw(125e6, 500e6), 0x4000_0000);
+/// assert_eq!(frequency_to_ftw(0.0, 500e6), 0);
+/// ```
+///
+/// Args:
+/// * `frequency` - The frequency in Hz.
+/// * `system_clock` - The DDS system clock frequency in Hz.
+///
+/// Returns:
+/// The frequency tuning word, truncating any fractional LSB. Frequencies at or above the system
+/// clock saturate to the largest tuning word.
+pub fn frequency_to_ftw(frequency: f32, system_clock: f32) -> u32 {
+    // The function for channel frequency is `f_out = FTW * f_s / 2^32`, where FTW is the
+    // frequency tuning word and f_s is the system clock rate.
+    (frequency / system_clock * (1u64 << 32) as f32) as u32
+}
+
+/// Convert a phase offset in turns to a 14-bit phase offset word (POW).
+///
+/// The phase is first wrapped into [0, 1) turns, such that negative phases and phases of more than
+/// one turn map deterministically onto the equivalent phase within a single turn. The wrapped
+/// phase is then scaled to the 14-bit POW range, truncating any fractional LSB.
+///
+/// ```
+/// use ad9959::phase_to_pow;
+///
+/// assert_eq!(phase_to_pow(0.75), 0x3000);
+/// assert_eq!(phase_to_pow(-0.25), phase_to_pow(0.75));
+/// assert_eq!(phase_to_pow(1.75), phase_to_pow(0.75));
+/// assert_eq!(phase_to_pow(-1.0), 0);
+/// ```
+///
+/// Args:
+/// * `phase_turns` - The desired phase offset in turns. Must be finite.
+///
+/// Returns:
+/// The phase offset word corresponding to the wrapped phase.
+pub fn phase_to_pow(phase_turns: f32) -> u16 {
+    // Note: `f32::floor()` is not available in `no_std`. Remove the integer part (truncating
+    // towards zero) and shift negative remainders up by one turn instead.
+    let mut wrapped = phase_turns - (phase_turns as i32) as f32;
+    if wrapped < 0.0 {
+        wrapped += 1.0;
+    }
+
+    // Note: A tiny negative remainder may round up to exactly one turn, which the mask wraps to 0.
+    (wrapped * (1 << 14) as f32) as u16 & 0x3FFFu16
+}
+
+/// Convert a normalized amplitude to an amplitude control register (ACR) value.
+///
+/// ```
+/// use ad9959::amplitude_to_acr;
+///
+/// // Full-scale bypasses the amplitude multiplier.
+/// assert_eq!(amplitude_to_acr(1.0), 0);
+/// assert_eq!(amplitude_to_acr(0.5), 0x1200);
+/// assert_eq!(amplitude_to_acr(0.0), 0x1000);
+/// ```
+///
+/// Args:
+/// * `amplitude` - A normalized amplitude. This is clamped to [0, 1].
+///
+/// Returns:
+/// The 24-bit ACR stored in the 3 LSB. The amplitude scale factor is truncated to 10 bits. The
+/// amplitude multiplier is enabled for all amplitudes below full-scale.
+pub fn amplitude_to_acr(amplitude: f32) -> u32 {
+    // Note(as): NaN is converted to zero.
+    let amplitude_control =
+        (amplitude.clamp(0.0, 1.0) * (1 << 10) as f32) as u32;
+
+    // The amplitude control has full-scale at 0x3FF (amplitude of 1), so the multiplier should be
+    // disabled whenever full-scale is used.
+    if amplitude_control < (1 << 10) {
+        let mut acr = amplitude_control & 0x3FF;
+
+        // Enable the amplitude multiplier
+        acr.set_bit(12, true);
+        acr
+    } else {
+        0
+    }
+}
+
+/// Convert an amplitude in dBFS to a normalized amplitude.
+///
+/// ```
+/// use ad9959::{amplitude_to_dbfs, dbfs_to_amplitude};
+///
+/// assert_eq!(dbfs_to_amplitude(0.0), 1.0);
+/// assert_eq!(dbfs_to_amplitude(f32::NEG_INFINITY), 0.0);
+/// assert!((dbfs_to_amplitude(-20.0) - 0.1).abs() < 1e-6);
+/// assert_eq!(amplitude_to_dbfs(0.0), f32::NEG_INFINITY);
+/// assert!((amplitude_to_dbfs(0.5) + 6.0206).abs() < 1e-4);
+/// ```
+///
+/// Args:
+/// * `dbfs` - The amplitude relative to full-scale in dB.
+///
+/// Returns:
+/// The normalized amplitude. This exceeds 1 for positive dBFS.
+pub fn dbfs_to_amplitude(dbfs: f32) -> f32 {
+    libm::powf(10.0, dbfs / 20.0)
+}
+
+/// Convert a normalized amplitude to dBFS.
+///
+/// Args:
+/// * `amplitude` - The normalized amplitude.
+///
+/// Returns:
+/// The amplitude relative to full-scale in dB. A zero amplitude is converted to negative infinity.
+pub fn amplitude_to_dbfs(amplitude: f32) -> f32 {
+    20.0 * libm::log10f(amplitude)
+}
+
+/// Perform a self-test of the communication interface to the DDS.
+///
+/// # Note
+/// This is intended for validating the interface after the DDS configuration has been frozen (see
+/// [Ad9959::freeze]). The interface must support register reads and writes. This modifies the
+/// existing channel enables. They are restored upon exit.
+///
+/// # Args
+/// * `interface` - The interface to the DDS.
+///
+/// # Returns
+/// True if the self test succeeded. False otherwise.
+pub fn self_test<I: Interface>(interface: &mut I) -> Result<bool, Error> {
+    let mut csr: [u8; 1] = [0];
+    interface
+        .read(Register::CSR as u8, &mut csr)
+        .or(Err(Error::Interface))?;
+    let old_csr = csr[0];
+
+    // Enable all channels.
+    csr[0].set_bits(4..8, 0xF);
+    interface
+        .write(Register::CSR as u8, &csr)
+        .or(Err(Error::Interface))?;
+
+    // Read back the enable.
+    csr[0] = 0;
+    interface
+        .read(Register::CSR as u8, &mut csr)
+        .or(Err(Error::Interface))?;
+    if csr[0].get_bits(4..8) != 0xF {
+        return Ok(false);
+    }
+
+    // Clear all channel enables.
+    csr[0].set_bits(4..8, 0x0);
+    interface
+        .write(Register::CSR as u8, &csr)
+        .or(Err(Error::Interface))?;
+
+    // Read back the enable.
+    csr[0] = 0xFF;
+    interface
+        .read(Register::CSR as u8, &mut csr)
+        .or(Err(Error::Interface))?;
+    if csr[0].get_bits(4..8) != 0 {
+        return Ok(false);
+    }
+
+    // Restore the CSR.
+    csr[0] = old_csr;
+    interface
+        .write(Register::CSR as u8, &csr)
+        .or(Err(Error::Interface))?;
+
+    Ok(true)
+}
+
+/// A group of channels sharing a frequency with fixed phases relative to each other.
+///
+/// The phase offset of each channel is the common base phase plus the differential phase of the
+/// channel. Retuning the group with [Ad9959::set_group_frequency] re-applies the phase offsets,
+/// such that only the base phase or the differential phases need to be changed.
+///
+/// Note:
+/// The relative phases of the outputs only equal the differential phases if the phase
+/// accumulators of the channels are synchronized, i.e. if the channels were only ever tuned
+/// together since the DDS was reset. Tuning channels individually using [Ad9959::set_frequency]
+/// breaks the relationship.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PhaseGroup {
+    /// The channels of the group.
+    pub channels: Channel,
+    /// The common phase of the channels in turns.
+    pub base_phase: f32,
+    /// The phase of each channel relative to the base phase in turns, indexed by the channel
+    /// number 0-3. Entries of channels not in the group are ignored.
+    pub differential: [f32; 4],
+}
+
+impl PhaseGroup {
+    /// Get the channels of the group along with their differential phases.
+    fn members(&self) -> impl Iterator<Item = (Channel, f32)> + '_ {
+        self.differential
+            .iter()
+            .enumerate()
+            .map(|(i, &differential)| {
+                (
+                    Channel::from_bits_truncate(Channel::ONE.bits() << i),
+                    differential,
+                )
+            })
+            .filter(move |(channel, _)| self.channels.contains(*channel))
+    }
+}
+
+/// A programmed channel frequency, see [Ad9959::set_frequency_checked].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CheckedFrequency {
+    /// The actual programmed frequency in Hz.
+    pub frequency: f32,
+    /// Specified true if the frequency exceeds [NEAR_NYQUIST_FRACTION] of the system clock
+    /// frequency, where the output is heavily attenuated and aliasing images are strong.
+    pub near_nyquist: bool,
+}
+
+/// A DDS channel profile in machine units.
+///
+/// With the `serde` feature, profiles can be serialized to snapshot and restore the exact DDS
+/// register contents.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Profile {
+    /// The frequency tuning word.
+    pub ftw: u32,
+    /// The phase offset word.
+    pub pow: u16,
+    /// The amplitude control register. The 24-bits of the ACR are stored in the 3 LSB.
+    pub acr: u32,
+}
+
 /// Represents a means of serializing a DDS profile for writing to a stream.
+///
+/// Each register write is serialized as the register address followed by the register data. The
+/// serialization is padded to a whole number of 32-bit words using dummy writes.
+///
+/// # Example
+/// ```
+/// use ad9959::{Channel, Mode, ProfileSerializer};
+///
+/// fn serialize(f: impl Fn(&mut ProfileSerializer)) -> Vec<u8> {
+///     let mut serializer = ProfileSerializer::new(Mode::FourBitSerial);
+///     f(&mut serializer);
+///     let words = serializer.finalize().unwrap();
+///     words.iter().flat_map(|word| word.to_le_bytes()).collect()
+/// }
+///
+/// // A CSR and CPOW0 write (5 bytes) is padded with a 3 byte LSRR write.
+/// let data = serialize(|s| s.update_channels(Channel::ONE, None, Some(0x1234), None));
+/// assert_eq!(data, [0x00, 0x16, 0x05, 0x12, 0x34, 0x07, 0x00, 0x00]);
+///
+/// // A CSR and ACR write (6 bytes) is padded with a 2 byte CSR write.
+/// let data = serialize(|s| s.update_channels(Channel::TWO, None, None, Some(0x12_3456)));
+/// assert_eq!(data, [0x00, 0x26, 0x06, 0x12, 0x34, 0x56, 0x00, 0x06]);
+///
+/// // A CSR and CFTW0 write (7 bytes) is padded with a 3 byte LSRR and a 2 byte CSR write.
+/// let data = serialize(|s| s.update_channels(Channel::ALL, Some(0x1234_5678), None, None));
+/// assert_eq!(
+///     data,
+///     [0x00, 0xF6, 0x04, 0x12, 0x34, 0x56, 0x78, 0x07, 0x00, 0x00, 0x00, 0x06]
+/// );
+/// ```
 pub struct ProfileSerializer {
     // heapless::Vec<u8, 32>, especially its extend_from_slice() is slow
     data: [u8; 32],
@@ -525,6 +1805,10 @@ pub struct ProfileSerializer {
 impl ProfileSerializer {
     /// Construct a new serializer.
     ///
+    /// # Note
+    /// The mode must match the communication mode the DDS is configured for. Use
+    /// [Ad9959::serializer] to construct a serializer in the mode of a driver.
+    ///
     /// # Args
     /// * `mode` - The communication mode of the DDS.
     pub fn new(mode: Mode) -> Self {
@@ -568,6 +1852,95 @@ impl ProfileSerializer {
         }
     }
 
+    /// Enable or disable the outputs of a number of channels.
+    ///
+    /// # Note
+    /// The CSR channel enables only select the channels whose registers are accessed. The outputs
+    /// are switched using the DAC power-down bit of the channel function register (CFR). As the
+    /// CFR cannot be read back in a stream, all other CFR bits are written with their power-on
+    /// defaults (full-scale DAC current, modulation and sweeps disabled).
+    ///
+    /// # Args
+    /// * `channels` - A set of channels to enable or disable.
+    /// * `enabled` - Specified true to enable the channel outputs, false to power them down.
+    #[inline]
+    pub fn set_channels_enabled(&mut self, channels: Channel, enabled: bool) {
+        let csr = [self.mode as u8 | channels.bits()];
+        self.add_write(Register::CSR, &csr);
+
+        // Power-on default CFR with the DAC full-scale current control at its maximum.
+        let mut cfr: [u8; 3] = [0x00, 0x03, 0x02];
+        cfr[2].set_bit(6, !enabled);
+        self.add_write(Register::CFR, &cfr);
+    }
+
+    /// Disable the outputs of a number of channels. See [ProfileSerializer::set_channels_enabled].
+    ///
+    /// # Args
+    /// * `channels` - A set of channels to disable.
+    #[inline]
+    pub fn disable_channels(&mut self, channels: Channel) {
+        self.set_channels_enabled(channels, false)
+    }
+
+    /// Get the number of unused bytes in the serialization buffer.
+    ///
+    /// Note:
+    /// Finalizing the profile requires up to 5 additional bytes for padding. Use
+    /// [ProfileSerializer::can_fit] to check whether further register writes can be finalized.
+    ///
+    /// Returns:
+    /// The number of bytes that can still be serialized before the buffer is exhausted.
+    #[inline]
+    pub fn remaining_bytes(&self) -> usize {
+        self.data.len() - self.index
+    }
+
+    /// Check whether further register writes fit into the serializer.
+    ///
+    /// ```
+    /// use ad9959::{Channel, Mode, ProfileSerializer};
+    ///
+    /// let mut serializer = ProfileSerializer::new(Mode::FourBitSerial);
+    /// // CSR, CFTW0, CPOW0 and ACR writes: 2 + 5 + 3 + 4 bytes.
+    /// assert!(serializer.can_fit(14));
+    /// serializer.update_channels(Channel::ONE, Some(0), Some(0), Some(0));
+    /// serializer.update_channels(Channel::TWO, Some(0), Some(0), Some(0));
+    /// assert_eq!(serializer.remaining_bytes(), 4);
+    /// // A 2 byte CSR write fits, but no room is left for the padding of a 3 byte CPOW0 write.
+    /// assert!(serializer.can_fit(2));
+    /// assert!(!serializer.can_fit(3));
+    /// ```
+    ///
+    /// Args:
+    /// * `bytes` - The serialized size of the register writes in bytes, including one address byte
+    ///   per register write.
+    ///
+    /// Returns:
+    /// True if the register writes can be added and the profile can still be finalized, including
+    /// its padding.
+    #[inline]
+    pub fn can_fit(&self, bytes: usize) -> bool {
+        let index = self.index + bytes;
+        index + Self::padding(index) <= self.data.len()
+    }
+
+    /// Get the number of padding bytes required to align serialized data to 32-bit words.
+    ///
+    /// Args:
+    /// * `index` - The number of serialized bytes.
+    #[inline]
+    fn padding(index: usize) -> usize {
+        // In the case of 1 byte padding, this instead pads with 5 bytes as there is no valid
+        // single-byte write that could be used.
+        match index & 3 {
+            0 => 0,
+            1 => 3,
+            2 => 2,
+            _ => 5,
+        }
+    }
+
     /// Add a register write to the serialization data.
     fn add_write(&mut self, register: Register, value: &[u8]) {
         let data = &mut self.data[self.index..];
@@ -577,10 +1950,12 @@ impl ProfileSerializer {
     }
 
     #[inline]
-    fn pad(&mut self) {
+    fn pad(&mut self) -> Result<(), Error> {
         // Pad the buffer to 32-bit (4 byte) alignment by adding dummy writes to CSR and LSRR.
-        // In the case of 1 byte padding, this instead pads with 5 bytes as there is no
-        // valid single-byte write that could be used.
+        if self.index + Self::padding(self.index) > self.data.len() {
+            return Err(Error::Bounds);
+        }
+
         if self.index & 1 != 0 {
             // Pad with 3 bytes
             self.add_write(Register::LSRR, &[0, 0]);
@@ -590,6 +1965,7 @@ impl ProfileSerializer {
             self.add_write(Register::CSR, &[self.mode as _]);
         }
         debug_assert_eq!(self.index & 3, 0);
+        Ok(())
     }
 
     /// Get the serialized profile as a slice of 32-bit words.
@@ -599,10 +1975,37 @@ impl ProfileSerializer {
     /// writes to the CSR or LSRR registers.
     ///
     /// # Returns
-    /// A slice of `u32` words representing the serialized profile.
+    /// A slice of `u32` words representing the serialized profile. Fails with [Error::Bounds] if
+    /// the buffer has no room left for the padding.
+    ///
+    /// # Example
+    /// ```
+    /// use ad9959::{Channel, Error, Mode, ProfileSerializer};
+    ///
+    /// // Two full profile writes (28 bytes) and a CSR write (2 bytes) end at index 30, which is
+    /// // padded with a 2 byte CSR write to fill the buffer exactly.
+    /// let mut serializer = ProfileSerializer::new(Mode::FourBitSerial);
+    /// serializer.update_channels(Channel::ONE, Some(0), Some(0), Some(0));
+    /// serializer.update_channels(Channel::TWO, Some(0), Some(0), Some(0));
+    /// serializer.update_channels(Channel::THREE, None, None, None);
+    /// assert_eq!(serializer.remaining_bytes(), 2);
+    /// let words = serializer.finalize().unwrap();
+    /// assert_eq!(words.len(), 8);
+    /// assert_eq!(words[7].to_le_bytes(), [0x00, 0x46, 0x00, 0x06]);
+    ///
+    /// // A full profile, a CSR, CFTW0 and ACR write (11 bytes) and a CSR and ACR write (6 bytes)
+    /// // end at index 31. The 5 byte padding does not fit.
+    /// let mut serializer = ProfileSerializer::new(Mode::FourBitSerial);
+    /// serializer.update_channels(Channel::ONE, Some(0), Some(0), Some(0));
+    /// serializer.update_channels(Channel::TWO, Some(0), None, Some(0));
+    /// serializer.update_channels(Channel::THREE, None, None, Some(0));
+    /// assert_eq!(serializer.remaining_bytes(), 1);
+    /// assert!(matches!(serializer.finalize(), Err(Error::Bounds)));
+    /// assert_eq!(serializer.remaining_bytes(), 1);
+    /// ```
     #[inline]
-    pub fn finalize(&mut self) -> &[u32] {
-        self.pad();
-        bytemuck::cast_slice(&self.data[..self.index])
+    pub fn finalize(&mut self) -> Result<&[u32], Error> {
+        self.pad()?;
+        Ok(bytemuck::cast_slice(&self.data[..self.index]))
     }
 }
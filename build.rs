@@ -1,3 +1,8 @@
 fn main() {
     println!("cargo:rerun-if-changed=memory.x");
+
+    // The defmt linker script places the interned log strings.
+    if std::env::var_os("CARGO_FEATURE_DEFMT").is_some() {
+        println!("cargo:rustc-link-arg=-Tdefmt.x");
+    }
 }
@@ -13,6 +13,10 @@ ADC_VOLTS_PER_LSB = (5.0 / 2.0 * 4.096)  / (1 << 15)
 # The number of volts per DAC LSB.
 DAC_VOLTS_PER_LSB = 1 / DAC_LSB_PER_VOLT
 
+# The analog front-end gains indexed by the gain code reported in the stream frame header, see
+# `Gain::as_multiplier`.
+AFE_GAINS = (1, 2, 5, 10)
+
 # The absolute full-scale output voltage in either positive or negative direction exposed by the
 # DAC.
 DAC_FULL_SCALE = float(0x7FFF / DAC_LSB_PER_VOLT)
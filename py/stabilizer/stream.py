@@ -6,12 +6,13 @@ import asyncio
 import logging
 import struct
 import socket
+import zlib
 from collections import namedtuple
 from dataclasses import dataclass
 
 import numpy as np
 
-from . import DAC_VOLTS_PER_LSB
+from . import ADC_VOLTS_PER_LSB, DAC_VOLTS_PER_LSB, AFE_GAINS
 
 logger = logging.getLogger(__name__)
 
@@ -38,55 +39,148 @@ def get_local_ip(remote):
 class AdcDac:
     """Stabilizer default striming data format"""
     format_id = 1
+    labels = ["ADC0", "ADC1", "DAC0", "DAC1"]
 
     def __init__(self, header, body):
         self.header = header
         self.body = body
 
+    def channels(self):
+        """Return the indices of the channels contained in the frame"""
+        return [i for i in range(4) if self.header.channel_mask & (1 << i)]
+
     def batch_count(self):
         """Return the number of batches in the frame"""
-        return self.size() // (4 * 2 * self.header.batch_size)
+        return self.size() // (len(self.channels()) * 2 * self.header.batch_size)
 
     def size(self):
         """Return the data size of the frame in bytes"""
         return len(self.body)
 
     def to_mu(self):
-        """Return the raw data in machine units"""
+        """Return the raw data of the contained channels in machine units"""
+        channels = self.channels()
         data = np.frombuffer(self.body, "<i2")
-        data = data.reshape(-1, 4, self.header.batch_size)
-        data = data.swapaxes(0, 1).reshape(4, -1)
+        data = data.reshape(-1, len(channels), self.header.batch_size)
+        data = data.swapaxes(0, 1).reshape(len(channels), -1)
         # convert DAC offset binary to two's complement
-        data[2:] ^= np.int16(0x8000)
+        for i, channel in enumerate(channels):
+            if channel >= 2:
+                data[i] ^= np.int16(0x8000)
         return data
 
+    def afe_gains(self):
+        """Return the ADC0 and ADC1 analog front-end gains of the frame"""
+        return [AFE_GAINS[(self.header.afe_gains >> (2 * i)) & 0b11] for i in range(2)]
+
+    def scales(self):
+        """Return the scale in volts per LSB of each contained channel. The ADC
+        scales refer to the input voltage in front of the analog front-end."""
+        gains = self.afe_gains()
+        return [ADC_VOLTS_PER_LSB / gains[channel] if channel < 2 else DAC_VOLTS_PER_LSB
+                for channel in self.channels()]
+
     def to_si(self):
         """Convert the raw data to SI units"""
-        data = self.to_mu() * DAC_VOLTS_PER_LSB
+        channels = self.channels()
+        data = self.to_mu() * np.array(self.scales())[:, None]
         return {
-            "adc": data[:2],
-            "dac": data[2:],
+            "adc": data[[i for i, c in enumerate(channels) if c < 2]],
+            "dac": data[[i for i, c in enumerate(channels) if c >= 2]],
         }
 
     def to_traces(self):
         """Convert the raw data to labelled Trace instances"""
         data = self.to_mu()
+        scales = self.scales()
         return [
-            Trace(data[0], scale=DAC_VOLTS_PER_LSB, label='ADC0'),
-            Trace(data[1], scale=DAC_VOLTS_PER_LSB, label='ADC1'),
-            Trace(data[2], scale=DAC_VOLTS_PER_LSB, label='DAC0'),
-            Trace(data[3], scale=DAC_VOLTS_PER_LSB, label='DAC1')
+            Trace(data[i], scale=scales[i], label=self.labels[channel])
+            for i, channel in enumerate(self.channels())
         ]
 
 
+class AdcDacIirState(AdcDac):
+    """ADC/DAC data followed by the IIR filter state of each channel"""
+    format_id = 3
+    # The number of cascaded IIR biquads per channel, see `IIR_CASCADE_LENGTH`
+    cascade_length = 1
+
+    def _batch_size(self):
+        """Return the size of a single batch in bytes"""
+        return self._codes_size() + 2 * self.cascade_length * 5 * 4
+
+    def _codes_size(self):
+        """Return the size of the ADC/DAC data of a single batch in bytes"""
+        return len(self.channels()) * 2 * self.header.batch_size
+
+    def batch_count(self):
+        """Return the number of batches in the frame"""
+        return self.size() // self._batch_size()
+
+    def _batches(self):
+        return np.frombuffer(self.body, np.uint8).reshape(-1, self._batch_size())
+
+    def to_mu(self):
+        """Return the raw ADC/DAC data in machine units"""
+        codes = self._batches()[:, :self._codes_size()].tobytes()
+        return AdcDac(self.header, codes).to_mu()
+
+    def iir_state(self):
+        """Return the IIR state `[x0, x1, y0, y1, y2]` of each batch in machine units.
+        The array is indexed by `[batch, channel, cascade, element]`."""
+        state = self._batches()[:, self._codes_size():].tobytes()
+        return np.frombuffer(state, "<f4").reshape(-1, 2, self.cascade_length, 5)
+
+
+class Capture(AdcDac):
+    """One-shot capture of the ADC/DAC data in the default format"""
+    format_id = 4
+
+
+class IqData:
+    """Demodulated in-phase and quadrature components"""
+    format_id = 5
+
+    def __init__(self, header, body):
+        self.header = header
+        self.body = body
+
+    def channels(self):
+        """Return the indices of the channels contained in the frame"""
+        return [i for i in range(8) if self.header.channel_mask & (1 << i)]
+
+    def batch_count(self):
+        """Return the number of batches in the frame"""
+        return self.size() // (len(self.channels()) * 2 * 4 * self.header.batch_size)
+
+    def size(self):
+        """Return the data size of the frame in bytes"""
+        return len(self.body)
+
+    def to_mu(self):
+        """Return the complex data of the contained channels in machine units"""
+        channels = self.channels()
+        data = np.frombuffer(self.body, "<f4")
+        data = data.reshape(-1, len(channels), self.header.batch_size, 2)
+        data = data.swapaxes(0, 1).reshape(len(channels), -1, 2)
+        return data[..., 0] + 1j * data[..., 1]
+
+
 class StabilizerStream(asyncio.DatagramProtocol):
     """Stabilizer streaming receiver protocol"""
     # The magic header half-word at the start of each packet.
     magic = 0x057B
-    header_fmt = struct.Struct("<HBBI")
-    header = namedtuple("Header", "magic format_id batch_size sequence")
+    header_fmt = struct.Struct("<HBBBBIIIHI")
+    header = namedtuple(
+        "Header",
+        "magic format_id batch_size channel_mask afe_gains sequence frame timestamp length crc")
+    # The CRC covers the header up to the CRC field and the frame body.
+    crc_offset = 20
     parsers = {
         AdcDac.format_id: AdcDac,
+        AdcDacIirState.format_id: AdcDacIirState,
+        Capture.format_id: Capture,
+        IqData.format_id: IqData,
     }
 
     @classmethod
@@ -119,12 +213,20 @@ class StabilizerStream(asyncio.DatagramProtocol):
         if header.magic != self.magic:
             logger.warning("Bad frame magic: %#04x, ignoring", header.magic)
             return
+        body = data[self.header_fmt.size:]
+        if header.length != len(body):
+            logger.warning("Bad frame length: %d != %d, ignoring",
+                           len(body), header.length)
+            return
+        if zlib.crc32(body, zlib.crc32(data[:self.crc_offset])) != header.crc:
+            logger.warning("Bad frame CRC, ignoring")
+            return
         try:
             parser = self.parsers[header.format_id]
         except KeyError:
             logger.warning("No parser for format %s, ignoring", header.format_id)
             return
-        frame = parser(header, data[self.header_fmt.size:])
+        frame = parser(header, body)
         if self.queue.full():
             old = self.queue.get_nowait()
             logger.debug("Dropping frame: %#08x", old.header.sequence)
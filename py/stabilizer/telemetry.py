@@ -11,6 +11,9 @@ from gmqtt import Client as MqttClient
 
 _logger = logging.getLogger(__name__)
 
+# The telemetry format version supported by this client.
+TELEMETRY_FORMAT_VERSION = 1
+
 class Telemetry:
     """
     Stabilizer MQTT Telemetry receiver.
@@ -40,10 +43,16 @@ class Telemetry:
 
     def _handle_telemetry(self, _client, topic, payload, _qos, _properties):
         assert topic == self._telemetry_topic
+        telemetry = json.loads(payload)
+        version = telemetry.get("format_version")
+        if version != TELEMETRY_FORMAT_VERSION:
+            _logger.warning("Discarding telemetry with unsupported format version %s",
+                            version)
+            return
         while self.queue.full():
             _logger.debug("Discarding oldest telemetry message")
             self.queue.get_nowait()
-        self.queue.put_nowait(json.loads(payload))
+        self.queue.put_nowait(telemetry)
 
     async def get(self):
         """Retrieve the latest telemetry message in the queue or wait for a new one"""
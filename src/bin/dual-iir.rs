@@ -12,7 +12,9 @@
 //! * Down to 2 µs latency
 //! * f32 IIR math
 //! * Generic biquad (second order) IIR filter
+//! * PID controller configuration
 //! * Anti-windup
+//! * Optional input averaging and decimation
 //! * Derivative kick avoidance
 //!
 //! ## Settings
@@ -23,14 +25,33 @@
 //! Refer to [Telemetry] for information about telemetry reported by this application.
 //!
 //! ## Livestreaming
-//! This application streams raw ADC and DAC data over UDP. Refer to
+//! This application streams raw ADC and DAC data over UDP. Optionally, the IIR filter state can be
+//! streamed as well, see the `stream_format` setting. Refer to
 //! [stabilizer::net::data_stream](../stabilizer/net/data_stream/index.html) for more information.
+//!
+//! ## Boot Settings
+//! By default, the application applies the built-in default settings on boot and starts driving
+//! the outputs immediately. Settings retained on the MQTT broker are applied once Stabilizer has
+//! connected to the broker. Neither the default settings nor the settings republished by Stabilizer
+//! after connecting are retained, so they never replace the settings stored on the broker.
+//!
+//! To avoid driving the outputs with the default settings until the retained settings are
+//! received, specify a hold time in seconds using the `SETTINGS_HOLD` environment variable during
+//! the firmware build. A malformed value fails the build. The outputs are then held at the
+//! interlock `safe_output` voltages while the retained settings are applied.
+//!
+//! The hold is a fixed timer started at boot. It is neither shortened once the retained settings
+//! have been received nor extended while Stabilizer is still connecting. The hold time should
+//! therefore cover obtaining a network address, connecting to the broker and receiving the
+//! retained settings, which Stabilizer awaits for two seconds after subscribing. Once the hold
+//! time has elapsed, the outputs are driven with the settings applied so far, which are the
+//! default settings if the broker was not reached in time.
 #![deny(warnings)]
 #![no_std]
 #![no_main]
 
 use core::mem::MaybeUninit;
-use core::sync::atomic::{fence, Ordering};
+use core::sync::atomic::{fence, AtomicBool, AtomicU32, Ordering};
 
 use fugit::ExtU64;
 use mutex_trait::prelude::*;
@@ -38,21 +59,30 @@ use mutex_trait::prelude::*;
 use idsp::iir;
 
 use stabilizer::{
+    dsp::{biquad::FilterDesign, pid::Pid},
     hardware::{
         self,
         adc::{Adc0Input, Adc1Input, AdcCode},
         afe::Gain,
         dac::{Dac0Output, Dac1Output, DacCode},
         hal,
+        pounder::{
+            self, attenuators::AttenuatorInterface, Channel, DdsClockConfig,
+            PounderConfig,
+        },
         signal_generator::{self, SignalGenerator},
-        timers::SamplingTimer,
+        timers::{SamplingTimer, ShadowSamplingTimer},
         DigitalInput0, DigitalInput1, SystemTimer, Systick, AFE0, AFE1,
     },
+    logging,
     net::{
         data_stream::{FrameGenerator, StreamFormat, StreamTarget},
         miniconf::Miniconf,
-        telemetry::{Telemetry, TelemetryBuffer},
-        NetworkState, NetworkUsers,
+        network_processor::StaticIpConfig,
+        parse_build_setting,
+        serde::{Deserialize, Serialize},
+        telemetry::{Meta, Telemetry, TelemetryBuffer, TelemetryTopicPeriods},
+        NetworkState, NetworkUsers, MQTT_KEEPALIVE,
     },
 };
 
@@ -61,15 +91,348 @@ const SCALE: f32 = i16::MAX as _;
 // The number of cascaded IIR biquads per channel. Select 1 or 2!
 const IIR_CASCADE_LENGTH: usize = 1;
 
-// The number of samples in each batch process
-const BATCH_SIZE: usize = 8;
+// The number of samples in each batch process. Defaults to 8. Larger batches reduce the processing
+// overhead at the expense of latency. Select a different size using one of the `batch-<n>`
+// features with <n> := [1, 2, 4, 16, 32].
+const BATCH_SIZE: usize = if cfg!(feature = "batch-1") {
+    1
+} else if cfg!(feature = "batch-2") {
+    2
+} else if cfg!(feature = "batch-4") {
+    4
+} else if cfg!(feature = "batch-16") {
+    16
+} else if cfg!(feature = "batch-32") {
+    32
+} else {
+    8
+};
+
+const _: () = assert!(
+    cfg!(feature = "batch-1") as u8
+        + cfg!(feature = "batch-2") as u8
+        + cfg!(feature = "batch-4") as u8
+        + cfg!(feature = "batch-16") as u8
+        + cfg!(feature = "batch-32") as u8
+        <= 1,
+    "Select at most one batch size feature"
+);
+
+// The channels contained in the data stream. Bits 0 to 3 select ADC0, ADC1, DAC0, and DAC1.
+// Streaming fewer channels increases the number of batches streamed per frame.
+const STREAM_CHANNELS: u8 = 0b1111;
+
+// The default time in seconds the outputs are held after boot while awaiting retained settings.
+// Zero drives the outputs with the default settings immediately. See the `SETTINGS_HOLD`
+// environment variable.
+const DEFAULT_SETTINGS_HOLD: u32 = 0;
+
+// The time in seconds the outputs are held after boot. A malformed `SETTINGS_HOLD` fails the build.
+const SETTINGS_HOLD: u32 = match option_env!("SETTINGS_HOLD") {
+    Some(hold) => parse_build_setting(hold, u32::MAX),
+    None => DEFAULT_SETTINGS_HOLD,
+};
+
+// The number of samples of each channel in a one-shot capture. This must be a multiple of the
+// batch size. The capture buffer occupies 8 bytes per sample.
+const CAPTURE_LENGTH: usize = 1024;
 
 // The logarithm of the number of 100MHz timer ticks between each sample. With a value of 2^7 =
-// 128, there is 1.28uS per sample, corresponding to a sampling frequency of 781.25 KHz.
+// 128, there is 1.28uS per sample, corresponding to a sampling frequency of 781.25 KHz. This is
+// also the shortest sample period the DSP processing is guaranteed to keep up with.
 const SAMPLE_TICKS_LOG2: u8 = 7;
 const SAMPLE_TICKS: u32 = 1 << SAMPLE_TICKS_LOG2;
-const SAMPLE_PERIOD: f32 =
-    SAMPLE_TICKS as f32 * hardware::design_parameters::TIMER_PERIOD;
+
+// The longest sample period supported by the 16-bit shadow sampling timer.
+const MAX_SAMPLE_TICKS: u32 = 1 << 16;
+
+/// Get the sample period in seconds.
+///
+/// # Args
+/// * `sample_ticks` - The number of 100MHz timer ticks between each sample.
+fn sample_period(sample_ticks: u32) -> f32 {
+    sample_ticks as f32 * hardware::design_parameters::TIMER_PERIOD
+}
+
+// The DAC0/DAC1 output voltages from boot until the first IIR outputs are generated. Afterwards,
+// the output is determined by the filters, e.g. their `y_offset`.
+const STARTUP_OUTPUT: [f32; 2] = [0.0, 0.0];
+
+/// The maximum log2 of the number of ADC samples averaged for each IIR update. This ensures that
+/// the accumulated ADC codes fit into an `i32`.
+pub const MAX_DECIMATION: u8 = 15;
+
+/// Boxcar averaging state of the input to an IIR channel.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Decimator {
+    /// The sum of the ADC codes accumulated so far.
+    sum: i32,
+    /// The number of ADC codes accumulated so far.
+    count: u32,
+    /// The most recent IIR output, held between updates.
+    output: f32,
+}
+
+/// Selects the trigger of a one-shot capture.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+enum CaptureTrigger {
+    /// The capture starts with the next batch once armed.
+    Immediate,
+    /// The capture starts with the first batch after DI0 changes from low to high.
+    DI0,
+    /// The capture starts with the first batch after DI1 changes from low to high.
+    DI1,
+}
+
+/// The progress of a one-shot capture.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CaptureState {
+    /// No capture is requested.
+    Idle,
+    /// The capture waits for the trigger.
+    Armed,
+    /// The capture is recording. Contains the index of the next batch to record.
+    Recording(usize),
+    /// The captured data is streamed. Contains the index of the next batch to stream.
+    Sending(usize),
+}
+
+/// A one-shot capture of the ADC and DAC codes.
+pub struct Capture {
+    state: CaptureState,
+    /// The digital input states of the previous batch, used to detect trigger edges.
+    inputs: [bool; 2],
+    /// The captured ADC0, ADC1, DAC0, and DAC1 codes of each batch.
+    batches: [[[u16; BATCH_SIZE]; 4]; CAPTURE_LENGTH / BATCH_SIZE],
+}
+
+impl Capture {
+    const fn new() -> Self {
+        Self {
+            state: CaptureState::Idle,
+            inputs: [false; 2],
+            batches: [[[0; BATCH_SIZE]; 4]; CAPTURE_LENGTH / BATCH_SIZE],
+        }
+    }
+
+    /// Advance the capture by one batch.
+    ///
+    /// # Args
+    /// * `trigger` - The trigger starting the capture once armed.
+    /// * `inputs` - The digital input states of the batch.
+    /// * `codes` - The ADC0, ADC1, DAC0, and DAC1 codes of the batch.
+    ///
+    /// # Returns
+    /// The captured batch to stream in place of the batch codes while the capture is sent.
+    fn update(
+        &mut self,
+        trigger: CaptureTrigger,
+        inputs: [bool; 2],
+        codes: [&[u16]; 4],
+    ) -> Option<&[[u16; BATCH_SIZE]; 4]> {
+        let previous = core::mem::replace(&mut self.inputs, inputs);
+
+        // A request received while a capture is in progress is applied once it completed.
+        if self.state == CaptureState::Idle
+            && CAPTURE_REQUESTED.swap(false, Ordering::Relaxed)
+        {
+            self.state = CaptureState::Armed;
+        }
+
+        if self.state == CaptureState::Armed {
+            let triggered = match trigger {
+                CaptureTrigger::Immediate => true,
+                CaptureTrigger::DI0 => inputs[0] && !previous[0],
+                CaptureTrigger::DI1 => inputs[1] && !previous[1],
+            };
+            if triggered {
+                self.state = CaptureState::Recording(0);
+            }
+        }
+
+        let count = self.batches.len();
+        match self.state {
+            CaptureState::Recording(index) => {
+                for (batch, codes) in self.batches[index].iter_mut().zip(codes)
+                {
+                    batch.copy_from_slice(codes);
+                }
+                self.state = if index + 1 < count {
+                    CaptureState::Recording(index + 1)
+                } else {
+                    CaptureState::Sending(0)
+                };
+                None
+            }
+            CaptureState::Sending(index) => {
+                self.state = if index + 1 < count {
+                    CaptureState::Sending(index + 1)
+                } else {
+                    CaptureState::Idle
+                };
+                Some(&self.batches[index])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Selects how the filter of an IIR channel is configured.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+enum FilterMode {
+    /// The raw biquad coefficients in `iir_ch` are used.
+    Iir,
+    /// The first biquad in `iir_ch` is computed from the controller parameters in `pid_ch`.
+    Pid,
+    /// The first biquad in `iir_ch` is computed from the filter design in `filter_design`.
+    Design,
+}
+
+/// Selects a digital input.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+enum DigitalInput {
+    DI0 = 0,
+    DI1 = 1,
+}
+
+/// Selects the digital input gating a signal generator.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+enum SignalGate {
+    /// The signal generator output is always applied.
+    Ungated,
+    /// The signal generator output is only applied while DI0 is high.
+    DI0,
+    /// The signal generator output is only applied while DI1 is high.
+    DI1,
+}
+
+/// Selects the response to an ADC or DAC SPI error.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+enum SpiErrorPolicy {
+    /// Panic, which halts the application.
+    Panic,
+    /// Clear the error, log it, count it in telemetry and hold the previous outputs for the
+    /// current batch.
+    HoldAndLog,
+}
+
+/// Configuration of the output interlock.
+///
+/// # Miniconf
+/// `{"timeout": <timeout>, "safe_output": [<out0>, <out1>]}`
+///
+/// * `<timeout>` is the time in seconds without a connection to the MQTT broker after which the
+///   interlock trips. Zero disables the interlock.
+/// * `<out0>`, `<out1>` are the DAC0/DAC1 output voltages while the interlock is tripped.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+struct Interlock {
+    timeout: u16,
+    safe_output: [f32; 2],
+}
+
+/// Configuration of the Pounder thermal derating.
+///
+/// # Miniconf
+/// `{"threshold": <threshold>, "step": <step>}`
+///
+/// * `<threshold>` is the Pounder temperature in degrees Celsius above which the output
+///   attenuation is increased.
+/// * `<step>` is the attenuation in dB added to both Pounder outputs every telemetry period while
+///   the temperature exceeds the threshold. The resulting attenuation is rounded to the 0.5 dB
+///   attenuator resolution. Zero disables the derating.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize, Miniconf)]
+struct ThermalDerating {
+    threshold: f32,
+    step: f32,
+}
+
+/// Specified true while the interlock is tripped. Set by the network processing and read by the
+/// DSP routine.
+static INTERLOCK_TRIPPED: AtomicBool = AtomicBool::new(false);
+
+/// Specified true while the outputs are held at the safe output voltages due to a failed settings
+/// update. Set by the settings update and read by the DSP routine.
+static SETTINGS_FAILED: AtomicBool = AtomicBool::new(false);
+
+/// Specified true while the outputs are held after boot awaiting retained settings from the broker.
+/// Set during initialization and cleared by the `release_outputs` task.
+static SETTINGS_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Specified true while a one-shot capture is requested but not yet armed. Set by the settings
+/// update and cleared by the DSP routine.
+static CAPTURE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Specified true if SPI errors are recovered instead of causing a panic. Set by the settings
+/// update and read by the SPI error handlers.
+static SPI_ERROR_HOLD: AtomicBool = AtomicBool::new(false);
+
+/// Specified true if an SPI error was recovered since the last batch. Set by the SPI error
+/// handlers and cleared by the DSP routine.
+static SPI_ERROR_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// The number of recovered SPI errors of ADC0, ADC1, DAC0 and DAC1.
+static SPI_ERRORS: [AtomicU32; 4] = [
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+    AtomicU32::new(0),
+];
+
+/// Apply a validated Pounder configuration to the hardware.
+///
+/// # Note
+/// The DDS profiles of IN0 and OUT0 as well as of IN1 and OUT1 are each written in a single
+/// transfer. The configuration is applied up to the first failure.
+///
+/// # Args
+/// * `pounder` - The Pounder devices.
+/// * `config` - The Pounder configuration. It must have passed [PounderConfig::validate].
+fn configure_pounder(
+    pounder: &mut hardware::setup::PounderDevices,
+    config: &PounderConfig,
+) -> Result<(), pounder::Error> {
+    let system_clock = config.clock.validate()?;
+
+    let channels =
+        [(Channel::In0, Channel::Out0), (Channel::In1, Channel::Out1)];
+    for ((input, output), (in_channel, out_channel)) in config
+        .in_channel
+        .iter()
+        .zip(config.out_channel.iter())
+        .zip(channels)
+    {
+        pounder
+            .pounder
+            .set_attenuation(in_channel, input.attenuation)?;
+        pounder
+            .pounder
+            .set_attenuation(out_channel, output.attenuation)?;
+
+        pounder.dds_output.update(&[
+            (in_channel.into(), input.mixer.to_profile(system_clock)?),
+            (out_channel.into(), output.channel.to_profile(system_clock)?),
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// Handle an ADC or DAC SPI error according to the configured [SpiErrorPolicy].
+///
+/// # Args
+/// * `index` - The index of the converter: ADC0, ADC1, DAC0 or DAC1.
+/// * `clear` - A function clearing the error flags of the SPI peripheral.
+fn spi_error(index: usize, clear: fn()) {
+    let name = ["ADC0", "ADC1", "DAC0", "DAC1"][index];
+    if !SPI_ERROR_HOLD.load(Ordering::Relaxed) {
+        panic!("{} SPI error", name);
+    }
+
+    clear();
+    SPI_ERRORS[index].fetch_add(1, Ordering::Relaxed);
+    SPI_ERROR_PENDING.store(true, Ordering::Relaxed);
+    logging::warn!("{} SPI error", name);
+}
 
 #[derive(Clone, Copy, Debug, Miniconf)]
 pub struct Settings {
@@ -96,7 +459,129 @@ pub struct Settings {
     /// See [iir::IIR#miniconf]
     iir_ch: [[iir::IIR<f32>; IIR_CASCADE_LENGTH]; 2],
 
-    /// Specified true if DI1 should be used as a "hold" input.
+    /// Selects how the IIR filters are configured.
+    ///
+    /// # Path
+    /// `filter_mode/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// Any of the variants of [FilterMode] enclosed in double quotes.
+    filter_mode: [FilterMode; 2],
+
+    /// Configure the PID controller parameters.
+    ///
+    /// # Path
+    /// `pid_ch/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// See [Pid#miniconf]
+    ///
+    /// # Note
+    /// The parameters are only used if the `filter_mode` of the channel is [FilterMode::Pid]. The
+    /// controller replaces the first biquad of the channel in `iir_ch`. The IIR sample period
+    /// including any `decimation` is accounted for in the conversion.
+    pid_ch: [Pid; 2],
+
+    /// Configure the filters using a named response, frequency, and quality factor.
+    ///
+    /// # Path
+    /// `filter_design/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// See [FilterDesign#miniconf]
+    ///
+    /// # Note
+    /// The design is only used if the `filter_mode` of the channel is [FilterMode::Design]. The
+    /// designed filter replaces the first biquad of the channel in `iir_ch`. The IIR sample period
+    /// including any `decimation` is accounted for in the conversion.
+    filter_design: [FilterDesign; 2],
+
+    /// Specifies the output voltage limits applied to the sum of the filter and signal generator
+    /// outputs.
+    ///
+    /// # Path
+    /// `output_limit/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// `[min, max]` in volts. The minimum must not exceed the maximum. Limits beyond the DAC
+    /// output range have no effect. Invalid limits are rejected and the previous limits retained.
+    /// The number of clamped output samples is reported in [Telemetry].
+    output_limit: [[f32; 2]; 2],
+
+    /// Specifies whether the output polarity of a channel is inverted.
+    ///
+    /// # Path
+    /// `output_invert/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// If true, the sum of the filter and signal generator outputs is negated before the
+    /// `output_limit` is applied. The negative full scale code saturates to the positive full
+    /// scale code.
+    output_invert: [bool; 2],
+
+    /// Specifies whether the filter of a channel is bypassed.
+    ///
+    /// # Path
+    /// `bypass/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// If true, the ADC input code is passed through to the DAC output, skipping the decimation and
+    /// the IIR filter. The signal generator, `output_invert`, `slew_limit` and `output_limit`
+    /// still apply.
+    ///
+    /// # Note
+    /// The ADC and DAC full scale ranges match, such that the passthrough has unity gain from the
+    /// ADC input to the DAC output. The AFE gain is not compensated. The filter state is frozen
+    /// while bypassed and resumes once the bypass is disabled.
+    bypass: [bool; 2],
+
+    /// Specifies the maximum output change per sample.
+    ///
+    /// # Path
+    /// `slew_limit/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// The maximum change of the output between consecutive samples in volts. Zero disables the
+    /// slew rate limit. Limits below one DAC LSB allow a change of one LSB per sample. Negative
+    /// values are rejected and the previous limit retained.
+    ///
+    /// # Note
+    /// The slew rate limit is applied to the sum of the filter and signal generator outputs
+    /// before the `output_limit`, so an output outside of changed output limits is clamped
+    /// immediately. The limit also applies when leaving hold, such that the output approaches
+    /// the resumed filter output gradually. The interlock safe output is applied immediately,
+    /// and the output slews from the safe output once the interlock is reset.
+    slew_limit: [f32; 2],
+
+    /// Specifies the log2 of the number of ADC samples averaged for each IIR update.
+    ///
+    /// # Path
+    /// `decimation`
+    ///
+    /// # Value
+    /// Any value from 0 to [MAX_DECIMATION]. With a value of `n`, `2^n` consecutive ADC samples
+    /// are averaged (boxcar) and the IIR filters are updated once with the average. The filter
+    /// output (and thus the DAC output, apart from any signal generator contribution) is held
+    /// between updates. The effective sample period of the IIR filters is `2^n` times the ADC
+    /// sample period, which must be accounted for in the filter coefficient design. Larger
+    /// values are limited to [MAX_DECIMATION].
+    decimation: u8,
+
+    /// Specified true if the `hold_source` digital input should be used as a "hold" input.
     ///
     /// # Path
     /// `allow_hold`
@@ -105,7 +590,27 @@ pub struct Settings {
     /// "true" or "false"
     allow_hold: bool,
 
-    /// Specified true if "hold" should be forced regardless of DI1 state and hold allowance.
+    /// Selects the digital input used as the "hold" input.
+    ///
+    /// # Path
+    /// `hold_source`
+    ///
+    /// # Value
+    /// Any of the variants of [DigitalInput] enclosed in double quotes.
+    hold_source: DigitalInput,
+
+    /// Specifies the polarity of the "hold" input.
+    ///
+    /// # Path
+    /// `hold_polarity`
+    ///
+    /// # Value
+    /// "true" if the hold is asserted while the input is high, "false" if the hold is asserted
+    /// while the input is low.
+    hold_polarity: bool,
+
+    /// Specified true if "hold" should be forced regardless of the hold input state and hold
+    /// allowance.
     ///
     /// # Path
     /// `force_hold`
@@ -123,6 +628,17 @@ pub struct Settings {
     /// Any non-zero value less than 65536.
     telemetry_period: u16,
 
+    /// Specifies the publication periods of the individual telemetry sub-topics.
+    ///
+    /// # Path
+    /// `telemetry_topic_period/<topic>`
+    ///
+    /// * `<topic>` is one of `adc`, `pounder`, `temperature`, or `status`.
+    ///
+    /// # Value
+    /// The sub-topic period in seconds. Zero disables the sub-topic.
+    telemetry_topic_period: TelemetryTopicPeriods,
+
     /// Specifies the target for data livestreaming.
     ///
     /// # Path
@@ -132,6 +648,108 @@ pub struct Settings {
     /// See [StreamTarget#miniconf]
     stream_target: StreamTarget,
 
+    /// Specifies whether the data livestream is transmitted.
+    ///
+    /// # Path
+    /// `stream_enabled`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// While disabled, stream data is discarded. The `stream_target` is retained, so streaming
+    /// resumes immediately once enabled again.
+    stream_enabled: bool,
+
+    /// Specifies the format of the data livestream.
+    ///
+    /// # Path
+    /// `stream_format`
+    ///
+    /// # Value
+    /// `"AdcDacData"` or `"IirState"`. See [StreamFormat].
+    ///
+    /// # Note
+    /// The format is changed on the next batch boundary. Frames never mix batches of different
+    /// formats.
+    stream_format: StreamFormat,
+
+    /// Selects the trigger of a one-shot capture.
+    ///
+    /// # Path
+    /// `capture_trigger`
+    ///
+    /// # Value
+    /// Any of the variants of [CaptureTrigger] enclosed in double quotes.
+    capture_trigger: CaptureTrigger,
+
+    /// Specified true to arm a one-shot capture.
+    ///
+    /// # Path
+    /// `capture_arm`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// The capture is armed once each time this setting changes from false to true. Once
+    /// triggered, [CAPTURE_LENGTH] samples of all ADC and DAC channels are recorded. The recorded
+    /// samples are then streamed in the [StreamFormat::Capture] format in place of the livestream,
+    /// which resumes afterwards.
+    capture_arm: bool,
+
+    /// Specifies a static IP configuration to apply at run-time.
+    ///
+    /// # Path
+    /// `static_ip`
+    ///
+    /// # Value
+    /// See [StaticIpConfig#miniconf]
+    static_ip: StaticIpConfig,
+
+    /// Specified true to run a self-test of the Pounder DDS communication interface.
+    ///
+    /// # Path
+    /// `dds_self_test`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// The self-test is run once each time this setting changes from false to true. The result is
+    /// reported in [Telemetry]. The setting is ignored if Pounder is not present.
+    dds_self_test: bool,
+
+    /// Specified true to reset and reinitialize the Pounder DDS.
+    ///
+    /// # Path
+    /// `dds_reset`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// The DDS is reset once each time this setting changes from false to true. This recovers a
+    /// DDS that lost synchronization without rebooting Stabilizer. The setting is ignored if
+    /// Pounder is not present.
+    dds_reset: bool,
+
+    /// Configures the Pounder DDS channels and attenuators.
+    ///
+    /// # Path
+    /// `pounder/<field>`
+    ///
+    /// # Value
+    /// See [PounderConfig]
+    ///
+    /// # Note
+    /// The complete configuration is validated on every update before any of it is applied. If any
+    /// value is invalid, the previous configuration is retained as a whole, such that Pounder is
+    /// never left partially configured. The DDS clock is configured during setup and can not be
+    /// changed at run-time, so a configuration with a different `clock` is rejected. The setting
+    /// is ignored if Pounder is not present.
+    pounder: PounderConfig,
+
     /// Specifies the config for signal generators to add on to DAC0/DAC1 outputs.
     ///
     /// # Path
@@ -142,6 +760,134 @@ pub struct Settings {
     /// # Value
     /// See [signal_generator::BasicConfig#miniconf]
     signal_generator: [signal_generator::BasicConfig; 2],
+
+    /// Selects the digital input gating the signal generator output of a channel.
+    ///
+    /// # Path
+    /// `signal_gate/<n>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    ///
+    /// # Value
+    /// Any of the variants of [SignalGate] enclosed in double quotes.
+    ///
+    /// # Note
+    /// While the gate is closed, the signal generator keeps running but its output is not added
+    /// to the DAC output. The signal phase thus remains continuous across gating.
+    signal_gate: [SignalGate; 2],
+
+    /// Configures the output interlock driving the DAC outputs to safe values when the connection
+    /// to the MQTT broker is lost.
+    ///
+    /// # Path
+    /// `interlock`
+    ///
+    /// # Value
+    /// See [Interlock#miniconf]
+    ///
+    /// # Note
+    /// While the interlock is tripped, the filters keep running but their outputs are replaced by
+    /// the safe output voltages. Output limits do not apply to the safe output voltages. Normal
+    /// output resumes as soon as the broker connection is reestablished.
+    interlock: Interlock,
+
+    /// Specifies whether a settings update that is not applied in full drives the outputs to safe
+    /// values.
+    ///
+    /// # Path
+    /// `fail_safe_on_error`
+    ///
+    /// # Value
+    /// If true, the DAC outputs are replaced by the `interlock` safe output voltages whenever any
+    /// value of a settings update is rejected. Normal output resumes once a settings update is
+    /// applied without rejecting a value. If false, rejected values are logged and the previous
+    /// values retained.
+    fail_safe_on_error: bool,
+
+    /// Specifies the response to an ADC or DAC SPI error.
+    ///
+    /// # Path
+    /// `spi_error_policy`
+    ///
+    /// # Value
+    /// One of the variants of [SpiErrorPolicy] enclosed in double quotes.
+    ///
+    /// # Note
+    /// SPI errors indicate that samples were lost, e.g. because the processing did not keep up.
+    /// With `"HoldAndLog"`, the outputs of the batch processed after an error are held at their
+    /// previous values and the errors are counted in telemetry. Isolated errors may be transient,
+    /// but repeated errors indicate a real hardware or timing problem that holding cannot fix.
+    spi_error_policy: SpiErrorPolicy,
+
+    /// Configures the automatic increase of the Pounder output attenuation on over-temperature.
+    ///
+    /// # Path
+    /// `pounder_derating`
+    ///
+    /// # Value
+    /// See [ThermalDerating#miniconf]
+    ///
+    /// # Note
+    /// The Pounder temperature is sampled every telemetry period. The attenuation is never
+    /// decreased automatically. The derating state is reported in [Telemetry]. The setting is
+    /// ignored if Pounder is not present.
+    pounder_derating: ThermalDerating,
+
+    /// Specifies the number of power detector samples averaged for each Pounder input power
+    /// measurement reported in telemetry.
+    ///
+    /// # Path
+    /// `pounder_power_samples`
+    ///
+    /// # Value
+    /// Any value from 1 to 255. Zero is treated as a single sample.
+    pounder_power_samples: u8,
+
+    /// Specifies the number of 100MHz timer ticks between each ADC/DAC sample.
+    ///
+    /// # Path
+    /// `sample_ticks`
+    ///
+    /// # Value
+    /// An integer of at least 128 (781.25 KHz sampling frequency) and at most 65536. Longer
+    /// sample periods are further limited such that a batch of samples spans less than one
+    /// millisecond.
+    ///
+    /// # Note
+    /// Shorter sample periods are rejected as the DSP processing could not keep up. Applying a
+    /// new sample period briefly pauses sampling. PID filters and signal generators are
+    /// reconfigured for the new sample period, but raw IIR coefficients given in `iir_ch` are
+    /// not rescaled and thus change their frequency response with the sample period.
+    sample_ticks: u32,
+
+    /// Specified true to restore the default settings.
+    ///
+    /// # Path
+    /// `reset_to_default`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// All settings, including this one, are reset to their defaults and republished. The request
+    /// should not be retained on the broker, as it would otherwise be applied again whenever
+    /// Stabilizer reconnects. Similarly, any other settings retained on the broker are applied
+    /// again on the next connection.
+    reset_to_default: bool,
+
+    /// Specified true to reboot the device.
+    ///
+    /// # Path
+    /// `reboot`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// The device reboots shortly after the request, such that the response to the settings
+    /// change is transmitted to the broker first. The request must not be retained on the
+    /// broker, as it would otherwise be applied again after every reboot.
+    reboot: bool,
 }
 
 impl Default for Settings {
@@ -155,16 +901,68 @@ impl Default for Settings {
             // The IIR coefficients can be mapped to other transfer function
             // representations, for example as described in https://arxiv.org/abs/1508.06319
             iir_ch: [[iir::IIR::new(1., -SCALE, SCALE); IIR_CASCADE_LENGTH]; 2],
-            // Permit the DI1 digital input to suppress filter output updates.
+            // Use the raw IIR coefficients.
+            filter_mode: [FilterMode::Iir; 2],
+            pid_ch: [Pid::default(); 2],
+            filter_design: [FilterDesign::default(); 2],
+            // Do not limit the output beyond the DAC range.
+            output_limit: [[-DacCode::FULL_SCALE, DacCode::FULL_SCALE]; 2],
+            // Do not invert the outputs.
+            output_invert: [false; 2],
+            // Do not bypass the filters.
+            bypass: [false; 2],
+            slew_limit: [0.; 2],
+            // Update the IIR filters with every ADC sample.
+            decimation: 0,
+            // Permit the hold input to suppress filter output updates.
             allow_hold: false,
+            // Use DI1 as an active-high hold input.
+            hold_source: DigitalInput::DI1,
+            hold_polarity: true,
             // Force suppress filter output updates.
             force_hold: false,
             // The default telemetry period in seconds.
             telemetry_period: 10,
+            // All telemetry sub-topics are disabled by default.
+            telemetry_topic_period: TelemetryTopicPeriods::default(),
 
             signal_generator: [signal_generator::BasicConfig::default(); 2],
+            signal_gate: [SignalGate::Ungated; 2],
 
             stream_target: StreamTarget::default(),
+            stream_enabled: true,
+            stream_format: StreamFormat::AdcDacData,
+            capture_trigger: CaptureTrigger::Immediate,
+            capture_arm: false,
+
+            // Keep the boot-time network configuration.
+            static_ip: StaticIpConfig::default(),
+
+            dds_self_test: false,
+            dds_reset: false,
+
+            // Disable the interlock.
+            interlock: Interlock {
+                timeout: 0,
+                safe_output: [0.0, 0.0],
+            },
+            // Keep operating with the previous values on settings errors.
+            fail_safe_on_error: false,
+            spi_error_policy: SpiErrorPolicy::Panic,
+
+            // Disable the thermal derating.
+            pounder_derating: ThermalDerating {
+                threshold: 60.0,
+                step: 0.0,
+            },
+            pounder_power_samples: 8,
+            // Disable all DDS channels at maximum attenuation.
+            pounder: PounderConfig::default(),
+
+            sample_ticks: SAMPLE_TICKS,
+
+            reset_to_default: false,
+            reboot: false,
         }
     }
 }
@@ -182,19 +980,27 @@ mod app {
 
         settings: Settings,
         telemetry: TelemetryBuffer,
+        afes: (AFE0, AFE1),
         signal_generator: [SignalGenerator; 2],
+        pounder: Option<hardware::setup::PounderDevices>,
+        sampling_timer: SamplingTimer,
+        shadow_sampling_timer: ShadowSamplingTimer,
     }
 
     #[local]
     struct Local {
-        sampling_timer: SamplingTimer,
         digital_inputs: (DigitalInput0, DigitalInput1),
-        afes: (AFE0, AFE1),
         adcs: (Adc0Input, Adc1Input),
         dacs: (Dac0Output, Dac1Output),
         iir_state: [[iir::Vec5<f32>; IIR_CASCADE_LENGTH]; 2],
+        decimators: [Decimator; 2],
         generator: FrameGenerator,
+        capture: Capture,
+        previous_output: [i16; 2],
         cpu_temp_sensor: stabilizer::hardware::cpu_temp_sensor::CpuTempSensor,
+        telemetry_elapsed: u32,
+        pounder_absent_warned: bool,
+        pounder_configured: bool,
     }
 
     #[init]
@@ -202,17 +1008,19 @@ mod app {
         let clock = SystemTimer::new(|| monotonics::now().ticks() as u32);
 
         // Configure the microcontroller
-        let (stabilizer, _pounder) = hardware::setup::setup(
+        let (stabilizer, pounder) = hardware::setup::setup(
             c.core,
             c.device,
             clock,
             BATCH_SIZE,
             SAMPLE_TICKS,
+            STARTUP_OUTPUT,
         );
 
         let mut network = NetworkUsers::new(
             stabilizer.net.stack,
             stabilizer.net.phy,
+            stabilizer.net.crc,
             clock,
             env!("CARGO_BIN_NAME"),
             stabilizer.net.mac_address,
@@ -220,40 +1028,71 @@ mod app {
                 .unwrap_or("10.34.16.10")
                 .parse()
                 .unwrap(),
+            MQTT_KEEPALIVE,
         );
 
-        let generator = network
-            .configure_streaming(StreamFormat::AdcDacData, BATCH_SIZE as _);
-
         let settings = Settings::default();
 
+        network.telemetry.set_meta(Meta::new(
+            env!("CARGO_BIN_NAME"),
+            stabilizer.net.mac_address,
+            pounder.is_some(),
+            BATCH_SIZE,
+            sample_period(settings.sample_ticks),
+        ));
+
+        let generator = network.configure_streaming(
+            settings.stream_format,
+            BATCH_SIZE as _,
+            STREAM_CHANNELS,
+        );
+
+        let telemetry = TelemetryBuffer {
+            pounder_present: pounder.is_some(),
+            ..Default::default()
+        };
+
         let shared = Shared {
             network,
             settings,
-            telemetry: TelemetryBuffer::default(),
+            telemetry,
+            afes: stabilizer.afes,
             signal_generator: [
                 SignalGenerator::new(
                     settings.signal_generator[0]
-                        .try_into_config(SAMPLE_PERIOD, DacCode::FULL_SCALE)
+                        .try_into_config(
+                            sample_period(settings.sample_ticks),
+                            DacCode::FULL_SCALE,
+                        )
                         .unwrap(),
                 ),
                 SignalGenerator::new(
                     settings.signal_generator[1]
-                        .try_into_config(SAMPLE_PERIOD, DacCode::FULL_SCALE)
+                        .try_into_config(
+                            sample_period(settings.sample_ticks),
+                            DacCode::FULL_SCALE,
+                        )
                         .unwrap(),
                 ),
             ],
+            pounder,
+            sampling_timer: stabilizer.adc_dac_timer,
+            shadow_sampling_timer: stabilizer.adc_dac_shadow_timer,
         };
 
         let mut local = Local {
-            sampling_timer: stabilizer.adc_dac_timer,
             digital_inputs: stabilizer.digital_inputs,
-            afes: stabilizer.afes,
             adcs: stabilizer.adcs,
             dacs: stabilizer.dacs,
             iir_state: [[[0.; 5]; IIR_CASCADE_LENGTH]; 2],
+            decimators: [Decimator::default(); 2],
             generator,
+            capture: Capture::new(),
+            previous_output: [0; 2],
             cpu_temp_sensor: stabilizer.temperature_sensor,
+            telemetry_elapsed: 0,
+            pounder_absent_warned: false,
+            pounder_configured: false,
         };
 
         // Enable ADC/DAC events
@@ -262,19 +1101,27 @@ mod app {
         local.dacs.0.start();
         local.dacs.1.start();
 
+        // Optionally hold the outputs while awaiting retained settings from the broker.
+        if SETTINGS_HOLD != 0 {
+            SETTINGS_PENDING.store(true, Ordering::Relaxed);
+            release_outputs::spawn_after((SETTINGS_HOLD as u64).secs())
+                .unwrap();
+        }
+
         // Spawn a settings update for default settings.
         settings_update::spawn().unwrap();
         telemetry::spawn().unwrap();
         ethernet_link::spawn().unwrap();
+        pounder_derating::spawn().unwrap();
         start::spawn_after(100.millis()).unwrap();
 
         (shared, local, init::Monotonics(stabilizer.systick))
     }
 
-    #[task(priority = 1, local=[sampling_timer])]
-    fn start(c: start::Context) {
+    #[task(priority = 1, shared=[sampling_timer])]
+    fn start(mut c: start::Context) {
         // Start sampling ADCs and DACs.
-        c.local.sampling_timer.start();
+        c.shared.sampling_timer.lock(|timer| timer.start());
     }
 
     /// Main DSP processing routine.
@@ -293,7 +1140,7 @@ mod app {
     ///
     /// Because the ADC and DAC operate at the same rate, these two constraints actually implement
     /// the same time bounds, meeting one also means the other is also met.
-    #[task(binds=DMA1_STR4, local=[digital_inputs, adcs, dacs, iir_state, generator], shared=[settings, signal_generator, telemetry], priority=3)]
+    #[task(binds=DMA1_STR4, local=[digital_inputs, adcs, dacs, iir_state, decimators, generator, capture, previous_output], shared=[settings, signal_generator, telemetry], priority=3)]
     #[link_section = ".itcm.process"]
     fn process(c: process::Context) {
         let process::SharedResources {
@@ -307,17 +1154,31 @@ mod app {
             adcs: (adc0, adc1),
             dacs: (dac0, dac1),
             iir_state,
+            decimators,
             generator,
+            capture,
+            previous_output,
         } = c.local;
 
+        #[cfg(feature = "profile")]
+        let start = cortex_m::peripheral::DWT::cycle_count();
+
         (settings, telemetry, signal_generator).lock(
             |settings, telemetry, signal_generator| {
                 let digital_inputs =
                     [digital_inputs.0.is_high(), digital_inputs.1.is_high()];
                 telemetry.digital_inputs = digital_inputs;
 
-                let hold = settings.force_hold
-                    || (digital_inputs[1] && settings.allow_hold);
+                let hold_input = digital_inputs[settings.hold_source as usize]
+                    == settings.hold_polarity;
+                let hold =
+                    settings.force_hold || (hold_input && settings.allow_hold);
+
+                let interlocked = INTERLOCK_TRIPPED.load(Ordering::Relaxed)
+                    || SETTINGS_FAILED.load(Ordering::Relaxed)
+                    || SETTINGS_PENDING.load(Ordering::Relaxed);
+                let spi_error =
+                    SPI_ERROR_PENDING.swap(false, Ordering::Relaxed);
 
                 (adc0, adc1, dac0, dac1).lock(|adc0, adc1, dac0, dac1| {
                     let adc_samples = [adc0, adc1];
@@ -327,24 +1188,106 @@ mod app {
                     fence(Ordering::SeqCst);
 
                     for channel in 0..adc_samples.len() {
+                        // Note(as): The conversion saturates limits beyond the DAC range.
+                        let [min, max] =
+                            settings.output_limit[channel].map(|limit| {
+                                (limit * DacCode::LSB_PER_VOLT) as i16
+                            });
+                        let clamps = &mut telemetry.output_clamps[channel];
+                        let safe = (settings.interlock.safe_output[channel]
+                            * DacCode::LSB_PER_VOLT)
+                            as i16;
+                        // Note(as): The conversion saturates limits beyond the DAC range.
+                        let slew =
+                            (settings.slew_limit[channel] > 0.).then(|| {
+                                ((settings.slew_limit[channel]
+                                    * DacCode::LSB_PER_VOLT)
+                                    as i16)
+                                    .max(1)
+                            });
+                        let previous = &mut previous_output[channel];
+                        let gate_open = match settings.signal_gate[channel] {
+                            SignalGate::Ungated => true,
+                            SignalGate::DI0 => digital_inputs[0],
+                            SignalGate::DI1 => digital_inputs[1],
+                        };
+
                         adc_samples[channel]
                             .iter()
                             .zip(dac_samples[channel].iter_mut())
                             .zip(&mut signal_generator[channel])
                             .map(|((ai, di), signal)| {
-                                let x = f32::from(*ai as i16);
-                                let y = settings.iir_ch[channel]
-                                    .iter()
-                                    .zip(iir_state[channel].iter_mut())
-                                    .fold(x, |yi, (ch, state)| {
-                                        ch.update(state, yi, hold)
-                                    });
+                                let y = if settings.bypass[channel] {
+                                    // Pass the ADC code through with unity gain.
+                                    *ai as i16
+                                } else {
+                                    let decimator = &mut decimators[channel];
+                                    decimator.sum += *ai as i16 as i32;
+                                    decimator.count += 1;
+
+                                    // Update the filter once the requested number of samples has
+                                    // been accumulated and hold the output otherwise.
+                                    if decimator.count >> settings.decimation
+                                        != 0
+                                    {
+                                        let x = decimator.sum as f32
+                                            / decimator.count as f32;
+                                        decimator.sum = 0;
+                                        decimator.count = 0;
+
+                                        decimator.output = settings.iir_ch
+                                            [channel]
+                                            .iter()
+                                            .zip(iir_state[channel].iter_mut())
+                                            .fold(x, |yi, (ch, state)| {
+                                                ch.update(state, yi, hold)
+                                            });
+                                    }
+
+                                    // The conversion saturates filter outputs beyond the DAC
+                                    // range. The truncation introduces 1/2 LSB distortion.
+                                    i16::from(DacCode::from_saturating(
+                                        decimator.output,
+                                    ))
+                                };
+
+                                // Note: The signal is generated regardless of the gate to
+                                // advance the phase.
+                                let y = if gate_open {
+                                    y.saturating_add(signal)
+                                } else {
+                                    y
+                                };
 
-                                // Note(unsafe): The filter limits must ensure that the value is in range.
-                                // The truncation introduces 1/2 LSB distortion.
-                                let y: i16 = unsafe { y.to_int_unchecked() };
+                                // Note: The negation saturates the asymmetric negative full
+                                // scale code.
+                                let y = if settings.output_invert[channel] {
+                                    y.saturating_neg()
+                                } else {
+                                    y
+                                };
 
-                                let y = y.saturating_add(signal);
+                                let y = match slew {
+                                    Some(step) => y.clamp(
+                                        previous.saturating_sub(step),
+                                        previous.saturating_add(step),
+                                    ),
+                                    None => y,
+                                };
+
+                                let limited = y.clamp(min, max);
+                                if limited != y {
+                                    *clamps = clamps.wrapping_add(1);
+                                }
+
+                                let y = if interlocked {
+                                    safe
+                                } else if spi_error {
+                                    *previous
+                                } else {
+                                    limited
+                                };
+                                *previous = y;
 
                                 // Convert to DAC code
                                 *di = DacCode::from(y).0;
@@ -355,10 +1298,14 @@ mod app {
                     // Stream the data.
                     const N: usize = BATCH_SIZE * core::mem::size_of::<i16>()
                         / core::mem::size_of::<MaybeUninit<u8>>();
-                    generator.add::<_, { N * 4 }>(|buf| {
+                    const M: usize = STREAM_CHANNELS.count_ones() as usize;
+                    let add_codes = |buf: &mut [MaybeUninit<u8>]| {
                         for (data, buf) in adc_samples
                             .iter()
                             .chain(dac_samples.iter())
+                            .enumerate()
+                            .filter(|(i, _)| STREAM_CHANNELS & (1 << i) != 0)
+                            .map(|(_, data)| data)
                             .zip(buf.chunks_exact_mut(N))
                         {
                             let data = unsafe {
@@ -369,7 +1316,72 @@ mod app {
                             };
                             buf.copy_from_slice(data)
                         }
-                    });
+                    };
+
+                    // Record a one-shot capture or stream a captured batch in place of the live
+                    // data.
+                    let captured = capture.update(
+                        settings.capture_trigger,
+                        digital_inputs,
+                        [
+                            adc_samples[0],
+                            adc_samples[1],
+                            dac_samples[0],
+                            dac_samples[1],
+                        ],
+                    );
+                    let (format, channel_mask) = if captured.is_some() {
+                        (StreamFormat::Capture, 0b1111)
+                    } else {
+                        (settings.stream_format, STREAM_CHANNELS)
+                    };
+
+                    // Apply a changed stream format on this batch boundary.
+                    if u8::from(format) != generator.format() {
+                        generator.reconfigure(
+                            format,
+                            BATCH_SIZE as _,
+                            channel_mask,
+                        );
+                    }
+                    generator.set_afe_gains(settings.afe);
+
+                    if let Some(batch) = captured {
+                        const C: usize =
+                            core::mem::size_of::<[[u16; BATCH_SIZE]; 4]>()
+                                / core::mem::size_of::<MaybeUninit<u8>>();
+                        generator.add::<_, C>(|buf| {
+                            let data = unsafe {
+                                core::slice::from_raw_parts(
+                                    batch.as_ptr() as *const MaybeUninit<u8>,
+                                    C,
+                                )
+                            };
+                            buf.copy_from_slice(data)
+                        });
+                    } else if settings.stream_format == StreamFormat::IirState {
+                        const S: usize =
+                            core::mem::size_of::<
+                                [[iir::Vec5<f32>; IIR_CASCADE_LENGTH]; 2],
+                            >() / core::mem::size_of::<MaybeUninit<u8>>();
+                        generator.add::<_, { N * M + S }>(|buf| {
+                            let (codes, state) = buf.split_at_mut(N * M);
+                            add_codes(codes);
+                            let data = unsafe {
+                                core::slice::from_raw_parts(
+                                    iir_state.as_ptr()
+                                        as *const MaybeUninit<u8>,
+                                    S,
+                                )
+                            };
+                            state.copy_from_slice(data)
+                        });
+                    } else {
+                        generator.add::<_, { N * M }>(add_codes);
+                    }
+                    telemetry.stream_dropped_frames =
+                        generator.dropped_frames();
+
                     // Update telemetry measurements.
                     telemetry.adcs = [
                         AdcCode(adc_samples[0][0]),
@@ -384,14 +1396,55 @@ mod app {
                     // Preserve instruction and data ordering w.r.t. DMA flag access.
                     fence(Ordering::SeqCst);
                 });
+
+                #[cfg(feature = "profile")]
+                {
+                    let cycles_per_tick = hardware::design_parameters::SYSCLK
+                        .to_Hz()
+                        / hardware::design_parameters::TIMER_FREQUENCY.to_Hz();
+                    telemetry.processing_time.record(
+                        cortex_m::peripheral::DWT::cycle_count()
+                            .wrapping_sub(start),
+                        settings.sample_ticks
+                            * BATCH_SIZE as u32
+                            * cycles_per_tick,
+                    );
+                }
             },
         );
     }
 
-    #[idle(shared=[network])]
+    #[idle(shared=[network, settings])]
     fn idle(mut c: idle::Context) -> ! {
+        let mut last_connected = monotonics::now();
+
         loop {
-            match c.shared.network.lock(|net| net.update()) {
+            let (state, connected) = c
+                .shared
+                .network
+                .lock(|net| (net.update(), net.telemetry.is_connected()));
+
+            // Trip the interlock once the broker connection has been lost for longer than the
+            // configured timeout.
+            let now = monotonics::now();
+            if connected {
+                last_connected = now;
+            }
+            let timeout = c
+                .shared
+                .settings
+                .lock(|settings| settings.interlock.timeout);
+            let tripped = timeout != 0
+                && now
+                    .checked_duration_since(last_connected)
+                    .map_or(false, |elapsed| {
+                        elapsed.to_secs() >= timeout as u64
+                    });
+            if tripped != INTERLOCK_TRIPPED.swap(tripped, Ordering::Relaxed) {
+                logging::warn!("Interlock tripped: {}", tripped);
+            }
+
+            match state {
                 NetworkState::SettingsChanged(_path) => {
                     settings_update::spawn().unwrap()
                 }
@@ -401,55 +1454,452 @@ mod app {
         }
     }
 
-    #[task(priority = 1, local=[afes], shared=[network, settings, signal_generator])]
+    #[task(priority = 1, shared=[network, settings, signal_generator, telemetry, afes, pounder, sampling_timer, shadow_sampling_timer], local=[pounder_absent_warned, pounder_configured])]
     fn settings_update(mut c: settings_update::Context) {
-        let settings = c.shared.network.lock(|net| *net.miniconf.settings());
+        let mut settings =
+            c.shared.network.lock(|net| *net.miniconf.settings());
+
+        let previous = c.shared.settings.lock(|current| *current);
+
+        // Cleared if any value of the update is rejected.
+        let mut valid = true;
+
+        if settings.decimation > MAX_DECIMATION {
+            logging::error!(
+                "Decimation {} exceeds the maximum of {}",
+                settings.decimation,
+                MAX_DECIMATION
+            );
+            settings.decimation = MAX_DECIMATION;
+            valid = false;
+        }
+
+        for (i, limit) in settings.output_limit.iter_mut().enumerate() {
+            if limit[0].is_nan() || limit[1].is_nan() || limit[0] > limit[1] {
+                logging::error!(
+                    "Invalid output limits on channel {}: {:?}",
+                    i,
+                    limit
+                );
+                *limit = previous.output_limit[i];
+                valid = false;
+            }
+        }
+
+        for (i, limit) in settings.slew_limit.iter_mut().enumerate() {
+            if limit.is_nan() || *limit < 0. {
+                logging::error!(
+                    "Invalid slew rate limit on channel {}: {}",
+                    i,
+                    limit
+                );
+                *limit = previous.slew_limit[i];
+                valid = false;
+            }
+        }
+
+        if !matches!(
+            settings.stream_format,
+            StreamFormat::AdcDacData | StreamFormat::IirState
+        ) {
+            logging::error!(
+                "Unsupported stream format: {:?}",
+                settings.stream_format
+            );
+            settings.stream_format = previous.stream_format;
+            valid = false;
+        }
+
+        if settings.static_ip.prefix_length > 32 {
+            logging::error!(
+                "Invalid static IP prefix length: {}",
+                settings.static_ip.prefix_length
+            );
+            settings.static_ip = previous.static_ip;
+            valid = false;
+        }
+
+        // Validate the complete Pounder configuration before any of it is applied.
+        if settings.pounder.validate().is_err() {
+            logging::error!("Rejected Pounder configuration");
+            settings.pounder = previous.pounder;
+            valid = false;
+        } else if settings.pounder.clock != DdsClockConfig::default() {
+            logging::error!("The DDS clock can not be changed at run-time");
+            settings.pounder = previous.pounder;
+            valid = false;
+        }
+
+        // Reject sample periods the DSP processing cannot keep up with or that the sampling
+        // timers and the RTIC monotonic cannot represent.
+        let batch_period = sample_period(settings.sample_ticks)
+            * BATCH_SIZE as f32
+            * hardware::MONOTONIC_FREQUENCY as f32;
+        if settings.sample_ticks < SAMPLE_TICKS
+            || settings.sample_ticks > MAX_SAMPLE_TICKS
+            || batch_period >= 1.
+        {
+            logging::error!(
+                "Invalid sample period of {} ticks",
+                settings.sample_ticks
+            );
+            settings.sample_ticks = previous.sample_ticks;
+            valid = false;
+        }
+
+        // Convert the PID parameters and filter designs into filter coefficients. On failure, the
+        // previous filter is retained.
+        let filter_period = sample_period(settings.sample_ticks)
+            * (1u32 << settings.decimation) as f32;
+        for (i, pid) in settings.pid_ch.iter().enumerate() {
+            if let FilterMode::Pid = settings.filter_mode[i] {
+                settings.iir_ch[i][0] =
+                    pid.try_into_iir(filter_period).unwrap_or_else(|err| {
+                        logging::error!(
+                            "Failed to configure PID on channel {}: {:?}",
+                            i,
+                            err
+                        );
+                        valid = false;
+                        previous.iir_ch[i][0]
+                    });
+            }
+        }
+        for (i, design) in settings.filter_design.iter().enumerate() {
+            if let FilterMode::Design = settings.filter_mode[i] {
+                settings.iir_ch[i][0] =
+                    design.try_into_iir(filter_period).unwrap_or_else(|err| {
+                        logging::error!(
+                            "Failed to design filter on channel {}: {:?}",
+                            i,
+                            err
+                        );
+                        valid = false;
+                        previous.iir_ch[i][0]
+                    });
+            }
+        }
+
         c.shared.settings.lock(|current| *current = settings);
 
-        c.local.afes.0.set_gain(settings.afe[0]);
-        c.local.afes.1.set_gain(settings.afe[1]);
+        // Note: The sampling timers are only reprogrammed once the period changes, such that
+        // sampling is not started before the `start` task.
+        (
+            &mut c.shared.sampling_timer,
+            &mut c.shared.shadow_sampling_timer,
+        )
+            .lock(|timer, shadow| {
+                if timer.get_period() + 1 != settings.sample_ticks {
+                    if let Err(err) =
+                        timer.set_period(settings.sample_ticks, shadow)
+                    {
+                        logging::error!(
+                            "Failed to set the sample period: {:?}",
+                            err
+                        );
+                    }
+                }
+            });
+
+        c.shared.afes.lock(|afes| {
+            afes.0.set_gain(settings.afe[0]);
+            afes.1.set_gain(settings.afe[1]);
+        });
 
-        // Update the signal generators
+        // Update the signal generators. Only changed configurations are applied as applying a
+        // configuration resets the signal phase.
         for (i, &config) in settings.signal_generator.iter().enumerate() {
-            match config.try_into_config(SAMPLE_PERIOD, DacCode::FULL_SCALE) {
+            if config == previous.signal_generator[i]
+                && settings.sample_ticks == previous.sample_ticks
+            {
+                continue;
+            }
+
+            match config.try_into_config(
+                sample_period(settings.sample_ticks),
+                DacCode::FULL_SCALE,
+            ) {
                 Ok(config) => {
                     c.shared
                         .signal_generator
                         .lock(|generator| generator[i].update_waveform(config));
                 }
-                Err(err) => log::error!(
-                    "Failed to update signal generation on DAC{}: {:?}",
-                    i,
-                    err
-                ),
+                Err(err) => {
+                    logging::error!(
+                        "Failed to update signal generation on DAC{}: {:?}",
+                        i,
+                        err
+                    );
+                    valid = false;
+                }
             }
         }
 
+        c.shared
+            .telemetry
+            .lock(|telemetry| telemetry.settings_valid = valid);
+
+        let failed = !valid && settings.fail_safe_on_error;
+        if failed != SETTINGS_FAILED.swap(failed, Ordering::Relaxed) {
+            logging::warn!("Settings fail-safe engaged: {}", failed);
+        }
+
+        SPI_ERROR_HOLD.store(
+            matches!(settings.spi_error_policy, SpiErrorPolicy::HoldAndLog),
+            Ordering::Relaxed,
+        );
+
         let target = settings.stream_target.into();
-        c.shared.network.lock(|net| net.direct_stream(target));
+        c.shared.network.lock(|net| {
+            net.direct_stream(target);
+            net.set_stream_enabled(settings.stream_enabled);
+            net.processor.set_static_ip(settings.static_ip);
+            if settings.sample_ticks != previous.sample_ticks {
+                net.telemetry.set_meta_sample_period(sample_period(
+                    settings.sample_ticks,
+                ));
+            }
+        });
+
+        // Arm a one-shot capture on a rising edge of the request.
+        if settings.capture_arm && !previous.capture_arm {
+            CAPTURE_REQUESTED.store(true, Ordering::Relaxed);
+        }
+
+        // Pounder settings have no effect without Pounder. Make this explicit instead of silently
+        // ignoring them.
+        let pounder_settings_changed = settings.dds_self_test
+            != previous.dds_self_test
+            || settings.dds_reset != previous.dds_reset
+            || settings.pounder_derating != previous.pounder_derating
+            || settings.pounder_power_samples != previous.pounder_power_samples
+            || settings.pounder != previous.pounder;
+        if pounder_settings_changed
+            && !*c.local.pounder_absent_warned
+            && c.shared.pounder.lock(|pounder| pounder.is_none())
+        {
+            logging::warn!(
+                "Pounder settings received, but no Pounder is present"
+            );
+            *c.local.pounder_absent_warned = true;
+        }
+
+        // Run the DDS self-test on a rising edge of the request. This is done from the settings
+        // task as the test temporarily halts the DDS profile stream.
+        if settings.dds_self_test && !previous.dds_self_test {
+            let passed = c.shared.pounder.lock(|pounder| {
+                pounder.as_mut().map(|pounder| {
+                    pounder.dds_output.self_test().unwrap_or_else(|err| {
+                        logging::error!(
+                            "Failed to run DDS self-test: {:?}",
+                            err
+                        );
+                        false
+                    })
+                })
+            });
+            if let Some(passed) = passed {
+                logging::info!("DDS self-test passed: {}", passed);
+                c.shared
+                    .telemetry
+                    .lock(|telemetry| telemetry.dds_self_test = Some(passed));
+            }
+        }
+
+        // Reset the DDS on a rising edge of the request.
+        if settings.dds_reset && !previous.dds_reset {
+            c.shared.pounder.lock(|pounder| {
+                if let Some(pounder) = pounder.as_mut() {
+                    match pounder.dds_output.reset() {
+                        Ok(()) => logging::info!("DDS reset"),
+                        Err(err) => {
+                            logging::error!("Failed to reset DDS: {:?}", err)
+                        }
+                    }
+                }
+            });
+
+            // The reset clears the channel profiles.
+            *c.local.pounder_configured = false;
+        }
+
+        // Apply the Pounder configuration initially, once it changed and after a DDS reset.
+        if settings.pounder != previous.pounder || !*c.local.pounder_configured
+        {
+            c.shared.pounder.lock(|pounder| {
+                if let Some(pounder) = pounder.as_mut() {
+                    if let Err(err) =
+                        configure_pounder(pounder, &settings.pounder)
+                    {
+                        logging::error!(
+                            "Failed to configure Pounder: {:?}",
+                            err
+                        );
+                    }
+                }
+            });
+            *c.local.pounder_configured = true;
+        }
+
+        // Reboot on a rising edge of the request. The reboot is delayed to allow the network
+        // stack to transmit the response to the settings change.
+        if settings.reboot && !previous.reboot {
+            logging::info!("Rebooting");
+            // A reboot requested again within the delay is already pending.
+            if reboot::spawn_after(1.secs()).is_err() {
+                logging::warn!("Reboot already scheduled");
+            }
+        }
     }
 
-    #[task(priority = 1, shared=[network, settings, telemetry], local=[cpu_temp_sensor])]
+    #[task(priority = 1, shared=[network, settings, telemetry, afes, pounder], local=[cpu_temp_sensor, telemetry_elapsed])]
     fn telemetry(mut c: telemetry::Context) {
-        let telemetry: TelemetryBuffer =
-            c.shared.telemetry.lock(|telemetry| *telemetry);
+        let (telemetry_period, topic_periods, sample_ticks, power_samples) =
+            c.shared.settings.lock(|settings| {
+                (
+                    settings.telemetry_period,
+                    settings.telemetry_topic_period,
+                    settings.sample_ticks,
+                    settings.pounder_power_samples,
+                )
+            });
 
-        let (gains, telemetry_period) = c
-            .shared
-            .settings
-            .lock(|settings| (settings.afe, settings.telemetry_period));
+        // The task runs once per second. Telemetry is published whenever the elapsed time is a
+        // multiple of the respective period.
+        let elapsed = *c.local.telemetry_elapsed;
+        *c.local.telemetry_elapsed = elapsed.wrapping_add(1);
+        let due = |period: u16| period != 0 && elapsed % period as u32 == 0;
 
-        c.shared.network.lock(|net| {
-            net.telemetry.publish(&telemetry.finalize(
-                gains[0],
-                gains[1],
-                c.local.cpu_temp_sensor.get_temperature().unwrap(),
-            ))
-        });
+        let publish_combined = due(telemetry_period);
+        let publish_adc = due(topic_periods.adc);
+        let publish_pounder = due(topic_periods.pounder);
+        let publish_temperature = due(topic_periods.temperature);
+        let publish_status = due(topic_periods.status);
+
+        if publish_combined
+            || publish_adc
+            || publish_pounder
+            || publish_temperature
+            || publish_status
+        {
+            let mut telemetry: TelemetryBuffer =
+                c.shared.telemetry.lock(|telemetry| *telemetry);
+            for (count, errors) in
+                telemetry.spi_errors.iter_mut().zip(SPI_ERRORS.iter())
+            {
+                *count = errors.load(Ordering::Relaxed);
+            }
+
+            // Scale the inputs using the gains applied to the AFEs.
+            let gains = c
+                .shared
+                .afes
+                .lock(|afes| [afes.0.get_gain(), afes.1.get_gain()]);
+
+            let pounder = c.shared.pounder.lock(|pounder| {
+                pounder.as_mut().map(|pounder| {
+                    pounder.pounder.get_telemetry(
+                        pounder.dds_output.clock_config(),
+                        power_samples,
+                    )
+                })
+            });
+
+            c.shared.network.lock(|net| {
+                let link = net.processor.link_status();
+                if publish_status {
+                    net.telemetry
+                        .publish_topic("status", &telemetry.status(link));
+                }
+
+                let telemetry = telemetry.finalize(
+                    gains[0],
+                    gains[1],
+                    c.local.cpu_temp_sensor.get_temperature().unwrap(),
+                    link,
+                    pounder,
+                    sample_period(sample_ticks),
+                    net.settings_version().clone(),
+                    monotonics::now().duration_since_epoch().to_secs(),
+                    net.stream_config(),
+                );
+
+                if publish_combined {
+                    net.telemetry.publish(&telemetry);
+                }
+                if publish_adc {
+                    net.telemetry.publish_topic("adc", &telemetry.adcs);
+                }
+                if publish_pounder {
+                    net.telemetry.publish_topic("pounder", &telemetry.pounder);
+                }
+                if publish_temperature {
+                    net.telemetry
+                        .publish_topic("temperature", &telemetry.cpu_temp);
+                }
+            });
+        }
 
         // Schedule the telemetry task in the future.
-        telemetry::Monotonic::spawn_after((telemetry_period as u64).secs())
-            .unwrap();
+        telemetry::Monotonic::spawn_after(1.secs()).unwrap();
+    }
+
+    #[task(priority = 1, shared=[settings, telemetry, pounder])]
+    fn pounder_derating(mut c: pounder_derating::Context) {
+        // Pounder is only detected at boot, so sampling stops if it is not present.
+        if c.shared.pounder.lock(|pounder| pounder.is_none()) {
+            return;
+        }
+
+        let (derating, telemetry_period) = c.shared.settings.lock(|settings| {
+            (settings.pounder_derating, settings.telemetry_period)
+        });
+
+        let throttled = c.shared.pounder.lock(|pounder| {
+            let pounder = &mut pounder.as_mut()?.pounder;
+
+            let temperature = pounder
+                .read_temperature()
+                .map_err(|err| {
+                    logging::warn!(
+                        "Failed to read Pounder temperature: {:?}",
+                        err
+                    )
+                })
+                .ok()?;
+
+            if !(derating.step > 0.0 && temperature > derating.threshold) {
+                return Some(false);
+            }
+
+            for channel in [Channel::Out0, Channel::Out1] {
+                let attenuation = pounder.cached_attenuations()
+                    [channel as usize]
+                    + derating.step;
+                if let Err(err) =
+                    pounder.set_attenuation_clamped(channel, attenuation)
+                {
+                    logging::error!(
+                        "Failed to derate {:?} attenuation: {:?}",
+                        channel,
+                        err
+                    );
+                }
+            }
+
+            Some(true)
+        });
+
+        if let Some(throttled) = throttled {
+            c.shared
+                .telemetry
+                .lock(|telemetry| telemetry.pounder_throttled = throttled);
+        }
+
+        pounder_derating::Monotonic::spawn_after(
+            (telemetry_period.max(1) as u64).secs(),
+        )
+        .unwrap();
     }
 
     #[task(priority = 1, shared=[network])]
@@ -458,6 +1908,17 @@ mod app {
         ethernet_link::Monotonic::spawn_after(1.secs()).unwrap();
     }
 
+    #[task(priority = 1)]
+    fn release_outputs(_: release_outputs::Context) {
+        logging::info!("Settings hold elapsed, releasing outputs");
+        SETTINGS_PENDING.store(false, Ordering::Relaxed);
+    }
+
+    #[task(priority = 1)]
+    fn reboot(_: reboot::Context) {
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+
     #[task(binds = ETH, priority = 1)]
     fn eth(_: eth::Context) {
         unsafe { hal::ethernet::interrupt_handler() }
@@ -465,21 +1926,21 @@ mod app {
 
     #[task(binds = SPI2, priority = 4)]
     fn spi2(_: spi2::Context) {
-        panic!("ADC0 SPI error");
+        spi_error(0, Adc0Input::clear_errors);
     }
 
     #[task(binds = SPI3, priority = 4)]
     fn spi3(_: spi3::Context) {
-        panic!("ADC1 SPI error");
+        spi_error(1, Adc1Input::clear_errors);
     }
 
     #[task(binds = SPI4, priority = 4)]
     fn spi4(_: spi4::Context) {
-        panic!("DAC0 SPI error");
+        spi_error(2, Dac0Output::clear_errors);
     }
 
     #[task(binds = SPI5, priority = 4)]
     fn spi5(_: spi5::Context) {
-        panic!("DAC1 SPI error");
+        spi_error(3, Dac1Output::clear_errors);
     }
 }
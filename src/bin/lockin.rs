@@ -22,7 +22,8 @@
 //! Refer to [Telemetry] for information about telemetry reported by this application.
 //!
 //! ## Livestreaming
-//! This application streams raw ADC and DAC data over UDP. Refer to
+//! This application streams raw ADC and DAC data or the demodulated in-phase and quadrature
+//! components over UDP, see the `stream_format` setting. Refer to
 //! [stabilizer::net::data_stream](../stabilizer/net/data_stream/index.html) for more information.
 #![deny(warnings)]
 #![no_std]
@@ -51,12 +52,14 @@ use stabilizer::{
         timers::SamplingTimer,
         DigitalInput0, DigitalInput1, SystemTimer, Systick, AFE0, AFE1,
     },
+    logging,
     net::{
         data_stream::{FrameGenerator, StreamFormat, StreamTarget},
         miniconf::Miniconf,
+        network_processor::StaticIpConfig,
         serde::{Deserialize, Serialize},
-        telemetry::{Telemetry, TelemetryBuffer},
-        NetworkState, NetworkUsers,
+        telemetry::{Meta, Telemetry, TelemetryBuffer},
+        NetworkState, NetworkUsers, MQTT_KEEPALIVE,
     },
 };
 
@@ -70,6 +73,11 @@ const BATCH_SIZE: usize = 1 << BATCH_SIZE_LOG2;
 // period of 1.28 uS or 781.25 KHz.
 const SAMPLE_TICKS_LOG2: u32 = 7;
 const SAMPLE_TICKS: u32 = 1 << SAMPLE_TICKS_LOG2;
+const SAMPLE_PERIOD: f32 =
+    SAMPLE_TICKS as f32 * hardware::design_parameters::TIMER_PERIOD;
+
+// The DAC0/DAC1 output voltages from boot until the first lockin outputs are generated.
+const STARTUP_OUTPUT: [f32; 2] = [0.0, 0.0];
 
 #[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
 enum Conf {
@@ -186,6 +194,70 @@ pub struct Settings {
     /// # Value
     /// See [StreamTarget#miniconf]
     stream_target: StreamTarget,
+
+    /// Specifies whether the data livestream is transmitted.
+    ///
+    /// # Path
+    /// `stream_enabled`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// While disabled, stream data is discarded. The `stream_target` is retained, so streaming
+    /// resumes immediately once enabled again.
+    stream_enabled: bool,
+
+    /// Specifies the format of the data livestream.
+    ///
+    /// # Path
+    /// `stream_format`
+    ///
+    /// # Value
+    /// `"AdcDacData"` or `"IqData"`. See [StreamFormat].
+    ///
+    /// # Note
+    /// With `"IqData"`, the demodulated ADC0 signal is streamed as one I/Q pair per batch, in ADC
+    /// codes. The format is changed on the next batch boundary.
+    stream_format: StreamFormat,
+
+    /// Specifies a static IP configuration to apply at run-time.
+    ///
+    /// # Path
+    /// `static_ip`
+    ///
+    /// # Value
+    /// See [StaticIpConfig#miniconf]
+    static_ip: StaticIpConfig,
+
+    /// Specified true to restore the default settings.
+    ///
+    /// # Path
+    /// `reset_to_default`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// All settings, including this one, are reset to their defaults and republished. The request
+    /// should not be retained on the broker, as it would otherwise be applied again whenever
+    /// Stabilizer reconnects. Similarly, any other settings retained on the broker are applied
+    /// again on the next connection.
+    reset_to_default: bool,
+
+    /// Specified true to reboot the device.
+    ///
+    /// # Path
+    /// `reboot`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// The device reboots shortly after the request, such that the response to the settings
+    /// change is transmitted to the broker first. The request must not be retained on the
+    /// broker, as it would otherwise be applied again after every reboot.
+    reboot: bool,
 }
 
 impl Default for Settings {
@@ -206,6 +278,14 @@ impl Default for Settings {
             telemetry_period: 10,
 
             stream_target: StreamTarget::default(),
+            stream_enabled: true,
+            stream_format: StreamFormat::AdcDacData,
+
+            // Keep the boot-time network configuration.
+            static_ip: StaticIpConfig::default(),
+
+            reset_to_default: false,
+            reboot: false,
         }
     }
 }
@@ -222,6 +302,7 @@ mod app {
         network: NetworkUsers<Settings, Telemetry>,
         settings: Settings,
         telemetry: TelemetryBuffer,
+        afes: (AFE0, AFE1),
     }
 
     #[local]
@@ -229,7 +310,6 @@ mod app {
         sampling_timer: SamplingTimer,
         digital_inputs: (DigitalInput0, DigitalInput1),
         timestamper: InputStamper,
-        afes: (AFE0, AFE1),
         adcs: (Adc0Input, Adc1Input),
         dacs: (Dac0Output, Dac1Output),
         pll: RPLL,
@@ -244,17 +324,19 @@ mod app {
         let clock = SystemTimer::new(|| monotonics::now().ticks() as u32);
 
         // Configure the microcontroller
-        let (mut stabilizer, _pounder) = hardware::setup::setup(
+        let (mut stabilizer, pounder) = hardware::setup::setup(
             c.core,
             c.device,
             clock,
             BATCH_SIZE,
             SAMPLE_TICKS,
+            STARTUP_OUTPUT,
         );
 
         let mut network = NetworkUsers::new(
             stabilizer.net.stack,
             stabilizer.net.phy,
+            stabilizer.net.crc,
             clock,
             env!("CARGO_BIN_NAME"),
             stabilizer.net.mac_address,
@@ -262,14 +344,27 @@ mod app {
                 .unwrap_or("10.34.16.10")
                 .parse()
                 .unwrap(),
+            MQTT_KEEPALIVE,
         );
 
-        let generator = network
-            .configure_streaming(StreamFormat::AdcDacData, BATCH_SIZE as _);
+        network.telemetry.set_meta(Meta::new(
+            env!("CARGO_BIN_NAME"),
+            stabilizer.net.mac_address,
+            pounder.is_some(),
+            BATCH_SIZE,
+            SAMPLE_PERIOD,
+        ));
+
+        let generator = network.configure_streaming(
+            StreamFormat::AdcDacData,
+            BATCH_SIZE as _,
+            0b1111,
+        );
 
         let shared = Shared {
             network,
             telemetry: TelemetryBuffer::default(),
+            afes: stabilizer.afes,
             settings: Settings::default(),
         };
 
@@ -280,12 +375,12 @@ mod app {
             amplitude: DacCode::try_from(1.0).unwrap().into(),
             signal: signal_generator::Signal::Cosine,
             phase_offset: 0,
+            initial_phase: 0,
         };
 
         let mut local = Local {
             sampling_timer: stabilizer.adc_dac_timer,
             digital_inputs: stabilizer.digital_inputs,
-            afes: stabilizer.afes,
             adcs: stabilizer.adcs,
             dacs: stabilizer.dacs,
             timestamper: stabilizer.timestamper,
@@ -422,24 +517,61 @@ mod app {
                     }
                 }
 
-                // Stream the data.
-                const N: usize = BATCH_SIZE * core::mem::size_of::<i16>()
-                    / core::mem::size_of::<MaybeUninit<u8>>();
-                generator.add::<_, { N * 4 }>(|buf| {
-                    for (data, buf) in adc_samples
-                        .iter()
-                        .chain(dac_samples.iter())
-                        .zip(buf.chunks_exact_mut(N))
-                    {
-                        let data = unsafe {
-                            core::slice::from_raw_parts(
-                                data.as_ptr() as *const MaybeUninit<u8>,
-                                N,
-                            )
+                // Apply a changed stream format on this batch boundary.
+                if u8::from(settings.stream_format) != generator.format() {
+                    let (batch_size, channel_mask) =
+                        match settings.stream_format {
+                            StreamFormat::IqData => (1, 0b1),
+                            _ => (BATCH_SIZE as _, 0b1111),
                         };
-                        buf.copy_from_slice(data)
-                    }
-                });
+                    generator.reconfigure(
+                        settings.stream_format,
+                        batch_size,
+                        channel_mask,
+                    );
+                }
+                generator.set_afe_gains(settings.afe);
+
+                // Stream the data.
+                if settings.stream_format == StreamFormat::IqData {
+                    // The demodulated output is scaled to ADC codes.
+                    let iq = [
+                        output.re as f32 / (1 << 16) as f32,
+                        output.im as f32 / (1 << 16) as f32,
+                    ];
+                    const Q: usize = core::mem::size_of::<[f32; 2]>()
+                        / core::mem::size_of::<MaybeUninit<u8>>();
+                    generator.add::<_, Q>(|buf| {
+                        for (data, buf) in
+                            iq.iter().zip(buf.chunks_exact_mut(4))
+                        {
+                            for (byte, value) in
+                                buf.iter_mut().zip(data.to_le_bytes())
+                            {
+                                byte.write(value);
+                            }
+                        }
+                    });
+                } else {
+                    const N: usize = BATCH_SIZE * core::mem::size_of::<i16>()
+                        / core::mem::size_of::<MaybeUninit<u8>>();
+                    generator.add::<_, { N * 4 }>(|buf| {
+                        for (data, buf) in adc_samples
+                            .iter()
+                            .chain(dac_samples.iter())
+                            .zip(buf.chunks_exact_mut(N))
+                        {
+                            let data = unsafe {
+                                core::slice::from_raw_parts(
+                                    data.as_ptr() as *const MaybeUninit<u8>,
+                                    N,
+                                )
+                            };
+                            buf.copy_from_slice(data)
+                        }
+                    });
+                }
+                telemetry.stream_dropped_frames = generator.dropped_frames();
 
                 // Update telemetry measurements.
                 telemetry.adcs =
@@ -467,19 +599,57 @@ mod app {
         }
     }
 
-    #[task(priority = 1, local=[afes], shared=[network, settings])]
+    #[task(priority = 1, shared=[network, settings, afes])]
     fn settings_update(mut c: settings_update::Context) {
-        let settings = c.shared.network.lock(|net| *net.miniconf.settings());
+        let mut settings =
+            c.shared.network.lock(|net| *net.miniconf.settings());
+        let previous = c.shared.settings.lock(|current| *current);
+
+        if !matches!(
+            settings.stream_format,
+            StreamFormat::AdcDacData | StreamFormat::IqData
+        ) {
+            logging::error!(
+                "Unsupported stream format: {:?}",
+                settings.stream_format
+            );
+            settings.stream_format = previous.stream_format;
+        }
+
+        if settings.static_ip.prefix_length > 32 {
+            logging::error!(
+                "Invalid static IP prefix length: {}",
+                settings.static_ip.prefix_length
+            );
+            settings.static_ip = previous.static_ip;
+        }
+
         c.shared.settings.lock(|current| *current = settings);
 
-        c.local.afes.0.set_gain(settings.afe[0]);
-        c.local.afes.1.set_gain(settings.afe[1]);
+        // Reboot on a rising edge of the request. The reboot is delayed to allow the network
+        // stack to transmit the response to the settings change.
+        if settings.reboot && !previous.reboot {
+            logging::info!("Rebooting");
+            // A reboot requested again within the delay is already pending.
+            if reboot::spawn_after(1.secs()).is_err() {
+                logging::warn!("Reboot already scheduled");
+            }
+        }
+
+        c.shared.afes.lock(|afes| {
+            afes.0.set_gain(settings.afe[0]);
+            afes.1.set_gain(settings.afe[1]);
+        });
 
         let target = settings.stream_target.into();
-        c.shared.network.lock(|net| net.direct_stream(target));
+        c.shared.network.lock(|net| {
+            net.direct_stream(target);
+            net.set_stream_enabled(settings.stream_enabled);
+            net.processor.set_static_ip(settings.static_ip);
+        });
     }
 
-    #[task(priority = 1, local=[digital_inputs, cpu_temp_sensor], shared=[network, settings, telemetry])]
+    #[task(priority = 1, local=[digital_inputs, cpu_temp_sensor], shared=[network, settings, telemetry, afes])]
     fn telemetry(mut c: telemetry::Context) {
         let mut telemetry: TelemetryBuffer =
             c.shared.telemetry.lock(|telemetry| *telemetry);
@@ -489,16 +659,26 @@ mod app {
             c.local.digital_inputs.1.is_high(),
         ];
 
-        let (gains, telemetry_period) = c
+        let telemetry_period =
+            c.shared.settings.lock(|settings| settings.telemetry_period);
+
+        // Scale the inputs using the gains applied to the AFEs.
+        let gains = c
             .shared
-            .settings
-            .lock(|settings| (settings.afe, settings.telemetry_period));
+            .afes
+            .lock(|afes| [afes.0.get_gain(), afes.1.get_gain()]);
 
         c.shared.network.lock(|net| {
             net.telemetry.publish(&telemetry.finalize(
                 gains[0],
                 gains[1],
                 c.local.cpu_temp_sensor.get_temperature().unwrap(),
+                net.processor.link_status(),
+                None,
+                SAMPLE_PERIOD,
+                net.settings_version().clone(),
+                monotonics::now().duration_since_epoch().to_secs(),
+                net.stream_config(),
             ))
         });
 
@@ -513,6 +693,11 @@ mod app {
         ethernet_link::Monotonic::spawn_after(1.secs()).unwrap();
     }
 
+    #[task(priority = 1)]
+    fn reboot(_: reboot::Context) {
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+
     #[task(binds = ETH, priority = 1)]
     fn eth(_: eth::Context) {
         unsafe { hal::ethernet::interrupt_handler() }
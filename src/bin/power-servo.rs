@@ -0,0 +1,539 @@
+//! # Power Servo
+//!
+//! The `power-servo` application stabilizes the RF power of the Pounder outputs OUT0/OUT1 to a
+//! setpoint in dBm.
+//!
+//! ## Features
+//! * Two independent servo channels
+//! * Power measurement using the Pounder RF power detectors
+//! * Coarse actuation using the 0.5 dB step output attenuators
+//! * Fine actuation using the DDS amplitude scale factor
+//! * Run-time configurable IIR loop filter with anti-windup
+//!
+//! ## Operation
+//! Pounder measures RF power only on its inputs. The output OUT0 (OUT1) is stabilized using the
+//! power measured on the input IN0 (IN1). A fraction of the output power must thus be coupled back
+//! into the respective input, e.g. using a directional coupler or a power splitter.
+//!
+//! Every [SERVO_PERIOD_MS] milliseconds, the measured power is compared to the setpoint. The error
+//! in dB is filtered by the loop filter, whose output is the total output attenuation in dB. The
+//! attenuation is split into a multiple of the 0.5 dB attenuator step and a remainder that is
+//! applied as a reduced DDS amplitude.
+//!
+//! This application requires Pounder. The ADC and DAC channels of Stabilizer are not used.
+//!
+//! ## Settings
+//! Refer to the [Settings] structure for documentation of run-time configurable settings for this
+//! application.
+//!
+//! ## Telemetry
+//! Refer to [Telemetry] for information about telemetry reported by this application.
+#![deny(warnings)]
+#![no_std]
+#![no_main]
+
+use fugit::ExtU64;
+
+use idsp::iir;
+
+use stabilizer::{
+    hardware::{
+        self,
+        design_parameters::DDS_SYSTEM_CLK,
+        hal,
+        pounder::{
+            attenuators::AttenuatorInterface,
+            rf_power::PowerMeasurementInterface, Channel, PounderDevices,
+        },
+        SystemTimer, Systick,
+    },
+    logging,
+    net::{
+        miniconf::Miniconf,
+        network_processor::{LinkState, StaticIpConfig},
+        serde::{Deserialize, Serialize},
+        NetworkState, NetworkUsers, MQTT_KEEPALIVE,
+    },
+};
+
+// The ADC/DAC sampling is not used by this application. The batch size and sample period only
+// configure the (idle) sampling hardware.
+const BATCH_SIZE: usize = 8;
+const SAMPLE_TICKS: u32 = 128;
+
+/// The period of the servo loop in milliseconds.
+pub const SERVO_PERIOD_MS: u64 = 10;
+
+// The Pounder channels measuring and actuating the power of each servo channel.
+const INPUTS: [Channel; 2] = [Channel::In0, Channel::In1];
+const OUTPUTS: [Channel; 2] = [Channel::Out0, Channel::Out1];
+
+// The step and range of the Pounder attenuators in dB.
+const ATTENUATION_STEP: f32 = <PounderDevices as AttenuatorInterface>::STEP_DB;
+const MAX_ATTENUATION: f32 = <PounderDevices as AttenuatorInterface>::MAX_DB;
+
+/// The configuration of a single power servo channel.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, Miniconf)]
+pub struct ServoChannel {
+    /// Specified true to servo the output power.
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// While disabled, the output is switched off using the maximum attenuation and a zero DDS
+    /// amplitude. Once enabled, the servo starts from the maximum attenuation of the loop filter
+    /// (`iir/y_max`).
+    pub enabled: bool,
+
+    /// The output power setpoint in dBm.
+    ///
+    /// # Value
+    /// Any finite value. The power is referred to the RF input connector, i.e. it includes the
+    /// input attenuation.
+    pub setpoint: f32,
+
+    /// The DDS output frequency in Hz.
+    ///
+    /// # Value
+    /// Any value between 0 and half of the DDS system clock frequency.
+    pub frequency: f32,
+
+    /// The attenuation of the RF input used for the power measurement in dB.
+    ///
+    /// # Value
+    /// Any value between 0 and 31.5. The attenuation is rounded to the nearest 0.5 dB step.
+    pub input_attenuation: f32,
+
+    /// The loop filter.
+    ///
+    /// # Value
+    /// See [iir::IIR#miniconf]
+    ///
+    /// # Note
+    /// The filter input is the measured power minus the setpoint in dB. The filter output is the
+    /// total output attenuation in dB and is clamped to `[y_min, y_max]`, where `y_min` must not
+    /// be negative. A positive gain is required for negative feedback.
+    pub iir: iir::IIR<f32>,
+}
+
+impl Default for ServoChannel {
+    fn default() -> Self {
+        // A pure integrator with a unity-gain frequency of about 1.6 Hz.
+        let mut iir = iir::IIR::new(0.1, 0.0, MAX_ATTENUATION);
+        iir.ba[3] = 1.0;
+
+        Self {
+            enabled: false,
+            setpoint: -10.0,
+            frequency: 100e6,
+            input_attenuation: 0.0,
+            iir,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Miniconf)]
+pub struct Settings {
+    /// Configure the power servo channels.
+    ///
+    /// # Path
+    /// `servo/<n>/<field>`
+    ///
+    /// * <n> specifies which channel to configure. <n> := [0, 1]
+    /// * <field> is one of the fields of [ServoChannel].
+    ///
+    /// # Value
+    /// See [ServoChannel]
+    servo: [ServoChannel; 2],
+
+    /// Specifies the telemetry output period in seconds.
+    ///
+    /// # Path
+    /// `telemetry_period`
+    ///
+    /// # Value
+    /// Any non-zero value less than 65536.
+    telemetry_period: u16,
+
+    /// Specifies a static IP configuration to apply at run-time.
+    ///
+    /// # Path
+    /// `static_ip`
+    ///
+    /// # Value
+    /// See [StaticIpConfig#miniconf]
+    static_ip: StaticIpConfig,
+
+    /// Specified true to restore the default settings.
+    ///
+    /// # Path
+    /// `reset_to_default`
+    ///
+    /// # Value
+    /// "true" or "false"
+    ///
+    /// # Note
+    /// All settings, including this one, are reset to their defaults and republished. The request
+    /// should not be retained on the broker, as it would otherwise be applied again whenever
+    /// Stabilizer reconnects. Similarly, any other settings retained on the broker are applied
+    /// again on the next connection.
+    reset_to_default: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            servo: [ServoChannel::default(); 2],
+
+            // The default telemetry period in seconds.
+            telemetry_period: 10,
+
+            // Keep the boot-time network configuration.
+            static_ip: StaticIpConfig::default(),
+
+            reset_to_default: false,
+        }
+    }
+}
+
+/// The state of a single power servo channel.
+#[derive(Serialize, Copy, Clone, Debug, Default)]
+pub struct ChannelTelemetry {
+    /// The most recent measured power in dBm referred to the RF input connector. `null` if the
+    /// servo is disabled or the measurement failed.
+    pub power: Option<f32>,
+
+    /// The output attenuation in dB.
+    pub attenuation: f32,
+
+    /// The normalized DDS output amplitude.
+    pub amplitude: f32,
+}
+
+/// Telemetry reported by the power servo.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct Telemetry {
+    /// The state of the servo channels.
+    pub channels: [ChannelTelemetry; 2],
+
+    /// The CPU temperature in degrees Celsius.
+    pub cpu_temp: f32,
+
+    /// The ethernet link state.
+    pub link: LinkState,
+}
+
+/// Split a total output attenuation into the attenuator setting and the DDS amplitude.
+///
+/// # Args
+/// * `attenuation` - The total output attenuation in dB. Must not be negative.
+///
+/// # Returns
+/// (attenuation, amplitude) where `attenuation` is the attenuator setting in dB and `amplitude` is
+/// the normalized DDS amplitude providing the remaining attenuation.
+fn split_attenuation(attenuation: f32) -> (f32, f32) {
+    // Round down to the next attenuator step such that the remainder is not negative.
+    let coarse = ((attenuation / ATTENUATION_STEP) as u32 as f32
+        * ATTENUATION_STEP)
+        .min(MAX_ATTENUATION);
+    let amplitude = libm::powf(10., (coarse - attenuation) / 20.);
+    (coarse, amplitude.min(1.))
+}
+
+#[rtic::app(device = stabilizer::hardware::hal::stm32, peripherals = true, dispatchers=[DCMI, JPEG, SDMMC])]
+mod app {
+    use super::*;
+
+    #[monotonic(binds = SysTick, default = true, priority = 2)]
+    type Monotonic = Systick;
+
+    #[shared]
+    struct Shared {
+        network: NetworkUsers<Settings, Telemetry>,
+        settings: Settings,
+        telemetry: [ChannelTelemetry; 2],
+        pounder: hardware::setup::PounderDevices,
+    }
+
+    #[local]
+    struct Local {
+        cpu_temp_sensor: stabilizer::hardware::cpu_temp_sensor::CpuTempSensor,
+        iir_state: [iir::Vec5<f32>; 2],
+        // The (attenuation, FTW, ACR) most recently applied to each output.
+        outputs: [Option<(f32, u32, u32)>; 2],
+    }
+
+    #[init]
+    fn init(c: init::Context) -> (Shared, Local, init::Monotonics) {
+        let clock = SystemTimer::new(|| monotonics::now().ticks() as u32);
+
+        // Configure the microcontroller
+        let (stabilizer, pounder) = hardware::setup::setup(
+            c.core,
+            c.device,
+            clock,
+            BATCH_SIZE,
+            SAMPLE_TICKS,
+            [0.0, 0.0],
+        );
+
+        let pounder = pounder.expect("Pounder is required");
+
+        let network = NetworkUsers::new(
+            stabilizer.net.stack,
+            stabilizer.net.phy,
+            stabilizer.net.crc,
+            clock,
+            env!("CARGO_BIN_NAME"),
+            stabilizer.net.mac_address,
+            option_env!("BROKER")
+                .unwrap_or("10.34.16.10")
+                .parse()
+                .unwrap(),
+            MQTT_KEEPALIVE,
+        );
+
+        let shared = Shared {
+            network,
+            settings: Settings::default(),
+            telemetry: [ChannelTelemetry::default(); 2],
+            pounder,
+        };
+
+        let local = Local {
+            cpu_temp_sensor: stabilizer.temperature_sensor,
+            iir_state: [[0.; 5]; 2],
+            outputs: [None; 2],
+        };
+
+        // Spawn a settings and telemetry update for default settings.
+        settings_update::spawn().unwrap();
+        telemetry::spawn().unwrap();
+        ethernet_link::spawn().unwrap();
+        servo::spawn().unwrap();
+
+        (shared, local, init::Monotonics(stabilizer.systick))
+    }
+
+    #[idle(shared=[network])]
+    fn idle(mut c: idle::Context) -> ! {
+        loop {
+            match c.shared.network.lock(|net| net.update()) {
+                NetworkState::SettingsChanged(_path) => {
+                    settings_update::spawn().unwrap()
+                }
+                NetworkState::Updated => {}
+                NetworkState::NoChange => cortex_m::asm::wfi(),
+            }
+        }
+    }
+
+    /// Periodically update the output power of all channels.
+    #[task(priority = 1, shared=[settings, telemetry, pounder], local=[iir_state, outputs])]
+    fn servo(mut c: servo::Context) {
+        let settings = c.shared.settings.lock(|settings| settings.servo);
+
+        for (i, servo) in settings.iter().enumerate() {
+            let state = &mut c.local.iir_state[i];
+
+            let (power, attenuation, amplitude) = if servo.enabled {
+                let power = c.shared.pounder.lock(|pounder| {
+                    let input_attenuation = pounder
+                        .pounder
+                        .cached_attenuations()[INPUTS[i] as usize];
+                    pounder
+                        .pounder
+                        .measure_power(INPUTS[i])
+                        .map(|power| power + input_attenuation)
+                        .map_err(|err| {
+                            logging::warn!(
+                                "Failed to measure power on channel {}: {:?}",
+                                i,
+                                err
+                            )
+                        })
+                        .ok()
+                });
+
+                // Hold the loop filter output if the power could not be measured.
+                let x = power.map_or(0., |power| power - servo.setpoint);
+                let y = servo.iir.update(state, x, power.is_none());
+                let (attenuation, amplitude) = split_attenuation(y);
+                (power, attenuation, amplitude)
+            } else {
+                // Restart from the maximum attenuation once enabled.
+                let y_max = servo.iir.y_max;
+                *state = [0., 0., y_max, y_max, 0.];
+                (None, MAX_ATTENUATION, 0.)
+            };
+
+            let output = (
+                attenuation,
+                ad9959::frequency_to_ftw(
+                    servo.frequency,
+                    DDS_SYSTEM_CLK.to_Hz() as f32,
+                ),
+                ad9959::amplitude_to_acr(amplitude),
+            );
+
+            let previous = c.local.outputs[i];
+            if previous != Some(output) {
+                let result = c.shared.pounder.lock(|pounder| {
+                    // Avoid the slow attenuator access unless the attenuation changes.
+                    if previous.map(|(attenuation, _, _)| attenuation)
+                        != Some(attenuation)
+                    {
+                        pounder
+                            .pounder
+                            .set_attenuation(OUTPUTS[i], attenuation)?;
+                    }
+                    pounder
+                        .dds_output
+                        .builder()
+                        .update_channels(
+                            OUTPUTS[i].into(),
+                            Some(output.1),
+                            None,
+                            Some(output.2),
+                        )
+                        .write()
+                });
+
+                match result {
+                    Ok(()) => c.local.outputs[i] = Some(output),
+                    Err(err) => {
+                        logging::warn!(
+                            "Failed to update output on channel {}: {:?}",
+                            i,
+                            err
+                        );
+                        c.local.outputs[i] = None;
+                    }
+                }
+            }
+
+            c.shared.telemetry.lock(|telemetry| {
+                telemetry[i] = ChannelTelemetry {
+                    power,
+                    attenuation,
+                    amplitude,
+                }
+            });
+        }
+
+        servo::Monotonic::spawn_after(SERVO_PERIOD_MS.millis()).unwrap();
+    }
+
+    #[task(priority = 1, shared=[network, settings, pounder])]
+    fn settings_update(mut c: settings_update::Context) {
+        let mut settings =
+            c.shared.network.lock(|net| *net.miniconf.settings());
+
+        let previous = c.shared.settings.lock(|current| *current);
+
+        let max_frequency = DDS_SYSTEM_CLK.to_Hz() as f32 / 2.;
+        for (i, servo) in settings.servo.iter_mut().enumerate() {
+            if !servo.setpoint.is_finite() {
+                logging::error!(
+                    "Invalid setpoint on channel {}: {}",
+                    i,
+                    servo.setpoint
+                );
+                servo.setpoint = previous.servo[i].setpoint;
+            }
+
+            if !(0.0..=max_frequency).contains(&servo.frequency) {
+                logging::error!(
+                    "Invalid frequency on channel {}: {} Hz",
+                    i,
+                    servo.frequency
+                );
+                servo.frequency = previous.servo[i].frequency;
+            }
+
+            if !(0.0..=MAX_ATTENUATION).contains(&servo.input_attenuation) {
+                logging::error!(
+                    "Invalid input attenuation on channel {}: {} dB",
+                    i,
+                    servo.input_attenuation
+                );
+                servo.input_attenuation = previous.servo[i].input_attenuation;
+            }
+
+            // The loop filter output is the total output attenuation, which can not be negative.
+            if !(servo.iir.y_min >= 0. && servo.iir.y_min <= servo.iir.y_max) {
+                logging::error!(
+                    "Invalid attenuation limits on channel {}: [{}, {}]",
+                    i,
+                    servo.iir.y_min,
+                    servo.iir.y_max
+                );
+                servo.iir = previous.servo[i].iir;
+            }
+        }
+
+        if settings.static_ip.prefix_length > 32 {
+            logging::error!(
+                "Invalid static IP prefix length: {}",
+                settings.static_ip.prefix_length
+            );
+            settings.static_ip = previous.static_ip;
+        }
+
+        c.shared.settings.lock(|current| *current = settings);
+
+        c.shared.pounder.lock(|pounder| {
+            for (i, servo) in settings.servo.iter().enumerate() {
+                pounder
+                    .pounder
+                    .set_attenuation(INPUTS[i], servo.input_attenuation)
+                    .unwrap_or_else(|err| {
+                        logging::error!(
+                            "Failed to set input attenuation on channel {}: {:?}",
+                            i,
+                            err
+                        );
+                        0.
+                    });
+            }
+        });
+
+        c.shared
+            .network
+            .lock(|net| net.processor.set_static_ip(settings.static_ip));
+    }
+
+    #[task(priority = 1, local=[cpu_temp_sensor], shared=[network, settings, telemetry])]
+    fn telemetry(mut c: telemetry::Context) {
+        let channels = c.shared.telemetry.lock(|telemetry| *telemetry);
+
+        let telemetry_period =
+            c.shared.settings.lock(|settings| settings.telemetry_period);
+
+        let cpu_temp = c.local.cpu_temp_sensor.get_temperature().unwrap();
+
+        c.shared.network.lock(|net| {
+            net.telemetry.publish(&Telemetry {
+                channels,
+                cpu_temp,
+                link: net.processor.link_status(),
+            })
+        });
+
+        // Schedule the telemetry task in the future.
+        telemetry::Monotonic::spawn_after((telemetry_period as u64).secs())
+            .unwrap();
+    }
+
+    #[task(priority = 1, shared=[network])]
+    fn ethernet_link(mut c: ethernet_link::Context) {
+        c.shared.network.lock(|net| net.processor.handle_link());
+        ethernet_link::Monotonic::spawn_after(1.secs()).unwrap();
+    }
+
+    #[task(binds = ETH, priority = 1)]
+    fn eth(_: eth::Context) {
+        unsafe { hal::ethernet::interrupt_handler() }
+    }
+}
@@ -0,0 +1,161 @@
+use idsp::iir;
+use miniconf::Miniconf;
+use serde::{Deserialize, Serialize};
+
+/// Represents the errors that can occur when converting a filter design into an IIR filter.
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+    /// The provided sample period is not positive.
+    InvalidSamplePeriod,
+    /// The frequency is not between zero and the Nyquist frequency.
+    InvalidFrequency,
+    /// The quality factor is not positive.
+    InvalidQ,
+    /// The gain is not finite.
+    InvalidGain,
+    /// The provided output limits are inverted.
+    InvalidLimits,
+}
+
+/// The frequency response of a [FilterDesign].
+#[derive(Copy, Clone, Debug, PartialEq, Miniconf, Deserialize, Serialize)]
+pub enum Response {
+    /// Second order lowpass with the cutoff at `frequency`.
+    Lowpass,
+    /// Second order highpass with the cutoff at `frequency`.
+    Highpass,
+    /// Bandpass with unity gain at the center `frequency`.
+    Bandpass,
+    /// Notch at the center `frequency`.
+    Notch,
+}
+
+/// Second order (biquad) filter design.
+///
+/// # Miniconf
+/// `{"response": "Lowpass", "frequency": 1000.0, "q": 0.707, "gain": 1.0, "y_offset": 0.0,
+/// "y_min": -32767.0, "y_max": 32767.0}`
+///
+/// * `response` is any of the variants of [Response] enclosed in double quotes.
+/// * `frequency` is the cutoff or center frequency in Hz.
+/// * `q` is the quality factor. A lowpass or highpass with `q` of `1/sqrt(2)` has a maximally flat
+///   (Butterworth) response. For bandpass and notch, `q` is the center frequency divided by the
+///   bandwidth.
+/// * `gain` is the passband gain.
+/// * `y_offset`, `y_min`, and `y_max` specify the output offset and limits.
+#[derive(Copy, Clone, Debug, Miniconf, Deserialize, Serialize)]
+pub struct FilterDesign {
+    /// The frequency response of the filter.
+    pub response: Response,
+
+    /// The cutoff or center frequency in Hz.
+    pub frequency: f32,
+
+    /// The quality factor.
+    pub q: f32,
+
+    /// The passband gain.
+    pub gain: f32,
+
+    /// The output offset.
+    pub y_offset: f32,
+
+    /// The lower output limit.
+    pub y_min: f32,
+
+    /// The upper output limit.
+    pub y_max: f32,
+}
+
+impl Default for FilterDesign {
+    fn default() -> Self {
+        Self {
+            response: Response::Lowpass,
+            frequency: 1000.0,
+            q: core::f32::consts::FRAC_1_SQRT_2,
+            gain: 1.0,
+            y_offset: 0.0,
+            y_min: -(i16::MAX as f32),
+            y_max: i16::MAX as f32,
+        }
+    }
+}
+
+impl FilterDesign {
+    /// Convert the filter design into IIR biquad coefficients.
+    ///
+    /// # Note
+    /// The coefficients are computed using the bilinear transform formulas of the Audio EQ
+    /// Cookbook by R. Bristow-Johnson. The frequency is prewarped, so the response at `frequency`
+    /// is exact.
+    ///
+    /// # Args
+    /// * `sample_period` - The time in seconds between filter updates.
+    ///
+    /// # Returns
+    /// The IIR filter implementing the design.
+    pub fn try_into_iir(
+        self,
+        sample_period: f32,
+    ) -> Result<iir::IIR<f32>, Error> {
+        if !sample_period.is_finite() || sample_period <= 0.0 {
+            return Err(Error::InvalidSamplePeriod);
+        }
+
+        let normalized = self.frequency * sample_period;
+        if !(normalized > 0.0 && normalized < 0.5) {
+            return Err(Error::InvalidFrequency);
+        }
+
+        if !self.q.is_finite() || self.q <= 0.0 {
+            return Err(Error::InvalidQ);
+        }
+
+        if !self.gain.is_finite() {
+            return Err(Error::InvalidGain);
+        }
+
+        if self.y_min.is_nan() || self.y_max.is_nan() || self.y_min > self.y_max
+        {
+            return Err(Error::InvalidLimits);
+        }
+
+        let w0 = 2.0 * core::f32::consts::PI * normalized;
+        let cos = libm::cosf(w0);
+        let alpha = libm::sinf(w0) / (2.0 * self.q);
+
+        // `1 - cos(w0)` is computed from the half angle to retain precision at low frequencies.
+        let half_sin = libm::sinf(w0 / 2.0);
+        let one_minus_cos = 2.0 * half_sin * half_sin;
+        let one_plus_cos = 2.0 - one_minus_cos;
+
+        let b = match self.response {
+            Response::Lowpass => {
+                [one_minus_cos / 2.0, one_minus_cos, one_minus_cos / 2.0]
+            }
+            Response::Highpass => {
+                [one_plus_cos / 2.0, -one_plus_cos, one_plus_cos / 2.0]
+            }
+            Response::Bandpass => [alpha, 0.0, -alpha],
+            Response::Notch => [1.0, -2.0 * cos, 1.0],
+        };
+
+        // Normalize to `a0` and negate the feedback taps, as the IIR computes
+        // `y0 = a1*y1 + a2*y2 + b0*x0 + b1*x1 + b2*x2`.
+        let a0 = 1.0 + alpha;
+        let g = self.gain / a0;
+
+        Ok(iir::IIR {
+            ba: [
+                g * b[0],
+                g * b[1],
+                g * b[2],
+                2.0 * cos / a0,
+                (alpha - 1.0) / a0,
+            ],
+            y_offset: self.y_offset,
+            y_min: self.y_min,
+            y_max: self.y_max,
+        })
+    }
+}
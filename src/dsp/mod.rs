@@ -0,0 +1,4 @@
+//! Filter design helpers that convert user-facing filter parameters into [idsp] filter
+//! configurations.
+pub mod biquad;
+pub mod pid;
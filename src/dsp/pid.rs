@@ -0,0 +1,111 @@
+use idsp::iir;
+use miniconf::Miniconf;
+use serde::{Deserialize, Serialize};
+
+/// Represents the errors that can occur when converting a PID configuration into an IIR filter.
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+    /// The provided sample period is not positive.
+    InvalidSamplePeriod,
+    /// A provided gain is not finite.
+    InvalidGain,
+    /// The provided output limits are inverted.
+    InvalidLimits,
+}
+
+/// Proportional-integral-derivative (PID) controller configuration.
+///
+/// # Miniconf
+/// `{"kp": 1.0, "ki": 0.0, "kd": 0.0, "y_offset": 0.0, "y_min": -32767.0, "y_max": 32767.0}`
+///
+/// The controller implements `y = kp * x + ki * ∫x dt + kd * dx/dt + y_offset`, where `x` is the
+/// filter input and `y` is the filter output, both in the units of the IIR filter they are
+/// converted to. `kp` is dimensionless, `ki` is in units of 1/s and `kd` is in units of s.
+/// `y_min` and `y_max` specify the output limits. Anti-windup of the integrator is inherent to the
+/// IIR implementation.
+#[derive(Copy, Clone, Debug, Miniconf, Deserialize, Serialize)]
+pub struct Pid {
+    /// The proportional gain.
+    pub kp: f32,
+
+    /// The integral gain in units of 1/s.
+    pub ki: f32,
+
+    /// The derivative gain in units of s.
+    pub kd: f32,
+
+    /// The output offset.
+    pub y_offset: f32,
+
+    /// The lower output limit.
+    pub y_min: f32,
+
+    /// The upper output limit.
+    pub y_max: f32,
+}
+
+impl Default for Pid {
+    fn default() -> Self {
+        Self {
+            kp: 1.0,
+            ki: 0.0,
+            kd: 0.0,
+            y_offset: 0.0,
+            y_min: -(i16::MAX as f32),
+            y_max: i16::MAX as f32,
+        }
+    }
+}
+
+impl Pid {
+    /// Convert the controller configuration into IIR biquad coefficients.
+    ///
+    /// # Note
+    /// The integral term is discretized using the bilinear (Tustin) transform, identical to
+    /// [iir::IIR::set_pi] with an integral gain of `ki * sample_period / 2` and no gain limit. As the
+    /// bilinear transform of a derivative places a pole at Nyquist, the derivative term is
+    /// discretized using the backward difference instead. Without integral gain, the filter has
+    /// no feedback taps.
+    ///
+    /// # Args
+    /// * `sample_period` - The time in seconds between filter updates.
+    ///
+    /// # Returns
+    /// The IIR filter implementing the controller.
+    pub fn try_into_iir(
+        self,
+        sample_period: f32,
+    ) -> Result<iir::IIR<f32>, Error> {
+        if !sample_period.is_finite() || sample_period <= 0.0 {
+            return Err(Error::InvalidSamplePeriod);
+        }
+
+        if !(self.kp.is_finite() && self.ki.is_finite() && self.kd.is_finite())
+        {
+            return Err(Error::InvalidGain);
+        }
+
+        if self.y_min.is_nan() || self.y_max.is_nan() || self.y_min > self.y_max
+        {
+            return Err(Error::InvalidLimits);
+        }
+
+        let ki = self.ki * sample_period / 2.0;
+        let kd = self.kd / sample_period;
+
+        // Multiplying the transfer function `kp + ki (1 + z^-1) / (1 - z^-1) + kd (1 - z^-1)` by
+        // the integrator denominator yields the feed-forward taps.
+        let ba = if ki == 0.0 {
+            [self.kp + kd, -kd, 0.0, 0.0, 0.0]
+        } else {
+            [self.kp + ki + kd, ki - self.kp - 2.0 * kd, kd, 1.0, 0.0]
+        };
+
+        Ok(iir::IIR {
+            ba,
+            y_offset: self.y_offset,
+            y_min: self.y_min,
+            y_max: self.y_max,
+        })
+    }
+}
@@ -83,6 +83,9 @@ use hal::{
 };
 
 /// A type representing an ADC sample.
+///
+/// The public field is the ADC-formatted binary code as read from the ADC, i.e. the two's
+/// complement code reinterpreted as `u16`.
 #[derive(Copy, Clone)]
 pub struct AdcCode(pub u16);
 
@@ -92,6 +95,14 @@ impl AdcCode {
     const FULL_SCALE: f32 = 5.0 / 2.0 * 4.096;
     const VOLT_PER_LSB: f32 = -Self::FULL_SCALE / i16::MIN as f32;
     const LSB_PER_VOLT: f32 = 1. / Self::VOLT_PER_LSB;
+
+    /// Get the raw signed ADC code.
+    ///
+    /// # Returns
+    /// The two's complement ADC code. This is the ADC sample as contained in the data stream.
+    pub fn code(&self) -> i16 {
+        self.0 as i16
+    }
 }
 
 impl From<u16> for AdcCode {
@@ -407,6 +418,28 @@ macro_rules! adc_input {
 
                 }
 
+                /// Clear the error flags of the SPI peripheral.
+                ///
+                /// # Note
+                /// This allows resuming operation after a transient SPI error from the SPI error
+                /// interrupt handler. A mode fault disables the SPI peripheral and is not recovered
+                /// by clearing the flags.
+                pub fn clear_errors() {
+                    // Note(unsafe): The flag clear register is write-only and clearing the error
+                    // flags does not interfere with the ongoing DMA transfers.
+                    let regs = unsafe { &*hal::stm32::$spi::ptr() };
+                    regs.ifcr.write(|w| {
+                        w.ovrc()
+                            .set_bit()
+                            .udrc()
+                            .set_bit()
+                            .crcec()
+                            .set_bit()
+                            .modfc()
+                            .set_bit()
+                    });
+                }
+
                 /// Wait for the transfer of the currently active buffer to complete,
                 /// then call a function on the now inactive buffer and acknowledge the
                 /// transfer complete flag.
@@ -4,8 +4,20 @@ use serde::{Deserialize, Serialize};
 use core::convert::TryFrom;
 use num_enum::TryFromPrimitive;
 
+/// The gain of an analog front-end.
+///
+/// # Note
+/// The discriminant is the gain code reported in the stream frame header. Use
+/// [Gain::as_multiplier] to convert it to a numerical gain.
 #[derive(
-    Copy, Clone, Debug, Serialize, Deserialize, TryFromPrimitive, Miniconf,
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    Serialize,
+    Deserialize,
+    TryFromPrimitive,
+    Miniconf,
 )]
 #[repr(u8)]
 pub enum Gain {
@@ -69,6 +81,10 @@ where
     }
 
     /// Get the programmed gain of the analog front-end.
+    ///
+    /// # Note
+    /// The gain is derived from the output latch of the gain-select pins. It is the gain commanded
+    /// by [ProgrammableGainAmplifier::set_gain] and not a read-back from the amplifier.
     pub fn get_gain(&self) -> Gain {
         let mut code: u8 = 0;
         if self.a0.is_set_high().unwrap() {
@@ -65,6 +65,7 @@ use hal::{
         traits::TargetAddress,
         DMAError, MemoryToPeripheral, Transfer,
     },
+    hal::spi::FullDuplex,
     spi::{HalDisabledSpi, HalEnabledSpi, HalSpi},
 };
 
@@ -77,7 +78,7 @@ static mut DAC_BUF: [[SampleBuffer; 2]; 2] =
     [[[0; MAX_SAMPLE_BUFFER_SIZE]; 2]; 2];
 
 /// Custom type for referencing DAC output codes.
-/// The internal integer is the raw code written to the DAC output register.
+/// The public internal integer is the raw offset binary code written to the DAC output register.
 #[derive(Copy, Clone)]
 pub struct DacCode(pub u16);
 impl DacCode {
@@ -86,6 +87,27 @@ impl DacCode {
     pub const FULL_SCALE: f32 = 4.096 * 2.5;
     pub const VOLT_PER_LSB: f32 = -Self::FULL_SCALE / i16::MIN as f32;
     pub const LSB_PER_VOLT: f32 = 1. / Self::VOLT_PER_LSB;
+
+    /// Convert a signed output value to a DAC code, saturating at the DAC output range.
+    ///
+    /// # Note
+    /// In contrast to an unchecked float-to-integer conversion, this is well-defined for any
+    /// input at the cost of a few additional cycles for the saturation. NaN is converted to zero.
+    ///
+    /// # Args
+    /// * `value` - The output value in LSB of the signed 16-bit DAC range.
+    pub fn from_saturating(value: f32) -> Self {
+        // Note(as): Float-to-integer casts saturate and map NaN to zero.
+        Self::from(value as i16)
+    }
+
+    /// Get the raw DAC output code.
+    ///
+    /// # Returns
+    /// The offset binary DAC code. This is the DAC sample as contained in the data stream.
+    pub fn code(&self) -> u16 {
+        self.0
+    }
 }
 
 impl TryFrom<f32> for DacCode {
@@ -190,11 +212,18 @@ macro_rules! dac_output {
             /// * `spi` - The SPI interface used to communicate with the ADC.
             /// * `stream` - The DMA stream used to write DAC codes over SPI.
             /// * `trigger_channel` - The sampling timer output compare channel for update triggers.
+            /// * `batch_size` - The number of DAC codes in each batch.
+            /// * `initial` - The code to output until the first batch is generated.
             pub fn new(
-                spi: hal::spi::Spi<hal::stm32::$spi, hal::spi::Enabled, u16>,
+                mut spi: hal::spi::Spi<
+                    hal::stm32::$spi,
+                    hal::spi::Enabled,
+                    u16,
+                >,
                 stream: hal::dma::dma::$data_stream<hal::stm32::DMA1>,
                 trigger_channel: timers::tim2::$trigger_channel,
                 batch_size: usize,
+                initial: DacCode,
             ) -> Self {
                 // Generate DMA events when an output compare of the timer hitting zero (timer roll over)
                 // occurs.
@@ -207,18 +236,24 @@ macro_rules! dac_output {
                     .double_buffer(true)
                     .peripheral_increment(false);
 
+                // Output the initial code right away instead of retaining the power-on output until
+                // the sampling timer starts the DMA transfers.
+                // Note(unwrap): The SPI was just configured, so no errors can be pending.
+                hal::block!(spi.send(initial.0)).unwrap();
+                while !spi.inner().sr.read().txc().is_completed() {}
+
                 // Listen for any potential SPI error signals, which may indicate that we are not generating
                 // update codes.
                 let mut spi = spi.disable();
                 spi.listen(hal::spi::Event::Error);
 
-                // AXISRAM is uninitialized. As such, we manually initialize it for a 0V DAC output
-                // here before starting the transfer .
+                // AXISRAM is uninitialized. As such, we manually initialize it for the initial DAC
+                // output here before starting the transfer.
                 // Note(unsafe): We currently own all DAC_BUF[index] buffers and are not using them
                 // elsewhere, so it is safe to access them here.
                 for buf in unsafe { DAC_BUF[$index].iter_mut() } {
                     for byte in buf.iter_mut() {
-                        *byte = DacCode::try_from(0.0f32).unwrap().0;
+                        *byte = initial.0;
                     }
                 }
 
@@ -241,6 +276,28 @@ macro_rules! dac_output {
                 self.transfer.start(|spi| spi.start_dma());
             }
 
+            /// Clear the error flags of the SPI peripheral.
+            ///
+            /// # Note
+            /// This allows resuming operation after a transient SPI error from the SPI error
+            /// interrupt handler. A mode fault disables the SPI peripheral and is not recovered
+            /// by clearing the flags.
+            pub fn clear_errors() {
+                // Note(unsafe): The flag clear register is write-only and clearing the error
+                // flags does not interfere with the ongoing DMA transfers.
+                let regs = unsafe { &*hal::stm32::$spi::ptr() };
+                regs.ifcr.write(|w| {
+                    w.ovrc()
+                        .set_bit()
+                        .udrc()
+                        .set_bit()
+                        .crcec()
+                        .set_bit()
+                        .modfc()
+                        .set_bit()
+                });
+            }
+
             /// Wait for the transfer of the currently active buffer to complete,
             /// then call a function on the now inactive buffer and acknowledge the
             /// transfer complete flag.
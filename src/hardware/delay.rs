@@ -1,10 +1,12 @@
 //! Basic blocking delay
 //!
-//! This module provides a basic asm-based blocking delay.
+//! This module provides a basic asm-based blocking delay and a cycle counter based delay.
 //!
 //! # Note
-//! This implementation takes into account the Cortex-M7 CPU pipeline architecture to ensure delays
-//! are at least as long as specified.
+//! The asm-based implementation takes into account the Cortex-M7 CPU pipeline architecture to
+//! ensure delays are at least as long as specified. The cycle counter based implementation is
+//! accurate to a few core clock cycles, also for delays of a few microseconds.
+use cortex_m::peripheral::{DCB, DWT};
 use embedded_hal::blocking::delay::{DelayMs, DelayUs};
 
 /// A basic delay implementation.
@@ -44,3 +46,41 @@ where
         cortex_m::asm::delay(self.frequency_ms * ms.into())
     }
 }
+
+/// A delay implementation based on the DWT cycle counter.
+///
+/// # Note
+/// The cycle counter wraps around after 2^32 core clock cycles, so delays are limited to about
+/// 10 seconds at a core clock of 400 MHz. Longer delays are truncated to this limit.
+pub struct CycleDelay {
+    cycles_us: u32,
+}
+
+impl CycleDelay {
+    /// Create a new delay and enable the cycle counter.
+    ///
+    /// # Args
+    /// * `dcb` - The debug control block, used to enable the trace unit.
+    /// * `dwt` - The data watchpoint and trace unit containing the cycle counter.
+    /// * `freq` - The CPU core frequency.
+    pub fn new(dcb: &mut DCB, dwt: &mut DWT, freq: u32) -> CycleDelay {
+        dcb.enable_trace();
+        DWT::unlock();
+        dwt.enable_cycle_counter();
+
+        CycleDelay {
+            cycles_us: freq / 1_000_000,
+        }
+    }
+}
+
+impl<U> DelayUs<U> for CycleDelay
+where
+    U: Into<u32>,
+{
+    fn delay_us(&mut self, us: U) {
+        let start = DWT::cycle_count();
+        let cycles = self.cycles_us.saturating_mul(us.into());
+        while DWT::cycle_count().wrapping_sub(start) < cycles {}
+    }
+}
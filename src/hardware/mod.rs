@@ -67,12 +67,8 @@ pub type I2c1Proxy =
 #[inline(never)]
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    use core::{
-        fmt::Write,
-        sync::atomic::{AtomicBool, Ordering},
-    };
+    use core::sync::atomic::{AtomicBool, Ordering};
     use cortex_m::asm;
-    use rtt_target::{ChannelMode, UpChannel};
 
     cortex_m::interrupt::disable();
 
@@ -88,11 +84,21 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     gpiod.odr.modify(|_, w| w.odr6().high().odr12().high());
 
     // Analogous to panic-rtt-target
-    if let Some(mut channel) = unsafe { UpChannel::conjure(0) } {
-        channel.set_mode(ChannelMode::BlockIfFull);
-        writeln!(channel, "{}", info).ok();
+    #[cfg(not(feature = "defmt"))]
+    {
+        use core::fmt::Write;
+        use rtt_target::{ChannelMode, UpChannel};
+
+        if let Some(mut channel) = unsafe { UpChannel::conjure(0) } {
+            channel.set_mode(ChannelMode::BlockIfFull);
+            writeln!(channel, "{}", info).ok();
+        }
     }
 
+    // The RTT channel is owned by `defmt-rtt`, which must not be written to directly.
+    #[cfg(feature = "defmt")]
+    defmt::error!("{}", defmt::Display2Format(info));
+
     // Abort
     asm::udf();
     // Halt
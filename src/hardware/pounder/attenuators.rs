@@ -1,5 +1,53 @@
 use super::{Channel, Error};
 
+/// The maximum attenuation of the default digital attenuators in dB.
+pub const MAX_ATTENUATION: f32 = 31.5;
+
+/// The attenuation step (code LSB) of the default digital attenuators in dB.
+pub const ATTENUATION_STEP: f32 = 0.5;
+
+/// Get the attenuation code closest to the provided attenuation.
+fn attenuation_code(attenuation: f32, step: f32, max: f32) -> u8 {
+    // Note(as): The conversion rounds to the nearest code. NaN is converted to zero.
+    (attenuation.clamp(0.0, max) / step + 0.5) as u8
+}
+
+/// Get the left shift of the attenuation code within the 8-bit shift register of an attenuator.
+/// The code is MSB-aligned and the unused LSBs are ignored by the attenuator.
+fn code_shift(step: f32, max: f32) -> u32 {
+    ((max / step) as u8).leading_zeros()
+}
+
+/// Get the closest attenuation that can be programmed into the default attenuators.
+///
+/// Args:
+/// * `attenuation` - The desired attenuation in dB.
+///
+/// Returns:
+/// The attenuation in dB clamped to [0, 31.5] dB and rounded to the nearest 0.5 dB step.
+pub fn quantize_attenuation(attenuation: f32) -> f32 {
+    attenuation_code(attenuation, ATTENUATION_STEP, MAX_ATTENUATION) as f32
+        * ATTENUATION_STEP
+}
+
+/// Convert the raw content of an attenuator shift register into an attenuation.
+///
+/// Args:
+/// * `register` - The active-low register content with the MSB-aligned attenuation code.
+///
+/// Returns:
+/// The attenuation in dB.
+pub(crate) fn register_to_attenuation<A: AttenuatorInterface + ?Sized>(
+    register: u8,
+) -> f32 {
+    // The attenuator stores the code as active-low, so inverting the result (before the shift)
+    // has the affect of transforming the bits of interest (and the dont-care bits) into an
+    // active-high state and then masking off the don't care bits. If the shift occurs before the
+    // inversion, the upper bits (which would then be don't care) would contain erroneous data.
+    let code = (!register) >> code_shift(A::STEP_DB, A::MAX_DB);
+    code as f32 * A::STEP_DB
+}
+
 /// Provide an interface for managing digital attenuators on Pounder hardware.
 ///
 /// Note: The digital attenuators do not allow read-back of attenuation. To circumvent this, this
@@ -7,41 +55,81 @@ use super::{Channel, Error};
 /// register of the attenuators. This allows the "active" attenuation code to be read back by
 /// reading the shfit register. The downside of this approach is that any read is destructive, so a
 /// read-writeback approach is employed.
+///
+/// The attenuation step size and range default to the installed attenuator part. Boards with a
+/// different part override [AttenuatorInterface::STEP_DB] and [AttenuatorInterface::MAX_DB].
 pub trait AttenuatorInterface {
+    /// The attenuation step (code LSB) of the attenuators in dB.
+    const STEP_DB: f32 = ATTENUATION_STEP;
+
+    /// The maximum attenuation of the attenuators in dB. This must be an integer multiple of
+    /// [AttenuatorInterface::STEP_DB] of at most 255 steps.
+    const MAX_DB: f32 = MAX_ATTENUATION;
+
     /// Set the attenuation of a single channel.
     ///
     /// Args:
     /// * `channel` - The pounder channel to configure the attenuation of.
-    /// * `attenuation` - The desired attenuation of the channel in dB. This has a resolution of
-    ///   0.5dB.
+    /// * `attenuation` - The desired attenuation of the channel in dB. This is rounded to the
+    ///   nearest [AttenuatorInterface::STEP_DB] step.
+    ///
+    /// Returns:
+    /// The programmed attenuation of the channel in dB.
     fn set_attenuation(
         &mut self,
         channel: Channel,
         attenuation: f32,
     ) -> Result<f32, Error> {
-        if !(0.0..=31.5).contains(&attenuation) {
+        if !(0.0..=Self::MAX_DB).contains(&attenuation) {
             return Err(Error::Bounds);
         }
 
         // Calculate the attenuation code to program into the attenuator. The attenuator uses a
-        // code where the LSB is 0.5 dB.
-        let attenuation_code = (attenuation * 2.0) as u8;
+        // code where the LSB is the attenuation step.
+        let attenuation_code =
+            attenuation_code(attenuation, Self::STEP_DB, Self::MAX_DB);
 
         // Read all the channels, modify the channel of interest, and write all the channels back.
         // This ensures the staging register and the output register are always in sync.
         let mut channels = [0_u8; 4];
         self.transfer_attenuators(&mut channels)?;
 
-        // The lowest 2 bits of the 8-bit shift register on the attenuator are ignored. Shift the
-        // attenuator code into the upper 6 bits of the register value. Note that the attenuator
+        // The unused LSBs of the 8-bit shift register on the attenuator are ignored. Shift the
+        // attenuator code into the upper bits of the register value. Note that the attenuator
         // treats inputs as active-low, so the code is inverted before writing.
-        channels[channel as usize] = !(attenuation_code << 2);
+        channels[channel as usize] =
+            !(attenuation_code << code_shift(Self::STEP_DB, Self::MAX_DB));
         self.transfer_attenuators(&mut channels)?;
 
         // Finally, latch the output of the updated channel to force it into an active state.
         self.latch_attenuator(channel)?;
 
-        Ok(attenuation_code as f32 / 2.0)
+        Ok(attenuation_code as f32 * Self::STEP_DB)
+    }
+
+    /// Set the attenuation of a single channel, clamping out-of-range attenuations.
+    ///
+    /// Args:
+    /// * `channel` - The pounder channel to configure the attenuation of.
+    /// * `attenuation` - The desired attenuation of the channel in dB. This is clamped to
+    ///   [0, [AttenuatorInterface::MAX_DB]] and rounded to the nearest
+    ///   [AttenuatorInterface::STEP_DB] step.
+    ///
+    /// Returns:
+    /// The programmed attenuation of the channel in dB.
+    fn set_attenuation_clamped(
+        &mut self,
+        channel: Channel,
+        attenuation: f32,
+    ) -> Result<f32, Error> {
+        if attenuation.is_nan() {
+            return Err(Error::Bounds);
+        }
+
+        let attenuation =
+            attenuation_code(attenuation, Self::STEP_DB, Self::MAX_DB) as f32
+                * Self::STEP_DB;
+        self.set_attenuation(channel, attenuation)
     }
 
     /// Get the attenuation of a channel.
@@ -60,16 +148,9 @@ pub trait AttenuatorInterface {
         self.transfer_attenuators(&mut channels)?;
         self.transfer_attenuators(&mut channels)?;
 
-        // The attenuation code is stored in the upper 6 bits of the register, where each LSB
-        // represents 0.5 dB. The attenuator stores the code as active-low, so inverting the result
-        // (before the shift) has the affect of transforming the bits of interest (and the
-        // dont-care bits) into an active-high state and then masking off the don't care bits. If
-        // the shift occurs before the inversion, the upper 2 bits (which would then be don't
-        // care) would contain erroneous data.
-        let attenuation_code = (!channels[channel as usize]) >> 2;
-
-        // Convert the desired channel code into dB of attenuation.
-        Ok(attenuation_code as f32 / 2.0)
+        // The attenuation code is stored in the upper bits of the register, where each LSB
+        // represents the attenuation step.
+        Ok(register_to_attenuation::<Self>(channels[channel as usize]))
     }
 
     fn reset_attenuators(&mut self) -> Result<(), Error>;
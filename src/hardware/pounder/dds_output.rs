@@ -52,17 +52,32 @@
 ///! compile-time-known register update sequence needed for the application, the serialization
 ///! process can be done once and then register values can be written into a pre-computed serialized
 ///! buffer to avoid the software overhead of much of the serialization process.
-use log::warn;
-use stm32h7xx_hal as hal;
+use super::{hrtimer::HighResTimerE, DdsClockConfig, Error, QspiInterface};
+use crate::{
+    hardware::{delay::CycleDelay, hal},
+    logging,
+};
+use ad9959::{Channel, Interface, Mode, Profile, ProfileSerializer, Register};
+use embedded_hal::blocking::delay::DelayUs;
 
-use super::{hrtimer::HighResTimerE, QspiInterface};
-use ad9959::{Channel, Mode, ProfileSerializer};
+/// The output pin connected to the DDS reset input.
+pub type ResetPin = hal::gpio::ErasedPin<hal::gpio::Output>;
+
+// The serialized size of a complete channel profile in bytes. This consists of the CSR, CFTW0,
+// CPOW0 and ACR register writes, each prefixed with the register address.
+const PROFILE_SIZE: usize = 2 + 5 + 3 + 4;
+
+// The capacity of a `ProfileSerializer` in bytes.
+const SERIALIZER_CAPACITY: usize = 32;
 
 /// The DDS profile update stream.
 pub struct DdsOutput {
-    _qspi: QspiInterface,
+    qspi: QspiInterface,
     io_update_trigger: HighResTimerE,
     mode: Mode,
+    reset_pin: ResetPin,
+    clock: DdsClockConfig,
+    delay: CycleDelay,
 }
 
 impl DdsOutput {
@@ -77,19 +92,50 @@ impl DdsOutput {
     /// * `qspi` - The QSPI interface to the run the stream on.
     /// * `io_update_trigger` - The HighResTimerE used to generate IO_Update pulses.
     /// * `config` - The frozen DDS configuration.
+    /// * `reset_pin` - The pin connected to the DDS reset input.
+    /// * `clock` - The DDS clock configuration, which is restored on reset.
+    /// * `delay` - A delay implementation used to time the reset sequence.
     pub fn new(
         mut qspi: QspiInterface,
         io_update_trigger: HighResTimerE,
         mode: Mode,
+        reset_pin: ResetPin,
+        clock: DdsClockConfig,
+        delay: CycleDelay,
     ) -> Self {
         qspi.start_stream().unwrap();
         Self {
             mode,
-            _qspi: qspi,
+            qspi,
             io_update_trigger,
+            reset_pin,
+            clock,
+            delay,
         }
     }
 
+    /// Configure the timing of the IO_Update pulse.
+    ///
+    /// # Note
+    /// The delay must leave sufficient time for the profile to be written over QSPI before the
+    /// IO_Update pulse is generated.
+    ///
+    /// # Args
+    /// * `delay_ns` - The delay from writing a profile to asserting IO_Update in nanoseconds.
+    /// * `width_ns` - The duration of the IO_Update pulse in nanoseconds.
+    pub fn configure_io_update(
+        &mut self,
+        delay_ns: u32,
+        width_ns: u32,
+    ) -> Result<(), Error> {
+        self.io_update_trigger.configure(delay_ns, width_ns)
+    }
+
+    /// Get the DDS clock configuration restored on reset.
+    pub fn clock_config(&self) -> &DdsClockConfig {
+        &self.clock
+    }
+
     /// Get a builder for serializing a Pounder DDS profile.
     #[allow(dead_code)]
     pub fn builder(&mut self) -> ProfileBuilder {
@@ -100,30 +146,153 @@ impl DdsOutput {
         }
     }
 
-    /// Write a profile to the stream.
+    /// Perform a self-test of the QSPI communication interface to the DDS.
     ///
-    /// # Note:
-    /// If a profile of more than 8 words is provided, the QSPI interface will likely
-    /// stall execution. If there are still bytes pending in the FIFO, the write will certainly
-    /// stall.
+    /// # Note
+    /// The stream is temporarily stopped to allow register reads and is restarted afterwards.
+    /// Any profile written concurrently would be lost, so this should only be called from a
+    /// low-priority context while no profiles are being streamed.
+    ///
+    /// # Returns
+    /// True if the self test succeeded. False otherwise. Communication errors during the test are
+    /// reported as a failed test.
+    pub fn self_test(&mut self) -> Result<bool, Error> {
+        self.qspi.stop_stream()?;
+        let passed = ad9959::self_test(&mut self.qspi).unwrap_or(false);
+        self.qspi.start_stream()?;
+
+        Ok(passed)
+    }
+
+    /// Reset the DDS and restore its interface and clock configuration.
+    ///
+    /// # Note
+    /// This recovers a DDS that lost synchronization, e.g. after the reference clock was
+    /// interrupted. The reset clears all channel profiles, so they have to be written again
+    /// afterwards. As with [DdsOutput::self_test], the stream is temporarily stopped, so this
+    /// should only be called from a low-priority context while no profiles are being streamed.
+    pub fn reset(&mut self) -> Result<(), Error> {
+        let system_clock = self.clock.validate()?;
+
+        self.qspi.stop_stream()?;
+
+        // Delay for at least 1 SYNC_CLK period for the reset to occur. The SYNC_CLK is guaranteed
+        // to be at least 250KHz (1/4 of 1MHz minimum REF_CLK). We use 5uS instead of 4uS to
+        // guarantee conformance with datasheet requirements.
+        self.reset_pin.set_high();
+        self.delay.delay_us(5u8);
+        self.reset_pin.set_low();
+
+        // The DDS communicates in single-bit two-wire mode after reset. Configure the interface
+        // mode and latch it.
+        self.qspi.configure_mode(Mode::SingleBitTwoWire)?;
+        let csr = [Channel::ALL.bits() | self.mode as u8];
+        self.qspi.write(Register::CSR as u8, &csr)?;
+        self.latch();
+        self.qspi.configure_mode(self.mode)?;
+
+        let mut updated_csr = [0];
+        self.qspi.read(Register::CSR as u8, &mut updated_csr)?;
+        if updated_csr != csr {
+            logging::error!("DDS CSR read-back mismatch after reset");
+            self.qspi.start_stream()?;
+            return Err(Error::InvalidState);
+        }
+
+        // Restore the system clock multiplier. All other FR1 bits are at their reset default.
+        let vco_range = system_clock > 255e6;
+        let fr1 = [
+            ((vco_range as u8) << 7) | (self.clock.multiplier << 2),
+            0,
+            0,
+        ];
+        self.qspi.write(Register::FR1 as u8, &fr1)?;
+        self.latch();
+
+        self.qspi.start_stream()
+    }
+
+    /// Generate an IO_Update pulse and wait for the DDS to apply the update.
+    fn latch(&mut self) {
+        self.io_update_trigger.trigger();
+
+        // The pulse is generated after the configured IO_Update delay, which can not exceed about
+        // 1.3 ms. Waiting 2 ms also covers the lock time of the DDS reference clock multiplier.
+        self.delay.delay_us(2_000u16);
+    }
+
+    /// Atomically update a number of channels with complete profiles.
+    ///
+    /// # Note
+    /// All profiles are serialized into a single transfer and become active simultaneously on
+    /// the next IO_Update pulse generated by the high-resolution timer. Due to the size of the
+    /// serialization buffer, at most 2 profiles can be written at once. Channels that share a
+    /// profile should be combined into a single entry.
     ///
     /// # Args
-    /// * `profile` - The serialized DDS profile to write.
-    pub fn write(&mut self, profile: &[u32]) {
-        // Note(unsafe): We own the QSPI interface, so it is safe to access the registers in a raw
-        // fashion.
-        let regs = unsafe { &*hal::stm32::QUADSPI::ptr() };
+    /// * `profiles` - A list of channels and the profile to apply to them.
+    pub fn update(
+        &mut self,
+        profiles: &[(Channel, Profile)],
+    ) -> Result<(), Error> {
+        if profiles.len() * PROFILE_SIZE > SERIALIZER_CAPACITY {
+            return Err(Error::Bounds);
+        }
 
-        // Warn if the fifo is still at least half full.
-        if regs.sr.read().flevel().bits() >= 16 {
-            warn!("QSPI stalling")
+        if profiles.is_empty() {
+            return Ok(());
         }
 
-        for word in profile.iter() {
-            // Note(unsafe): any bit pattern is valid for a TX FIFO write.
-            regs.dr.write(|w| unsafe { w.bits(*word) });
+        let mut serializer = ProfileSerializer::new(self.mode);
+        for (channels, profile) in profiles.iter() {
+            serializer.update_channels(
+                *channels,
+                Some(profile.ftw),
+                Some(profile.pow),
+                Some(profile.acr),
+            );
         }
 
+        self.write(serializer.finalize().or(Err(Error::Bounds))?);
+
+        Ok(())
+    }
+
+    /// Gate the outputs of a number of channels synchronously to the IO_Update pulse.
+    ///
+    /// # Note
+    /// The gate is serialized and streamed like a profile. It takes effect on the IO_Update pulse
+    /// generated by the high-resolution timer, i.e. after the configured IO_Update delay, such
+    /// that the timing is deterministic relative to the calling DSP routine. The outputs are
+    /// switched using the DAC power-down bit, resetting all other channel function register bits
+    /// to their defaults. See [ProfileSerializer::set_channels_enabled].
+    ///
+    /// # Args
+    /// * `channels` - A set of channels to gate.
+    /// * `enabled` - Specified true to enable the channel outputs, false to power them down.
+    pub fn set_outputs_enabled(
+        &mut self,
+        channels: Channel,
+        enabled: bool,
+    ) -> Result<(), Error> {
+        let mut serializer = ProfileSerializer::new(self.mode);
+        serializer.set_channels_enabled(channels, enabled);
+        self.write(serializer.finalize().or(Err(Error::Bounds))?);
+        Ok(())
+    }
+
+    /// Write a profile to the stream.
+    ///
+    /// # Note:
+    /// If there are still bytes pending in the FIFO, the write will stall.
+    ///
+    /// # Args
+    /// * `profile` - The serialized DDS profile to write. At most 8 words may be provided.
+    pub fn write(&mut self, profile: &[u32]) {
+        // Note(unwrap): The stream is started on construction and profiles generated by the
+        // `ProfileSerializer` always fit into the FIFO.
+        self.qspi.stream_profile(profile).unwrap();
+
         // Trigger the IO_update signal generating timer to asynchronous create the IO_Update pulse.
         self.io_update_trigger.trigger();
     }
@@ -158,9 +327,14 @@ impl<'a> ProfileBuilder<'a> {
     }
 
     /// Write the profile to the DDS asynchronously.
+    ///
+    /// # Returns
+    /// Fails with [Error::Bounds] if the serialized profile does not fit into the FIFO.
     #[allow(dead_code)]
     #[inline]
-    pub fn write(&mut self) {
-        self.dds_output.write(self.serializer.finalize());
+    pub fn write(&mut self) -> Result<(), Error> {
+        let profile = self.serializer.finalize().or(Err(Error::Bounds))?;
+        self.dds_output.write(profile);
+        Ok(())
     }
 }
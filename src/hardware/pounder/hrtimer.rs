@@ -1,4 +1,5 @@
 ///! The HRTimer (High Resolution Timer) is used to generate IO_Update pulses to the Pounder DDS.
+use super::Error;
 use stm32h7xx_hal::{
     self as hal,
     rcc::{rec, CoreClocks, ResetEnable},
@@ -6,6 +7,7 @@ use stm32h7xx_hal::{
 
 /// A HRTimer output channel.
 #[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
 pub enum Channel {
     One,
     Two,
@@ -18,6 +20,7 @@ pub struct HighResTimerE {
     common: hal::stm32::HRTIM_COMMON,
 
     clocks: CoreClocks,
+    channel: Option<Channel>,
 }
 
 impl HighResTimerE {
@@ -36,6 +39,7 @@ impl HighResTimerE {
             timer: timer_regs,
             common: common_regs,
             clocks,
+            channel: None,
         }
     }
 
@@ -45,27 +49,67 @@ impl HighResTimerE {
     /// This will configure the timer to generate a single pulse on an output channel. The timer
     /// will only count up once and must be `trigger()`'d after / configured.
     ///
-    /// The output will be asserted from `set_offset` to `set_offset` + `set_duration` in the count.
+    /// The output will be asserted from `delay` to `delay` + `duration` in the count.
     ///
     /// # Args
     /// * `channel` - The timer output channel to configure.
-    /// * `set_duration` - The duration that the output should be asserted for.
-    /// * `set_offset` - The first time at which the output should be asserted.
+    /// * `delay` - The first time at which the output should be asserted in seconds.
+    /// * `duration` - The duration that the output should be asserted for in seconds.
     pub fn configure_single_shot(
         &mut self,
         channel: Channel,
         delay: f32,
         duration: f32,
     ) {
-        // Disable the timer before configuration.
-        self.master.mcr.modify(|_, w| w.tecen().clear_bit());
-
-        // Configure the desired timer for single shot mode with set and reset of the specified
-        // channel at the desired durations. The HRTIM is on APB2 (D2 domain), and the kernel clock
-        // is the APB bus clock.
         let clk = self.clocks.timy_ker_ck().to_Hz() as f32;
+        let start = (delay * clk) as u32;
         let end = ((delay + duration) * clk) as u32 + 1;
 
+        self.configure_ticks(channel, start, end)
+            .expect("Unattainable timing parameters!");
+    }
+
+    /// Reconfigure the timing of the single-shot pulse.
+    ///
+    /// # Note
+    /// The timer must have been configured using [HighResTimerE::configure_single_shot] before.
+    /// The pulse is generated on the previously configured output channel. This allows adjusting
+    /// when DDS profiles are latched relative to the trigger, e.g. to align IO_Update with the
+    /// ADC/DAC batch boundary.
+    ///
+    /// # Args
+    /// * `delay_ns` - The delay from the trigger to the assertion of the output in nanoseconds.
+    /// * `width_ns` - The duration that the output should be asserted for in nanoseconds.
+    ///
+    /// # Returns
+    /// An error if the timer has not been configured yet or if the timing parameters can not be
+    /// realized with the timer clock.
+    pub fn configure(
+        &mut self,
+        delay_ns: u32,
+        width_ns: u32,
+    ) -> Result<(), Error> {
+        let channel = self.channel.ok_or(Error::InvalidState)?;
+
+        let clk = self.clocks.timy_ker_ck().to_Hz() as u64;
+        let to_ticks = |ns: u64| ns * clk / 1_000_000_000;
+
+        let start = to_ticks(delay_ns as u64);
+        let end = to_ticks(delay_ns as u64 + width_ns as u64) + 1;
+        if end > u32::MAX as u64 {
+            return Err(Error::Bounds);
+        }
+
+        self.configure_ticks(channel, start as u32, end as u32)
+    }
+
+    /// Configure the timer to assert the output from `start` to `end` timer kernel clock ticks.
+    fn configure_ticks(
+        &mut self,
+        channel: Channel,
+        start: u32,
+        end: u32,
+    ) -> Result<(), Error> {
         // Determine the clock divider, which may be 1, 2, or 4. We will choose a clock divider that
         // allows us the highest resolution per tick, so lower dividers are favored.
         let div: u8 = if end < 0xFFDF {
@@ -75,14 +119,25 @@ impl HighResTimerE {
         } else if (end / 4) < 0xFFDF {
             3
         } else {
-            panic!("Unattainable timing parameters!");
+            return Err(Error::Bounds);
         };
 
         // The period register must be greater than or equal to 3 cycles.
-        let period = (end / (1 << (div - 1)) as u32) as u16;
-        assert!(period > 2);
+        let period = (end >> (div - 1)) as u16;
+
+        // The compare register must be greater than or equal to 3 cycles and assert the output
+        // before the end of the period.
+        let delay = (start >> (div - 1)) as u16;
 
-        // We now have the prescaler and the period registers. Configure the timer.
+        if period <= 2 || delay < 3 || delay >= period {
+            return Err(Error::Bounds);
+        }
+
+        // Disable the timer before configuration.
+        self.master.mcr.modify(|_, w| w.tecen().clear_bit());
+
+        // We now have the prescaler and the period registers. Configure the timer. The HRTIM is on
+        // APB2 (D2 domain), and the kernel clock is the APB bus clock.
         // Note(unsafe): The prescaler is guaranteed to be greater than or equal to 4 (minimum
         // allowed value) due to the addition. The setting is always 1, 2, or 3, which represents
         // all valid values.
@@ -95,10 +150,8 @@ impl HighResTimerE {
         self.timer.perer.write(|w| unsafe { w.perx().bits(period) });
 
         // Configure the comparator 1 level.
-        let delay = (delay * clk) as u16;
         // Note(unsafe): The offset is always a 16-bit value, so is always valid for values >= 3, as
         // specified by the datasheet.
-        assert!(delay >= 3);
         self.timer
             .cmp1er
             .write(|w| unsafe { w.cmp1x().bits(delay) });
@@ -118,8 +171,12 @@ impl HighResTimerE {
             }
         }
 
+        self.channel.replace(channel);
+
         // Enable the timer now that it is configured.
         self.master.mcr.modify(|_, w| w.tecen().set_bit());
+
+        Ok(())
     }
 
     /// Generate a single trigger of the timer to start the output pulse generation.
@@ -1,9 +1,14 @@
 use self::attenuators::AttenuatorInterface;
+use self::rf_power::PowerMeasurementInterface;
 
 use super::hal;
-use crate::hardware::{shared_adc::AdcChannel, I2c1Proxy};
+use crate::{
+    hardware::{design_parameters, shared_adc::AdcChannel, I2c1Proxy},
+    logging,
+};
 use embedded_hal::blocking::spi::Transfer;
 use enum_iterator::Sequence;
+use miniconf::Miniconf;
 use serde::{Deserialize, Serialize};
 
 pub mod attenuators;
@@ -51,6 +56,23 @@ impl From<GpioPin> for mcp230xx::Mcp23017 {
     }
 }
 
+// The number of attempts made for each I2C transaction before failing.
+const I2C_ATTEMPTS: usize = 3;
+
+// The number of polls of the I2C bus state while waiting for the bus to be released.
+const I2C_IDLE_POLLS: usize = 10_000;
+
+/// The levels of the Pounder GPIO expander pins as read back from the expander.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct GpioState(u16);
+
+impl GpioState {
+    /// Check whether a pin is at a high level.
+    pub fn is_high(&self, pin: GpioPin) -> bool {
+        self.0 & (1 << mcp230xx::Mcp23017::from(pin) as usize) != 0
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum Error {
     Spi,
@@ -69,10 +91,31 @@ impl From<hal::xspi::QspiError> for Error {
     }
 }
 
+/// Telemetry reported by Pounder.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct PounderTelemetry {
+    /// The voltage on the auxiliary ADC inputs of RF input channels IN0/IN1. `null` if the
+    /// measurement failed.
+    pub aux_adc: [Option<f32>; 2],
+
+    /// The last attenuation in dB latched into the attenuators of IN0, OUT0, IN1, and OUT1.
+    pub attenuation: [f32; 4],
+
+    /// Whether the external reference clock input is selected, as read back from the GPIO
+    /// expander. `null` if the read-back failed.
+    pub ext_clk: Option<bool>,
+
+    /// The multiplier of the DDS reference clock configured to generate the DDS system clock.
+    pub clock_multiplier: u8,
+
+    /// The averaged RF power in dBm of the input channels IN0/IN1, measured after the input
+    /// attenuators. `null` if the measurement failed.
+    pub input_power: [Option<f32>; 2],
+}
+
 /// The numerical value (discriminant) of the Channel enum is the index in the attenuator shift
 /// register as well as the attenuator latch enable signal index on the GPIO extender.
-#[derive(Debug, Copy, Clone)]
-#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, Sequence)]
 pub enum Channel {
     In0 = 0,
     Out0 = 1,
@@ -91,7 +134,9 @@ impl From<Channel> for GpioPin {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(
+    Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Miniconf,
+)]
 pub struct DdsChannelState {
     pub phase_offset: f32,
     pub frequency: f32,
@@ -105,26 +150,187 @@ pub struct ChannelState {
     pub attenuation: f32,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Miniconf)]
 pub struct InputChannelState {
     pub attenuation: f32,
     pub power: f32,
     pub mixer: DdsChannelState,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Miniconf)]
 pub struct OutputChannelState {
     pub attenuation: f32,
     pub channel: DdsChannelState,
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Miniconf)]
 pub struct DdsClockConfig {
     pub multiplier: u8,
     pub reference_clock: f32,
     pub external_clock: bool,
 }
 
+impl Default for DdsClockConfig {
+    /// The DDS clock configured during setup.
+    fn default() -> Self {
+        Self {
+            multiplier: design_parameters::DDS_MULTIPLIER,
+            reference_clock: design_parameters::DDS_REF_CLK.to_Hz() as f32,
+            external_clock: false,
+        }
+    }
+}
+
+impl Default for InputChannelState {
+    /// A disabled input channel at maximum attenuation.
+    fn default() -> Self {
+        Self {
+            attenuation: <PounderDevices as AttenuatorInterface>::MAX_DB,
+            power: 0.0,
+            mixer: DdsChannelState::default(),
+        }
+    }
+}
+
+impl Default for OutputChannelState {
+    /// A disabled output channel at maximum attenuation.
+    fn default() -> Self {
+        Self {
+            attenuation: <PounderDevices as AttenuatorInterface>::MAX_DB,
+            channel: DdsChannelState::default(),
+        }
+    }
+}
+
+/// A complete Pounder configuration.
+#[derive(
+    Serialize, Deserialize, Copy, Clone, Debug, Default, PartialEq, Miniconf,
+)]
+pub struct PounderConfig {
+    /// The DDS clock configuration.
+    pub clock: DdsClockConfig,
+    /// The attenuation and mixer DDS configuration of the inputs IN0 and IN1.
+    pub in_channel: [InputChannelState; 2],
+    /// The attenuation and DDS configuration of the outputs OUT0 and OUT1.
+    pub out_channel: [OutputChannelState; 2],
+}
+
+impl DdsClockConfig {
+    /// Validate the DDS clock configuration.
+    ///
+    /// # Returns
+    /// The resulting DDS system clock frequency in Hz.
+    pub fn validate(&self) -> Result<f32, Error> {
+        if self.multiplier != 1 && !(4..=20).contains(&self.multiplier) {
+            logging::error!(
+                "Invalid DDS clock multiplier: {}",
+                self.multiplier
+            );
+            return Err(Error::Bounds);
+        }
+
+        let system_clock = self.reference_clock * self.multiplier as f32;
+        if !(self.reference_clock > 0.0 && system_clock <= 500_000_000.0) {
+            logging::error!(
+                "Invalid DDS reference clock: {} Hz",
+                self.reference_clock
+            );
+            return Err(Error::Bounds);
+        }
+
+        Ok(system_clock)
+    }
+}
+
+impl DdsChannelState {
+    /// Validate the DDS channel parameters.
+    ///
+    /// # Note
+    /// Non-finite values are rejected along with out-of-range values. The parameters are validated
+    /// by [DdsChannelState::to_profile] before they are converted to machine units.
+    ///
+    /// # Args
+    /// * `system_clock` - The DDS system clock frequency in Hz.
+    pub fn validate(&self, system_clock: f32) -> Result<(), Error> {
+        if !(0.0..=system_clock / 2.0).contains(&self.frequency) {
+            logging::error!("Invalid DDS frequency: {} Hz", self.frequency);
+            return Err(Error::Bounds);
+        }
+
+        if !(0.0..=1.0).contains(&self.amplitude) {
+            logging::error!("Invalid DDS amplitude: {}", self.amplitude);
+            return Err(Error::Bounds);
+        }
+
+        if !self.phase_offset.is_finite() {
+            logging::error!("Invalid DDS phase offset: {}", self.phase_offset);
+            return Err(Error::Bounds);
+        }
+
+        Ok(())
+    }
+
+    /// Convert the channel parameters to a DDS profile in machine units.
+    ///
+    /// # Note
+    /// The profile does not control the channel output enable, so a disabled channel is
+    /// converted to a profile with zero amplitude.
+    ///
+    /// # Args
+    /// * `system_clock` - The DDS system clock frequency in Hz.
+    ///
+    /// # Returns
+    /// The profile if the parameters are valid.
+    pub fn to_profile(
+        &self,
+        system_clock: f32,
+    ) -> Result<ad9959::Profile, Error> {
+        self.validate(system_clock)?;
+
+        let amplitude = if self.enabled { self.amplitude } else { 0.0 };
+        Ok(ad9959::Profile {
+            ftw: ad9959::frequency_to_ftw(self.frequency, system_clock),
+            pow: ad9959::phase_to_pow(self.phase_offset),
+            acr: ad9959::amplitude_to_acr(amplitude),
+        })
+    }
+}
+
+impl PounderConfig {
+    /// Validate the complete configuration without accessing the hardware.
+    ///
+    /// # Note
+    /// This allows rejecting an invalid configuration as a whole before any part of it is
+    /// applied. The first invalid field is logged.
+    pub fn validate(&self) -> Result<(), Error> {
+        let system_clock = self.clock.validate()?;
+
+        let attenuations = self
+            .in_channel
+            .iter()
+            .map(|channel| channel.attenuation)
+            .chain(self.out_channel.iter().map(|channel| channel.attenuation));
+        for attenuation in attenuations {
+            if !(0.0..=<PounderDevices as AttenuatorInterface>::MAX_DB)
+                .contains(&attenuation)
+            {
+                logging::error!("Invalid attenuation: {} dB", attenuation);
+                return Err(Error::Bounds);
+            }
+        }
+
+        for channel in self.in_channel.iter() {
+            channel.mixer.validate(system_clock)?;
+        }
+
+        for channel in self.out_channel.iter() {
+            channel.channel.validate(system_clock)?;
+        }
+
+        Ok(())
+    }
+}
+
 impl From<Channel> for ad9959::Channel {
     /// Translate pounder channels to DDS output channels.
     fn from(other: Channel) -> Self {
@@ -137,6 +343,9 @@ impl From<Channel> for ad9959::Channel {
     }
 }
 
+// The size of the QSPI FIFO in 32-bit words.
+const QSPI_FIFO_WORDS: usize = 8;
+
 /// A structure for the QSPI interface for the DDS.
 pub struct QspiInterface {
     pub qspi: hal::xspi::Qspi<hal::stm32::QUADSPI>,
@@ -163,6 +372,12 @@ impl QspiInterface {
         })
     }
 
+    /// Start streaming profiles to the DDS.
+    ///
+    /// # Note
+    /// This configures the QSPI peripheral for an infinite transaction without instruction or
+    /// address phases. While streaming, register writes and reads are rejected. Use
+    /// [QspiInterface::stop_stream] to return to register access.
     pub fn start_stream(&mut self) -> Result<(), Error> {
         self.qspi.is_busy()?;
 
@@ -182,6 +397,67 @@ impl QspiInterface {
 
         Ok(())
     }
+
+    /// Write a pre-serialized DDS profile into the ongoing stream.
+    ///
+    /// # Note
+    /// The stream must have been started using [QspiInterface::start_stream] and the interface must
+    /// be operating in [ad9959::Mode::FourBitSerial], as profiles are clocked out with all four
+    /// data lines. The profile must be serialized for that mode and padded to a 32-bit word
+    /// boundary, which is guaranteed by [ad9959::ProfileSerializer::finalize]. Words are written
+    /// directly into the QSPI FIFO, which holds at most 8 words.
+    ///
+    /// # Args
+    /// * `words` - The serialized profile to write.
+    pub fn stream_profile(&mut self, words: &[u32]) -> Result<(), Error> {
+        if !self.streaming || self.mode != ad9959::Mode::FourBitSerial {
+            return Err(Error::InvalidState);
+        }
+
+        if words.len() > QSPI_FIFO_WORDS {
+            return Err(Error::Bounds);
+        }
+
+        // Note(unsafe): We own the QSPI interface, so it is safe to access the registers in a raw
+        // fashion.
+        let regs = unsafe { &*hal::stm32::QUADSPI::ptr() };
+
+        // Warn if the fifo is still at least half full.
+        if regs.sr.read().flevel().bits() >= 16 {
+            logging::warn!("QSPI stalling")
+        }
+
+        for word in words.iter() {
+            // Note(unsafe): any bit pattern is valid for a TX FIFO write.
+            regs.dr.write(|w| unsafe { w.bits(*word) });
+        }
+
+        Ok(())
+    }
+
+    /// Stop an ongoing stream and return to indirect register access.
+    ///
+    /// # Note
+    /// The infinite QSPI transaction is aborted, so any profile still in the QSPI FIFO is lost.
+    /// Afterwards, [ad9959::Interface::write] and [ad9959::Interface::read] can be used again
+    /// until the stream is restarted using [QspiInterface::start_stream].
+    pub fn stop_stream(&mut self) -> Result<(), Error> {
+        // Note(unsafe): We own the QSPI interface, so it is safe to access the registers in a raw
+        // fashion.
+        let qspi_regs = unsafe { &*hal::stm32::QUADSPI::ptr() };
+        qspi_regs.cr.modify(|_, w| w.abort().set_bit());
+        while qspi_regs.cr.read().abort().bit_is_set() {}
+
+        // Wait for the peripheral to become idle before reconfiguring it.
+        while self.qspi.is_busy().is_err() {}
+
+        // Restore the address and data phases of register transactions removed by the stream.
+        self.qspi.configure_mode(hal::xspi::QspiMode::FourBit)?;
+
+        self.streaming = false;
+
+        Ok(())
+    }
 }
 
 impl ad9959::Interface for QspiInterface {
@@ -295,8 +571,9 @@ impl ad9959::Interface for QspiInterface {
             return Err(Error::InvalidAddress);
         }
 
-        // This implementation only supports operation (read) in four-bit-serial mode.
-        if self.mode != ad9959::Mode::FourBitSerial {
+        // This implementation only supports operation (read) in four-bit-serial mode. Reads are
+        // not possible during an ongoing stream.
+        if self.mode != ad9959::Mode::FourBitSerial || self.streaming {
             return Err(Error::InvalidState);
         }
 
@@ -331,6 +608,10 @@ pub struct PounderDevices {
         hal::stm32::ADC3,
         hal::gpio::gpiof::PF4<hal::gpio::Analog>,
     >,
+    // The raw codes most recently shifted into the attenuator shift registers.
+    attenuator_staging: [u8; 4],
+    // The raw codes most recently latched into the attenuator outputs.
+    attenuator_latched: [u8; 4],
 }
 
 impl PounderDevices {
@@ -377,30 +658,84 @@ impl PounderDevices {
             pwr1,
             aux_adc0,
             aux_adc1,
+            attenuator_staging: [0; 4],
+            attenuator_latched: [0; 4],
         };
 
         // Configure power-on-default state for pounder. All LEDs are off, on-board oscillator
         // selected and enabled, attenuators out of reset. Note that testing indicates the
         // output state needs to be set first to properly update the output registers.
         for pin in enum_iterator::all::<GpioPin>() {
-            devices
-                .mcp23017
-                .set_gpio(pin.into(), mcp230xx::Level::Low)
-                .map_err(|_| Error::I2c)?;
-            devices
-                .mcp23017
-                .set_direction(pin.into(), mcp230xx::Direction::Output)
-                .map_err(|_| Error::I2c)?;
+            devices.i2c_transaction(|devices| {
+                devices.mcp23017.set_gpio(pin.into(), mcp230xx::Level::Low)
+            })?;
+            devices.i2c_transaction(|devices| {
+                devices
+                    .mcp23017
+                    .set_direction(pin.into(), mcp230xx::Direction::Output)
+            })?;
         }
         devices.reset_attenuators().unwrap();
+
+        // Initialize the attenuation cache from the reset state of the attenuators.
+        let mut channels = [0_u8; 4];
+        devices.transfer_attenuators(&mut channels)?;
+        devices.transfer_attenuators(&mut channels)?;
+        devices.attenuator_latched = channels;
+
         Ok(devices)
     }
 
+    /// Perform an I2C transaction on the Pounder I2C bus with bounded retries.
+    ///
+    /// # Note
+    /// The transaction is only started once the bus has been released, such that a bus held by a
+    /// stuck device does not block the caller indefinitely. A transaction that stalls after it
+    /// has been started is not interrupted.
+    ///
+    /// # Args
+    /// * `transaction` - The transaction to perform. It is repeated on failure and must thus be
+    ///   idempotent.
+    ///
+    /// # Returns
+    /// The result of the first successful transaction or `Error::I2c` if all attempts failed.
+    fn i2c_transaction<T, E>(
+        &mut self,
+        mut transaction: impl FnMut(&mut Self) -> Result<T, E>,
+    ) -> Result<T, Error> {
+        // Note(unsafe): Only the status register of the I2C peripheral is read.
+        let i2c = unsafe { &*hal::stm32::I2C1::ptr() };
+
+        for _ in 0..I2C_ATTEMPTS {
+            let idle = (0..I2C_IDLE_POLLS)
+                .any(|_| i2c.isr.read().busy().is_not_busy());
+            if idle {
+                if let Ok(result) = transaction(self) {
+                    return Ok(result);
+                }
+            }
+        }
+
+        Err(Error::I2c)
+    }
+
+    /// Read the Pounder temperature.
+    ///
+    /// # Returns
+    /// The temperature in degrees Celsius.
+    pub fn read_temperature(&mut self) -> Result<f32, Error> {
+        self.i2c_transaction(|devices| devices.lm75.read_temperature())
+    }
+
     /// Sample one of the two auxiliary ADC channels associated with the respective RF input channel.
     pub fn sample_aux_adc(&mut self, channel: Channel) -> Result<f32, Error> {
         let adc_scale = match channel {
-            Channel::In0 => self.aux_adc0.read_normalized().unwrap(),
-            Channel::In1 => self.aux_adc1.read_normalized().unwrap(),
+            Channel::In0 => {
+                self.aux_adc0.read_normalized().map_err(|_| Error::Adc)?
+            }
+            Channel::In1 => {
+                self.aux_adc1.read_normalized().map_err(|_| Error::Adc)?
+            }
             _ => return Err(Error::InvalidChannel),
         };
 
@@ -409,15 +744,118 @@ impl PounderDevices {
         Ok(adc_scale * 2.048)
     }
 
+    /// Sample the Pounder monitoring inputs for telemetry reporting.
+    ///
+    /// # Args
+    /// * `clock` - The DDS clock configuration to report. This is held by the DDS output and
+    ///   reported without accessing the DDS.
+    /// * `power_samples` - The number of power detector samples averaged for each input power
+    ///   measurement.
+    ///
+    /// # Returns
+    /// The Pounder telemetry. Measurements that fail are reported as `None`.
+    pub fn get_telemetry(
+        &mut self,
+        clock: &DdsClockConfig,
+        power_samples: u8,
+    ) -> PounderTelemetry {
+        let mut aux_adc = [None; 2];
+        for (voltage, channel) in
+            aux_adc.iter_mut().zip([Channel::In0, Channel::In1])
+        {
+            *voltage = self
+                .sample_aux_adc(channel)
+                .map_err(|err| {
+                    logging::warn!(
+                        "Failed to sample aux ADC {:?}: {:?}",
+                        channel,
+                        err
+                    )
+                })
+                .ok();
+        }
+
+        let mut input_power = [None; 2];
+        for (power, channel) in
+            input_power.iter_mut().zip([Channel::In0, Channel::In1])
+        {
+            *power = self
+                .measure_average_power(channel, power_samples)
+                .map_err(|err| {
+                    logging::warn!(
+                        "Failed to measure power of {:?}: {:?}",
+                        channel,
+                        err
+                    )
+                })
+                .ok();
+        }
+
+        let ext_clk = self
+            .i2c_transaction(|devices| {
+                devices.mcp23017.gpio(GpioPin::ExtClkSel.into())
+            })
+            .map(|level| level == mcp230xx::Level::High)
+            .map_err(|_| {
+                logging::warn!("Failed to read back the clock selection")
+            })
+            .ok();
+
+        PounderTelemetry {
+            aux_adc,
+            attenuation: self.cached_attenuations(),
+            ext_clk,
+            clock_multiplier: clock.multiplier,
+            input_power,
+        }
+    }
+
+    /// Get the attenuations most recently latched into the attenuators.
+    ///
+    /// # Note
+    /// In contrast to [attenuators::AttenuatorInterface::get_attenuation], this does not access
+    /// the attenuators and thus does not require a read-writeback of the shift registers.
+    ///
+    /// # Returns
+    /// The attenuation in dB of the IN0, OUT0, IN1, and OUT1 channels.
+    pub fn cached_attenuations(&self) -> [f32; 4] {
+        let mut attenuations = [0.0; 4];
+        for channel in enum_iterator::all::<Channel>() {
+            attenuations[channel as usize] =
+                attenuators::register_to_attenuation::<Self>(
+                    self.attenuator_latched[channel as usize],
+                );
+        }
+        attenuations
+    }
+
     /// Set the state (its electrical level) of the given GPIO pin on Pounder.
     pub fn set_gpio_pin(
         &mut self,
         pin: GpioPin,
         level: mcp230xx::Level,
     ) -> Result<(), Error> {
-        self.mcp23017
-            .set_gpio(pin.into(), level)
-            .map_err(|_| Error::I2c)
+        self.i2c_transaction(|devices| {
+            devices.mcp23017.set_gpio(pin.into(), level)
+        })
+    }
+
+    /// Read back the levels of all GPIO expander pins.
+    ///
+    /// # Note
+    /// The levels are read from the expander port registers. They reflect the actual pin levels
+    /// and can be used to verify that the expander is in the commanded state.
+    pub fn read_gpio_state(&mut self) -> Result<GpioState, Error> {
+        let mut state = GpioState::default();
+        for pin in enum_iterator::all::<GpioPin>() {
+            let level = self
+                .i2c_transaction(|devices| devices.mcp23017.gpio(pin.into()))?;
+            if level == mcp230xx::Level::High {
+                state.0 |= 1 << mcp230xx::Mcp23017::from(pin) as usize;
+            }
+        }
+
+        Ok(state)
     }
 
     /// Select external reference clock input.
@@ -449,7 +887,11 @@ impl attenuators::AttenuatorInterface for PounderDevices {
         // Rising edge sensitive
         // Be robust against initial state: drive low, then high (contrary to the datasheet figure).
         self.set_gpio_pin(channel.into(), mcp230xx::Level::Low)?;
-        self.set_gpio_pin(channel.into(), mcp230xx::Level::High)
+        self.set_gpio_pin(channel.into(), mcp230xx::Level::High)?;
+
+        self.attenuator_latched[channel as usize] =
+            self.attenuator_staging[channel as usize];
+        Ok(())
     }
 
     /// Read the raw attenuation codes stored in the attenuator shift registers.
@@ -461,9 +903,11 @@ impl attenuators::AttenuatorInterface for PounderDevices {
         &mut self,
         channels: &mut [u8; 4],
     ) -> Result<(), Error> {
+        let staging = *channels;
         self.attenuator_spi
             .transfer(channels)
             .map_err(|_| Error::Spi)?;
+        self.attenuator_staging = staging;
 
         Ok(())
     }
@@ -479,8 +923,12 @@ impl rf_power::PowerMeasurementInterface for PounderDevices {
     /// The sampled voltage of the specified channel.
     fn sample_converter(&mut self, channel: Channel) -> Result<f32, Error> {
         let adc_scale = match channel {
-            Channel::In0 => self.pwr0.read_normalized().unwrap(),
-            Channel::In1 => self.pwr1.read_normalized().unwrap(),
+            Channel::In0 => {
+                self.pwr0.read_normalized().map_err(|_| Error::Adc)?
+            }
+            Channel::In1 => {
+                self.pwr1.read_normalized().map_err(|_| Error::Adc)?
+            }
             _ => return Err(Error::InvalidChannel),
         };
 
@@ -1,5 +1,13 @@
 use super::{Channel, Error};
 
+/// Convert the power detector output voltage to the input power in dBm.
+fn detector_power(voltage: f32) -> f32 {
+    // The AD8363 with VSET connected to VOUT provides an output voltage of 51.7 mV/dB at
+    // 100MHz with an intercept of -58 dBm.
+    // It is placed behind a 20 dB tap.
+    voltage * (1. / 0.0517) + (-58. + 20.)
+}
+
 /// Provide an interface to measure RF input power in dBm.
 pub trait PowerMeasurementInterface {
     fn sample_converter(&mut self, channel: Channel) -> Result<f32, Error>;
@@ -12,11 +20,31 @@ pub trait PowerMeasurementInterface {
     /// Returns:
     /// Power in dBm after the digitally controlled attenuator before the amplifier.
     fn measure_power(&mut self, channel: Channel) -> Result<f32, Error> {
-        let analog_measurement = self.sample_converter(channel)?;
+        Ok(detector_power(self.sample_converter(channel)?))
+    }
+
+    /// Measure the power of an input channel in dBm averaged over multiple samples.
+    ///
+    /// Args:
+    /// * `channel` - The pounder input channel to measure the power of.
+    /// * `samples` - The number of detector samples to average. At least one sample is taken.
+    ///
+    /// Returns:
+    /// The mean power in dBm after the digitally controlled attenuator before the amplifier.
+    ///
+    /// Note:
+    /// The detector output is logarithmic, so this is the mean of the power in dBm.
+    fn measure_average_power(
+        &mut self,
+        channel: Channel,
+        samples: u8,
+    ) -> Result<f32, Error> {
+        let samples = samples.max(1);
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            sum += self.sample_converter(channel)?;
+        }
 
-        // The AD8363 with VSET connected to VOUT provides an output voltage of 51.7 mV/dB at
-        // 100MHz with an intercept of -58 dBm.
-        // It is placed behind a 20 dB tap.
-        Ok(analog_measurement * (1. / 0.0517) + (-58. + 20.))
+        Ok(detector_power(sum / samples as f32))
     }
 }
@@ -16,13 +16,26 @@
 ///! capture is simultaneously triggered. That trigger is prescaled (its rate is divided) by the
 ///! batch size. This results in the input capture triggering identically to when the ADC samples
 ///! the last sample of the batch. That sample is then available for processing by the user.
+///!
+///! The timestamp timer wraps after its period (65536 ticks by default). Captured timestamps are
+///! extended to 32 bits in software by accumulating the difference between consecutive captures
+///! modulo the timer period. This requires that timestamps are read at least once per timer period
+///! (approximately 2 ms for the default period and a 32 ns tick).
+///! The extended timestamps wrap at 32 bits and can be provided directly to a digital PLL (e.g.
+///! `idsp::RPLL`) to track the phase of the external reference.
+use super::Error;
 use crate::hardware::timers;
+use idsp::RPLL;
 use stm32h7xx_hal as hal;
 
 /// Software unit to timestamp stabilizer ADC samples using an external pounder reference clock.
 pub struct Timestamper {
     timer: timers::PounderTimestampTimer,
     capture_channel: timers::tim8::Channel1InputCapture,
+    last_capture: Option<u16>,
+    period: u16,
+    timestamp: u32,
+    overflow: bool,
 }
 
 impl Timestamper {
@@ -67,8 +80,12 @@ impl Timestamper {
         input_capture.configure_prescaler(prescaler);
 
         Self {
+            period: timestamp_timer.get_period(),
             timer: timestamp_timer,
             capture_channel: input_capture,
+            last_capture: None,
+            timestamp: 0,
+            overflow: false,
         }
     }
 
@@ -78,16 +95,167 @@ impl Timestamper {
     }
 
     /// Update the period of the underlying timestamp timer.
+    ///
+    /// # Note
+    /// The timer wraps after `period + 1` ticks. Timestamps captured before and after the update
+    /// are not related, so the extension of the timestamps to 32 bits restarts with the next
+    /// capture.
+    ///
+    /// # Args
+    /// * `period` - The maximum count of the timestamp timer.
     pub fn update_period(&mut self, period: u16) {
         self.timer.set_period_ticks(period);
+        self.period = period;
+        self.last_capture = None;
+    }
+
+    /// Obtain the latest timestamp of the external reference.
+    ///
+    /// # Note
+    /// This function must be called at least as often as timestamps arrive and at least once per
+    /// timestamp timer period to correctly extend the timestamps to 32 bits. If an over-capture
+    /// occurs, the capture is of unknown recency and is discarded. The overflow is recorded and
+    /// can be queried using [Timestamper::overflowed].
+    ///
+    /// # Returns
+    /// The most recently captured timestamp in timestamp timer ticks, extended to 32 bits. `None`
+    /// if no new valid timestamp was captured.
+    pub fn latest_timestamp(&mut self) -> Option<u32> {
+        let capture = match self.capture_channel.latest_capture() {
+            Ok(capture) => capture?,
+            Err(_) => {
+                self.overflow = true;
+                return None;
+            }
+        };
+
+        if let Some(last) = self.last_capture.replace(capture) {
+            // The timer counts from zero up to and including the period.
+            let modulus = self.period as u32 + 1;
+            let delta = (capture as u32 + modulus - last as u32) % modulus;
+            self.timestamp = self.timestamp.wrapping_add(delta);
+        } else {
+            self.timestamp = capture as u32;
+        }
+
+        Some(self.timestamp)
+    }
+
+    /// Check if a capture overflow occurred.
+    ///
+    /// # Note
+    /// The overflow indication is cleared upon reading.
+    ///
+    /// # Returns
+    /// True if at least one timestamp was dropped since the last check.
+    pub fn overflowed(&mut self) -> bool {
+        core::mem::replace(&mut self.overflow, false)
+    }
+}
+
+/// The phase and frequency estimate of a [TimestampPll].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PllState {
+    /// The phase of the reference at the time of the update. A full turn corresponds to 1 << 32,
+    /// wrapping at the i32 boundary.
+    pub phase: i32,
+
+    /// The phase advance of the reference per update in units of 1 << 32 per turn. The reference
+    /// frequency in Hz is `frequency * update_rate / (1 << 32)`.
+    pub frequency: i32,
+}
+
+/// A software phase-locked loop tracking the external reference using Pounder timestamps.
+///
+/// # Design
+/// The PLL is a reciprocal PLL (see `idsp::RPLL`) consuming the 32-bit timestamps provided by
+/// [Timestamper::latest_timestamp]. It is updated once per batch and reconstructs the phase and
+/// frequency of the reference relative to the update rate, which can be used to steer the
+/// demodulation phase or the DDS outputs.
+///
+/// # Pull-in range
+/// At most one timestamp can be provided per update, so the reference frequency must be below
+/// the update rate. The frequency settling time (1 << `shift_frequency` timestamp ticks) must be
+/// larger than the reference period to lock to.
+pub struct TimestampPll {
+    pll: RPLL,
+    dt2: u32,
+    shift_frequency: u32,
+    shift_phase: u32,
+}
+
+impl TimestampPll {
+    /// Construct a new PLL.
+    ///
+    /// # Args
+    /// * `dt2` - The log2 of the number of timestamp timer ticks per update.
+    /// * `shift_frequency` - The log2 of the frequency lock settling time in timestamp ticks.
+    /// * `shift_phase` - The log2 of the phase lock settling time in timestamp ticks. Usually one
+    ///   less than `shift_frequency`.
+    pub fn new(
+        dt2: u32,
+        shift_frequency: u32,
+        shift_phase: u32,
+    ) -> Result<Self, Error> {
+        let mut pll = Self {
+            pll: RPLL::new(dt2),
+            dt2,
+            shift_frequency: 0,
+            shift_phase: 0,
+        };
+
+        pll.set_gains(shift_frequency, shift_phase)?;
+
+        Ok(pll)
+    }
+
+    /// Configure the loop gains of the PLL.
+    ///
+    /// # Args
+    /// * `shift_frequency` - The log2 of the frequency lock settling time in timestamp ticks. Must
+    ///   be larger than `dt2` and less than 32.
+    /// * `shift_phase` - The log2 of the phase lock settling time in timestamp ticks. Must be at
+    ///   least `dt2` and less than 32.
+    pub fn set_gains(
+        &mut self,
+        shift_frequency: u32,
+        shift_phase: u32,
+    ) -> Result<(), Error> {
+        if shift_frequency <= self.dt2
+            || shift_frequency > 31
+            || shift_phase < self.dt2
+            || shift_phase > 31
+        {
+            return Err(Error::Bounds);
+        }
+
+        self.shift_frequency = shift_frequency;
+        self.shift_phase = shift_phase;
+
+        Ok(())
     }
 
-    /// Obtain a timestamp.
+    /// Advance the PLL by one update.
+    ///
+    /// # Note
+    /// This must be called exactly once per update period, regardless of whether a new timestamp
+    /// was captured.
+    ///
+    /// # Args
+    /// * `timestamp` - The newly captured timestamp, if any.
     ///
     /// # Returns
-    /// A `Result` potentially indicating capture overflow and containing a `Option` of a captured
-    /// timestamp.
-    pub fn latest_timestamp(&mut self) -> Result<Option<u16>, Option<u16>> {
-        self.capture_channel.latest_capture()
+    /// The current phase and frequency estimate.
+    pub fn update(&mut self, timestamp: Option<u32>) -> PllState {
+        let (phase, frequency) = self.pll.update(
+            timestamp.map(|t| t as i32),
+            self.shift_frequency,
+            self.shift_phase,
+        );
+
+        PllState {
+            phase,
+            frequency: frequency as i32,
+        }
     }
 }
@@ -1,7 +1,7 @@
 ///! Stabilizer hardware configuration
 ///!
 ///! This file contains all of the hardware-specific configuration of Stabilizer.
-use core::sync::atomic::{self, AtomicBool, Ordering};
+use core::sync::atomic::{self, Ordering};
 use core::{ptr, slice};
 use stm32h7xx_hal::{
     self as hal,
@@ -19,6 +19,7 @@ use super::{
     DigitalInput0, DigitalInput1, EthernetPhy, NetworkStack, SystemTimer,
     Systick, AFE0, AFE1,
 };
+use crate::logging;
 
 const NUM_TCP_SOCKETS: usize = 4;
 const NUM_UDP_SOCKETS: usize = 1;
@@ -98,6 +99,7 @@ pub struct NetworkDevices {
     pub stack: NetworkStack,
     pub phy: EthernetPhy,
     pub mac_address: smoltcp::wire::EthernetAddress,
+    pub crc: hal::crc::Crc,
 }
 
 /// The available hardware interfaces on Stabilizer.
@@ -109,6 +111,7 @@ pub struct StabilizerDevices {
     pub dacs: (dac::Dac0Output, dac::Dac1Output),
     pub timestamper: InputStamper,
     pub adc_dac_timer: timers::SamplingTimer,
+    pub adc_dac_shadow_timer: timers::ShadowSamplingTimer,
     pub timestamp_timer: timers::TimestampTimer,
     pub net: NetworkDevices,
     pub digital_inputs: (DigitalInput0, DigitalInput1),
@@ -185,6 +188,8 @@ fn load_itcm() {
 /// * `clock` - A `SystemTimer` implementing `Clock`.
 /// * `batch_size` - The size of each ADC/DAC batch.
 /// * `sample_ticks` - The number of timer ticks between each sample.
+/// * `startup_output` - The DAC0/DAC1 output voltages from the moment the DACs are configured
+///   until the application generates the first DAC output batch.
 ///
 /// # Returns
 /// (stabilizer, pounder) where `stabilizer` is a `StabilizerDevices` structure containing all
@@ -197,48 +202,57 @@ pub fn setup(
     clock: SystemTimer,
     batch_size: usize,
     sample_ticks: u32,
+    startup_output: [f32; 2],
 ) -> (StabilizerDevices, Option<PounderDevices>) {
     // Set up RTT logging
     {
         // Enable debug during WFE/WFI-induced sleep
         device.DBGMCU.cr.modify(|_, w| w.dbgsleep_d1().set_bit());
 
-        // Set up RTT channel to use for `rprintln!()` as "best effort".
-        // This removes a critical section around the logging and thus allows
-        // high-prio tasks to always interrupt at low latency.
-        // It comes at a cost:
-        // If a high-priority tasks preempts while we are logging something,
-        // and if we then also want to log from within that high-preiority task,
-        // the high-prio log message will be lost.
-
-        let channels = rtt_target::rtt_init_default!();
-        // Note(unsafe): The closure we pass does not establish a critical section
-        // as demanded but it does ensure synchronization and implements a lock.
-        unsafe {
-            rtt_target::set_print_channel_cs(
-                channels.up.0,
-                &((|arg, f| {
-                    static LOCKED: AtomicBool = AtomicBool::new(false);
-                    if LOCKED.compare_exchange_weak(
-                        false,
-                        true,
-                        Ordering::Acquire,
-                        Ordering::Relaxed,
-                    ) == Ok(false)
-                    {
-                        f(arg);
-                        LOCKED.store(false, Ordering::Release);
-                    }
-                }) as rtt_target::CriticalSectionFunc),
-            );
+        // With `defmt`, the RTT channel is set up by `defmt-rtt` instead.
+        #[cfg(not(feature = "defmt"))]
+        {
+            use core::sync::atomic::AtomicBool;
+
+            // Set up RTT channel to use for `rprintln!()` as "best effort".
+            // This removes a critical section around the logging and thus allows
+            // high-prio tasks to always interrupt at low latency.
+            // It comes at a cost:
+            // If a high-priority tasks preempts while we are logging something,
+            // and if we then also want to log from within that high-preiority task,
+            // the high-prio log message will be lost.
+
+            let channels = rtt_target::rtt_init_default!();
+            // Note(unsafe): The closure we pass does not establish a critical section
+            // as demanded but it does ensure synchronization and implements a lock.
+            unsafe {
+                rtt_target::set_print_channel_cs(
+                    channels.up.0,
+                    &((|arg, f| {
+                        static LOCKED: AtomicBool = AtomicBool::new(false);
+                        if LOCKED.compare_exchange_weak(
+                            false,
+                            true,
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        ) == Ok(false)
+                        {
+                            f(arg);
+                            LOCKED.store(false, Ordering::Release);
+                        }
+                    })
+                        as rtt_target::CriticalSectionFunc),
+                );
+            }
+
+            static LOGGER: rtt_logger::RTTLogger =
+                rtt_logger::RTTLogger::new(log::LevelFilter::Info);
+            log::set_logger(&LOGGER)
+                .map(|()| log::set_max_level(log::LevelFilter::Trace))
+                .unwrap();
         }
 
-        static LOGGER: rtt_logger::RTTLogger =
-            rtt_logger::RTTLogger::new(log::LevelFilter::Info);
-        log::set_logger(&LOGGER)
-            .map(|()| log::set_max_level(log::LevelFilter::Trace))
-            .unwrap();
-        log::info!("Starting");
+        logging::info!("Starting");
     }
 
     let pwr = device.PWR.constrain();
@@ -276,6 +290,14 @@ pub fn setup(
     // After ITCM loading.
     core.SCB.enable_icache();
 
+    // Enable the CPU cycle counter used to measure the processing time.
+    #[cfg(feature = "profile")]
+    {
+        core.DCB.enable_trace();
+        cortex_m::peripheral::DWT::unlock();
+        core.DWT.enable_cycle_counter();
+    }
+
     let mut delay = delay::AsmDelay::new(ccdr.clocks.c_ck().to_Hz());
 
     let gpioa = device.GPIOA.split(ccdr.peripheral.GPIOA);
@@ -284,7 +306,7 @@ pub fn setup(
     let gpiod = device.GPIOD.split(ccdr.peripheral.GPIOD);
     let gpioe = device.GPIOE.split(ccdr.peripheral.GPIOE);
     let gpiof = device.GPIOF.split(ccdr.peripheral.GPIOF);
-    let mut gpiog = device.GPIOG.split(ccdr.peripheral.GPIOG);
+    let gpiog = device.GPIOG.split(ccdr.peripheral.GPIOG);
 
     let dma_streams =
         hal::dma::dma::StreamsTuple::new(device.DMA1, ccdr.peripheral.DMA1);
@@ -458,9 +480,20 @@ pub fn setup(
     };
 
     let dacs = {
+        // Configure the DACs to update on every SPI transaction before they are written to.
         let mut dac_clr_n = gpioe.pe12.into_push_pull_output();
+        dac_clr_n.set_low();
+        // dac0_ldac_n
+        gpioe.pe11.into_push_pull_output().set_low();
+        // dac1_ldac_n
+        gpioe.pe15.into_push_pull_output().set_low();
         dac_clr_n.set_high();
 
+        // Note(unwrap): The startup output is specified by the application and must be within the
+        // DAC output range.
+        let startup_output = startup_output
+            .map(|voltage| dac::DacCode::try_from(voltage).unwrap());
+
         let dac0_spi = {
             let miso = gpioe.pe5.into_alternate().speed(Speed::VeryHigh);
             let sck = gpioe.pe2.into_alternate().speed(Speed::VeryHigh);
@@ -518,21 +551,16 @@ pub fn setup(
             dma_streams.6,
             sampling_timer_channels.ch3,
             batch_size,
+            startup_output[0],
         );
         let dac1 = dac::Dac1Output::new(
             dac1_spi,
             dma_streams.7,
             sampling_timer_channels.ch4,
             batch_size,
+            startup_output[1],
         );
 
-        dac_clr_n.set_low();
-        // dac0_ldac_n
-        gpioe.pe11.into_push_pull_output().set_low();
-        // dac1_ldac_n
-        gpioe.pe15.into_push_pull_output().set_low();
-        dac_clr_n.set_high();
-
         (dac0, dac1)
     };
 
@@ -581,7 +609,7 @@ pub fn setup(
         &mut eeprom_i2c,
         &mut delay,
     ));
-    log::info!("EUI48: {}", mac_addr);
+    logging::info!("EUI48: {}", mac_addr);
 
     let network_devices = {
         let ethernet_pins = {
@@ -639,11 +667,14 @@ pub fn setup(
 
         store.ip_addrs[0] = smoltcp::wire::IpCidr::new(ip_addrs, 24);
 
+        // The default gateway is only relevant for static IP configurations. DHCP will
+        // otherwise provide the route.
+        let gateway: smoltcp::wire::Ipv4Address =
+            option_env!("GATEWAY").unwrap_or("0.0.0.0").parse().unwrap();
+
         let mut routes =
             smoltcp::iface::Routes::new(&mut store.routes_cache[..]);
-        routes
-            .add_default_ipv4_route(smoltcp::wire::Ipv4Address::UNSPECIFIED)
-            .unwrap();
+        routes.add_default_ipv4_route(gateway).unwrap();
 
         let neighbor_cache =
             smoltcp::iface::NeighborCache::new(&mut store.neighbor_cache[..]);
@@ -706,10 +737,19 @@ pub fn setup(
 
         stack.seed_random_port(&random_seed);
 
+        // The CRC unit is configured for the standard CRC-32 (as used by Ethernet and zlib).
+        let mut crc = device.CRC.crc(ccdr.peripheral.CRC);
+        crc.set_config(
+            &hal::crc::Config::new()
+                .reflect(true)
+                .output_xor(0xFFFF_FFFF),
+        );
+
         NetworkDevices {
             stack,
             phy: lan8742a,
             mac_address: mac_addr,
+            crc,
         }
     };
 
@@ -775,7 +815,7 @@ pub fn setup(
     let pounder_pgood = gpiob.pb13.into_pull_down_input();
     delay.delay_ms(2u8);
     let pounder = if pounder_pgood.is_high() {
-        log::info!("Found Pounder");
+        logging::info!("Found Pounder");
 
         let i2c1 = {
             let sda = gpiob.pb7.into_alternate().set_open_drain();
@@ -833,7 +873,7 @@ pub fn setup(
         )
         .unwrap();
 
-        let ad9959 = {
+        let (ad9959, reset_pin, dds_delay) = {
             let qspi_interface = {
                 // Instantiate the QUADSPI pins and peripheral interface.
                 let qspi_pins = {
@@ -861,35 +901,42 @@ pub fn setup(
             };
 
             #[cfg(not(feature = "pounder_v1_0"))]
-            let reset_pin = gpiog.pg6.into_push_pull_output();
+            let mut reset_pin = gpiog.pg6.into_push_pull_output().erase();
             #[cfg(feature = "pounder_v1_0")]
-            let reset_pin = gpioa.pa0.into_push_pull_output();
+            let mut reset_pin = gpioa.pa0.into_push_pull_output().erase();
+
+            let io_update = gpiog.pg7.into_push_pull_output();
 
-            let mut io_update = gpiog.pg7.into_push_pull_output();
+            // The DDS reset and interface handshake require accurate delays of a few microseconds.
+            let mut dds_delay = delay::CycleDelay::new(
+                &mut core.DCB,
+                &mut core.DWT,
+                ccdr.clocks.c_ck().to_Hz(),
+            );
 
-            let mut ad9959 = ad9959::Ad9959::new(
+            // IO_Update is generated by the high resolution timer when streaming profiles.
+            let mut ad9959 = ad9959::Ad9959Builder::new(
                 qspi_interface,
-                reset_pin,
-                &mut io_update,
-                &mut delay,
-                ad9959::Mode::FourBitSerial,
+                io_update,
                 design_parameters::DDS_REF_CLK.to_Hz() as f32,
-                design_parameters::DDS_MULTIPLIER,
             )
+            .mode(ad9959::Mode::FourBitSerial)
+            .multiplier(design_parameters::DDS_MULTIPLIER)
+            .io_update_mode(ad9959::IoUpdateMode::HrTimer)
+            .build(&mut reset_pin, &mut dds_delay)
             .unwrap();
 
             ad9959.self_test().unwrap();
 
-            // Return IO_Update
-            gpiog.pg7 = io_update.into_analog();
-
-            ad9959
+            (ad9959, reset_pin, dds_delay)
         };
 
+        let (qspi, config, io_update) = ad9959.freeze();
+
         let dds_output = {
             let io_update_trigger = {
                 let _io_update =
-                    gpiog.pg7.into_alternate::<2>().speed(Speed::VeryHigh);
+                    io_update.into_alternate::<2>().speed(Speed::VeryHigh);
 
                 // Configure the IO_Update signal for the DDS.
                 let mut hrtimer = pounder::hrtimer::HighResTimerE::new(
@@ -924,13 +971,22 @@ pub fn setup(
                 hrtimer
             };
 
-            let (qspi, config) = ad9959.freeze();
-            DdsOutput::new(qspi, io_update_trigger, config)
+            // The DDS clock configuration is restored if the DDS is reset.
+            let clock = pounder::DdsClockConfig::default();
+
+            DdsOutput::new(
+                qspi,
+                io_update_trigger,
+                config,
+                reset_pin,
+                clock,
+                dds_delay,
+            )
         };
 
         #[cfg(not(feature = "pounder_v1_0"))]
         let pounder_stamper = {
-            log::info!("Assuming Pounder v1.1 or later");
+            logging::info!("Assuming Pounder v1.1 or later");
             let etr_pin = gpioa.pa0.into_alternate();
 
             // The frequency in the constructor is dont-care, as we will modify the period + clock
@@ -984,6 +1040,7 @@ pub fn setup(
         timestamper: input_stamper,
         net: network_devices,
         adc_dac_timer: sampling_timer,
+        adc_dac_shadow_timer: shadow_sampling_timer,
         timestamp_timer,
         digital_inputs,
     };
@@ -991,7 +1048,7 @@ pub fn setup(
     // info!("Version {} {}", build_info::PKG_VERSION, build_info::GIT_VERSION.unwrap());
     // info!("Built on {}", build_info::BUILT_TIME_UTC);
     // info!("{} {}", build_info::RUSTC_VERSION, build_info::TARGET);
-    log::info!("setup() complete");
+    logging::info!("setup() complete");
 
     (stabilizer, pounder)
 }
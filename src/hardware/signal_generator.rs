@@ -4,7 +4,7 @@ use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
 
 /// Types of signals that can be generated.
-#[derive(Copy, Clone, Debug, Deserialize, Serialize, Miniconf)]
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize, Miniconf)]
 pub enum Signal {
     Cosine,
     Square,
@@ -15,12 +15,15 @@ pub enum Signal {
 /// Basic configuration for a generated signal.
 ///
 /// # Miniconf
-/// `{"signal": <signal>, "frequency", 1000.0, "symmetry": 0.5, "amplitude": 1.0}`
+/// `{"signal": <signal>, "frequency", 1000.0, "symmetry": 0.5, "amplitude": 1.0, "phase": 0.0,
+/// "phase_offset": 0.0}`
 ///
 /// Where `<signal>` may be any of [Signal] variants, `frequency` specifies the signal frequency
-/// in Hertz, `symmetry` specifies the normalized signal symmetry which ranges from 0 - 1.0, and
-/// `amplitude` specifies the signal amplitude in Volts.
-#[derive(Copy, Clone, Debug, Miniconf, Deserialize)]
+/// in Hertz, `symmetry` specifies the normalized signal symmetry which ranges from 0 - 1.0
+/// (exclusive), `amplitude` specifies the signal amplitude in Volts, `phase` specifies the phase in
+/// turns, and `phase_offset` specifies the phase in turns at which the signal starts when the
+/// configuration is applied, which ranges from 0 - 1.0 (exclusive).
+#[derive(Copy, Clone, Debug, PartialEq, Miniconf, Deserialize)]
 pub struct BasicConfig {
     /// The signal type that should be generated. See [Signal] variants.
     pub signal: Signal,
@@ -28,16 +31,24 @@ pub struct BasicConfig {
     /// The frequency of the generated signal in Hertz.
     pub frequency: f32,
 
-    /// The normalized symmetry of the signal. At 0% symmetry, the duration of the first half oscillation is minimal.
-    /// At 25% symmetry, the first half oscillation lasts for 25% of the signal period. For square wave output this
-    /// symmetry is the duty cycle.
+    /// The normalized symmetry of the signal, which must lie between 0% and 100% (exclusive). At 25% symmetry,
+    /// the first half oscillation lasts for 25% of the signal period. For square wave output this symmetry is the
+    /// duty cycle. For triangle wave output, it selects the fraction of the period spent on the rising ramp, such
+    /// that symmetries close to 0% and 100% approach sawtooth waves. The symmetry is ignored for cosine and white
+    /// noise output.
     pub symmetry: f32,
 
     /// The amplitude of the output signal in volts.
     pub amplitude: f32,
 
-    /// The phase of the output signal in turns.
+    /// The phase of the output signal in turns. Converted into [Config::phase_offset].
     pub phase: f32,
+
+    /// The phase of the phase accumulator in turns at the time the configuration is applied. This
+    /// references the signal phase to the configuration update, such that applying the same
+    /// configuration always reproduces the same output phase. Converted into
+    /// [Config::initial_phase].
+    pub phase_offset: f32,
 }
 
 impl Default for BasicConfig {
@@ -48,6 +59,7 @@ impl Default for BasicConfig {
             signal: Signal::Cosine,
             amplitude: 0.0,
             phase: 0.0,
+            phase_offset: 0.0,
         }
     }
 }
@@ -61,6 +73,8 @@ pub enum Error {
     InvalidSymmetry,
     /// The provided frequency is out of range.
     InvalidFrequency,
+    /// The provided phase offset is out of range.
+    InvalidPhaseOffset,
 }
 
 impl BasicConfig {
@@ -74,12 +88,18 @@ impl BasicConfig {
         sample_period: f32,
         full_scale: f32,
     ) -> Result<Config, Error> {
-        let symmetry_complement = 1.0 - self.symmetry;
-        // Validate symmetry
-        if self.symmetry < 0.0 || symmetry_complement < 0.0 {
+        // Validate symmetry to be within the open interval (0, 1), rejecting NaN.
+        if !(self.symmetry > 0.0 && self.symmetry < 1.0) {
             return Err(Error::InvalidSymmetry);
         }
 
+        // The cosine is always generated symmetrically.
+        let symmetry = match self.signal {
+            Signal::Cosine | Signal::WhiteNoise => 0.5,
+            Signal::Square | Signal::Triangle => self.symmetry,
+        };
+        let symmetry_complement = 1.0 - symmetry;
+
         const NYQUIST: f32 = (1u32 << 31) as _;
         let ftw = self.frequency * sample_period * NYQUIST;
 
@@ -91,8 +111,8 @@ impl BasicConfig {
         // Calculate the frequency tuning words.
         // Clip both frequency tuning words to within Nyquist before rounding.
         let phase_increment = [
-            if self.symmetry * NYQUIST > ftw {
-                ftw / self.symmetry
+            if symmetry * NYQUIST > ftw {
+                ftw / symmetry
             } else {
                 NYQUIST
             } as i32,
@@ -110,11 +130,19 @@ impl BasicConfig {
 
         let phase = self.phase * (1u64 << 32) as f32;
 
+        // Validate the phase offset and map it onto the full phase accumulator range.
+        if !(0.0..1.0).contains(&self.phase_offset) {
+            return Err(Error::InvalidPhaseOffset);
+        }
+        let initial_phase =
+            (self.phase_offset * (1u64 << 32) as f32) as u32 as i32;
+
         Ok(Config {
             amplitude: amplitude as i16,
             signal: self.signal,
             phase_increment,
             phase_offset: phase as i32,
+            initial_phase,
         })
     }
 }
@@ -130,8 +158,12 @@ pub struct Config {
     /// The frequency tuning word of the signal. Phase is incremented by this amount
     pub phase_increment: [i32; 2],
 
-    /// The phase offset
+    /// The phase offset added to the phase accumulator. Converted from [BasicConfig::phase].
     pub phase_offset: i32,
+
+    /// The value of the phase accumulator when the configuration is applied. Converted from
+    /// [BasicConfig::phase_offset].
+    pub initial_phase: i32,
 }
 
 impl Default for Config {
@@ -141,6 +173,7 @@ impl Default for Config {
             amplitude: 0,
             phase_increment: [0, 0],
             phase_offset: 0,
+            initial_phase: 0,
         }
     }
 }
@@ -163,14 +196,18 @@ impl SignalGenerator {
     pub fn new(config: Config) -> Self {
         Self {
             config,
-            phase_accumulator: 0,
+            phase_accumulator: config.initial_phase,
             rng: XorShiftRng::from_seed([0; 16]), // zeros will initialize with XorShiftRng internal seed
         }
     }
 
     /// Update waveform generation settings.
+    ///
+    /// # Note
+    /// The phase accumulator is reset to the initial phase of the new configuration.
     pub fn update_waveform(&mut self, new_config: Config) {
         self.config = new_config;
+        self.phase_accumulator = new_config.initial_phase;
     }
 
     /// Clear the phase accumulator.
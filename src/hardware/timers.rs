@@ -11,6 +11,13 @@ use hal::stm32::{
     tim3 as __tim3,
 };
 
+/// Represents errors that can occur when configuring the timers.
+#[derive(Debug, Copy, Clone)]
+pub enum Error {
+    /// The requested value is out of range.
+    Bounds,
+}
+
 /// The event that should generate an external trigger from the peripheral.
 #[allow(dead_code)]
 pub enum TriggerGenerator {
@@ -399,3 +406,43 @@ timer_channels!(ShadowSamplingTimer, TIM3, u16);
 
 timer_channels!(TimestampTimer, TIM5, u32);
 timer_channels!(PounderTimestampTimer, TIM8, u16);
+
+impl SamplingTimer {
+    /// Reprogram the sample period of the running sampling timers.
+    ///
+    /// # Note
+    /// The sampling timer and the shadow sampling timer are paused, reprogrammed and restarted
+    /// together. Restarting the sampling timer triggers the start of the shadow sampling timer,
+    /// such that both timers remain in sync. The sample in progress is discarded.
+    ///
+    /// # Args
+    /// * `ticks` - The number of timer ticks between samples. Must be in the range [1, 2^16].
+    /// * `shadow` - The shadow sampling timer slaved to this timer.
+    ///
+    /// # Returns
+    /// Fails with [Error::Bounds] if `ticks` is out of range. The timers are left untouched in
+    /// that case.
+    pub fn set_period(
+        &mut self,
+        ticks: u32,
+        shadow: &mut ShadowSamplingTimer,
+    ) -> Result<(), Error> {
+        if !(1..=1 << 16).contains(&ticks) {
+            return Err(Error::Bounds);
+        }
+
+        self.timer.pause();
+        shadow.timer.pause();
+
+        shadow.set_period_ticks((ticks - 1) as u16);
+        shadow.timer.reset_counter();
+
+        self.set_period_ticks(ticks - 1);
+        self.timer.reset_counter();
+
+        // The shadow sampling timer is restarted by the trigger of the sampling timer.
+        self.timer.resume();
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+//! Stabilizer logging shim
+//!
+//! # Design
+//! By default, log messages are emitted using the [log] macros and transferred over RTT by the
+//! logger installed during setup. With the `defmt` feature, the macros of this module route log
+//! messages through `defmt` over RTT instead. This is significantly cheaper, as format strings are
+//! interned at build time and not transferred by the device.
+//!
+//! Log messages should thus be emitted using the macros of this module, e.g.
+//! `logging::info!("Starting")`, instead of the [log] macros directly. The macros accept the same
+//! arguments as the [log] macros. If both logging backends are available, `defmt` is used.
+//!
+//! # Note
+//! With `defmt`, only `{}` and `{:?}` placeholders are supported. All arguments are formatted
+//! using their `Debug` implementation on the device. Messages logged by dependencies using [log]
+//! are discarded, as no [log] logger is installed.
+#[cfg(not(feature = "defmt"))]
+pub use log::{debug, error, info, trace, warn};
+
+#[cfg(feature = "defmt")]
+pub use defmt;
+
+// Note: The global defmt logger is provided by `defmt-rtt`.
+#[cfg(feature = "defmt")]
+use defmt_rtt as _;
+
+#[cfg(feature = "defmt")]
+pub use crate::{
+    __defmt_debug as debug, __defmt_error as error, __defmt_info as info,
+    __defmt_trace as trace, __defmt_warn as warn,
+};
+
+/// Emit a log message at a given level using `defmt`. Every argument is wrapped such that it is
+/// formatted using its `Debug` implementation.
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt_log {
+    ($level:ident, $fmt:literal $(, $arg:expr)* $(,)?) => {
+        $crate::logging::defmt::$level!(
+            $fmt $(, $crate::logging::defmt::Debug2Format(&$arg))*
+        )
+    };
+}
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt_trace {
+    ($($arg:tt)+) => { $crate::__defmt_log!(trace, $($arg)+) };
+}
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt_debug {
+    ($($arg:tt)+) => { $crate::__defmt_log!(debug, $($arg)+) };
+}
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt_info {
+    ($($arg:tt)+) => { $crate::__defmt_log!(info, $($arg)+) };
+}
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt_warn {
+    ($($arg:tt)+) => { $crate::__defmt_log!(warn, $($arg)+) };
+}
+
+#[cfg(feature = "defmt")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __defmt_error {
+    ($($arg:tt)+) => { $crate::__defmt_log!(error, $($arg)+) };
+}
@@ -0,0 +1,118 @@
+//! MQTT reconnection backoff
+//!
+//! # Design
+//! The MQTT clients attempt to reconnect to the broker on every poll once a connection is lost.
+//! If the broker actively refuses connections, this results in a flood of connection attempts on
+//! the network.
+//!
+//! To prevent this, the network stack used by the MQTT clients is wrapped in a [BackoffStack],
+//! which only forwards TCP connection requests to the network stack after an exponentially
+//! increasing delay. Connection requests made during the delay are silently ignored, such that
+//! the MQTT client keeps trying to connect without blocking any other processing. The delay is
+//! reset once a connection is established.
+use minimq::embedded_time::{duration::Extensions, Clock, Instant};
+use smoltcp_nal::embedded_nal::{nb, SocketAddr, TcpClientStack};
+
+use super::NetworkReference;
+use crate::hardware::SystemTimer;
+
+// The delay after the first failed connection attempt.
+const INITIAL_DELAY_MS: u32 = 1_000;
+
+/// A network stack proxy that applies exponential backoff to TCP connection attempts.
+pub struct BackoffStack {
+    stack: NetworkReference,
+    clock: SystemTimer,
+    next_attempt: Option<Instant<SystemTimer>>,
+    delay_ms: u32,
+    max_delay_ms: u32,
+}
+
+impl BackoffStack {
+    /// Construct a new backoff proxy.
+    ///
+    /// # Args
+    /// * `stack` - The network stack to forward requests to.
+    /// * `clock` - A `SystemTimer` implementing `Clock`.
+    /// * `max_delay_ms` - The maximum delay between connection attempts in milliseconds.
+    pub fn new(
+        stack: NetworkReference,
+        clock: SystemTimer,
+        max_delay_ms: u32,
+    ) -> Self {
+        Self {
+            stack,
+            clock,
+            next_attempt: None,
+            delay_ms: INITIAL_DELAY_MS.min(max_delay_ms),
+            max_delay_ms,
+        }
+    }
+}
+
+impl TcpClientStack for BackoffStack {
+    type TcpSocket = <NetworkReference as TcpClientStack>::TcpSocket;
+    type Error = <NetworkReference as TcpClientStack>::Error;
+
+    fn socket(&mut self) -> Result<Self::TcpSocket, Self::Error> {
+        self.stack.socket()
+    }
+
+    fn connect(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        remote: SocketAddr,
+    ) -> nb::Result<(), Self::Error> {
+        // Note(unwrap): The system timer never fails to provide the current time.
+        let now = self.clock.try_now().unwrap();
+
+        // Ignore the request while waiting for the next attempt. The client will keep requesting
+        // a connection until one is established.
+        if let Some(next_attempt) = self.next_attempt {
+            if now < next_attempt {
+                return Ok(());
+            }
+        }
+
+        self.next_attempt
+            .replace(now + self.delay_ms.milliseconds());
+        self.delay_ms = self.delay_ms.saturating_mul(2).min(self.max_delay_ms);
+
+        self.stack.connect(socket, remote)
+    }
+
+    fn is_connected(
+        &mut self,
+        socket: &Self::TcpSocket,
+    ) -> Result<bool, Self::Error> {
+        let connected = self.stack.is_connected(socket)?;
+
+        // Reset the backoff once a connection has been established.
+        if connected {
+            self.next_attempt = None;
+            self.delay_ms = INITIAL_DELAY_MS.min(self.max_delay_ms);
+        }
+
+        Ok(connected)
+    }
+
+    fn send(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &[u8],
+    ) -> nb::Result<usize, Self::Error> {
+        self.stack.send(socket, buffer)
+    }
+
+    fn receive(
+        &mut self,
+        socket: &mut Self::TcpSocket,
+        buffer: &mut [u8],
+    ) -> nb::Result<usize, Self::Error> {
+        self.stack.receive(socket, buffer)
+    }
+
+    fn close(&mut self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+        self.stack.close(socket)
+    }
+}
@@ -16,34 +16,93 @@
 //! * **Format Code** <u8>: a unique ID that indicates the serialization format of each batch of data
 //!   in the frame. Refer to [StreamFormat] for further information.
 //! * **Batch Size** <u8>: the number of samples in each batch of data.
+//! * **Channel Mask** <u8>: the channels contained in each batch of data. Bit `n` is set if
+//!   channel `n` is streamed. The channel numbering is defined by the format, see [StreamFormat].
+//! * **AFE Gains** <u8>: the analog front-end gains of the ADC inputs when the batches of the frame
+//!   were taken. Bits 0-1 contain the gain code of ADC0 and bits 2-3 the gain code of ADC1. The
+//!   gain codes are the discriminants of [Gain] and the corresponding gains are given by
+//!   [Gain::as_multiplier]. An input voltage is obtained by dividing the ADC voltage by the gain.
 //! * **Sequence Number** <u32>: an the sequence number of the first batch in the frame.
 //!   This can be used to determine if and how many stream batches are lost.
+//! * **Frame Number** <u32>: a counter incremented for every generated frame. This can be used to
+//!   determine how many frames are lost in transmission. Frames that could not be generated due
+//!   to a lack of frame buffers are reported in telemetry instead.
+//! * **Timestamp** <u32>: the [SystemTimer] time in milliseconds at which the first batch of the
+//!   frame was added. The time is counted from boot and wraps around after 2^32 ms (about 49.7
+//!   days). Receivers can use the timestamp to coarsely correlate frames of different devices.
+//!   Sample-accurate alignment within a frame requires the sequence number.
+//! * **Length** <u16>: the number of bytes following the header.
+//! * **CRC** <u32>: the CRC-32 (as used by Ethernet and zlib) of the header up to and excluding
+//!   this field followed by the bytes after the header. This can be used to detect corrupted or
+//!   truncated frames.
+//!
+//! ## Byte Order
+//! The header and all batch data are little-endian. Applications serialize batches by copying
+//! the samples in memory order, which relies on the little-endian Cortex-M7 target. The byte
+//! order is fixed and thus not recorded in the header. Receivers on big-endian hosts or expecting
+//! network byte order must swap bytes accordingly.
 //!
 //! # Example
 //! A sample Python script is available in `scripts/stream_throughput.py` to demonstrate reception
 //! of livestreamed data.
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, Ordering};
 use heapless::{
     pool::{Box, Init, Pool, Uninit},
     spsc::{Consumer, Producer, Queue},
 };
-use miniconf::MiniconfAtomic;
+use miniconf::{Miniconf, MiniconfAtomic};
+use minimq::embedded_time::Clock;
 use num_enum::IntoPrimitive;
 use serde::{Deserialize, Serialize};
-use smoltcp_nal::embedded_nal::{IpAddr, Ipv4Addr, SocketAddr, UdpClientStack};
+use smoltcp_nal::{
+    embedded_nal::{nb, IpAddr, Ipv4Addr, SocketAddr, UdpClientStack},
+    NetworkError,
+};
+
+use super::{parse_build_setting, NetworkReference};
+use crate::{
+    hardware::{afe::Gain, hal::crc::Crc, SystemTimer},
+    logging,
+};
 
-use super::NetworkReference;
+// Batches are serialized by copying samples in memory order, which is only little-endian on a
+// little-endian target.
+#[cfg(target_endian = "big")]
+compile_error!("The data stream serialization requires a little-endian target");
 
 // Magic first bytes indicating a UDP frame of straming data
 const MAGIC: u16 = 0x057B;
 
 // The size of the header, calculated in words.
-// The header has a 16-bit magic word, an 8-bit format, 8-bit batch-size, and 32-bit sequence
-// number, which corresponds to 8 bytes.
-const HEADER_SIZE: usize = 8;
-
-// The number of frames that can be buffered.
-const FRAME_COUNT: usize = 4;
+// The header has a 16-bit magic word, an 8-bit format, 8-bit batch-size, 8-bit channel mask,
+// 8-bit AFE gains, 32-bit sequence number, 32-bit frame number, 32-bit timestamp, 16-bit length
+// and 32-bit CRC, which corresponds to 24 bytes.
+const HEADER_SIZE: usize = 24;
+
+// The offset of the length field within the header. The CRC field follows the length field.
+const LENGTH_OFFSET: usize = 18;
+const CRC_OFFSET: usize = LENGTH_OFFSET + 2;
+
+/// The default number of stream frames that can be buffered.
+pub const DEFAULT_STREAM_FRAME_COUNT: usize = 4;
+
+// The maximum number of stream frames that can be buffered.
+const MAX_STREAM_FRAME_COUNT: u32 = 32;
+
+// The number of frames that can be buffered. Frames are retained while the socket is momentarily
+// unable to accept them and transmitted in order once it is, such that brief network stalls do not
+// lose data. Each frame buffer occupies `FRAME_SIZE` bytes of static memory.
+//
+// The number of frames defaults to [DEFAULT_STREAM_FRAME_COUNT] and can be specified (1 to
+// `MAX_STREAM_FRAME_COUNT`) using the `STREAM_FRAME_COUNT` environment variable at build time.
+const FRAME_COUNT: usize = match option_env!("STREAM_FRAME_COUNT") {
+    Some(count) => match parse_build_setting(count, MAX_STREAM_FRAME_COUNT) {
+        0 => panic!("At least one stream frame must be buffered"),
+        count => count as usize,
+    },
+    None => DEFAULT_STREAM_FRAME_COUNT,
+};
 
 // The size of each livestream frame in bytes.
 // Ensure the resulting ethernet frame is within the MTU:
@@ -79,25 +138,121 @@ pub struct StreamTarget {
     pub port: u16,
 }
 
+impl From<SocketAddr> for StreamTarget {
+    fn from(remote: SocketAddr) -> StreamTarget {
+        let ip = match remote.ip() {
+            IpAddr::V4(ip) => ip.octets(),
+            // Note: IPv6 targets can not be configured.
+            IpAddr::V6(_) => [0; 4],
+        };
+
+        StreamTarget {
+            ip,
+            port: remote.port(),
+        }
+    }
+}
+
+/// The current configuration of the data stream.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct StreamConfig {
+    /// The format code of the streamed batches. See [StreamFormat].
+    pub format: u8,
+
+    /// The number of samples in each batch.
+    pub batch_size: u8,
+
+    /// The channels contained in each batch. Bit `n` is set if channel `n` is streamed.
+    pub channel_mask: u8,
+
+    /// The destination of the stream. The IP address is unspecified (`[0, 0, 0, 0]`) if no
+    /// target is configured.
+    pub target: StreamTarget,
+
+    /// Specified true if stream data is transmitted. See [DataStream::set_enabled].
+    pub enabled: bool,
+}
+
 /// Specifies the format of streamed data
+///
+/// # Miniconf
+/// The name of the format enclosed in double quotes, e.g. `"AdcDacData"`.
 #[repr(u8)]
-#[derive(Debug, Copy, Clone, PartialEq, IntoPrimitive)]
+#[derive(
+    Debug,
+    Copy,
+    Clone,
+    PartialEq,
+    IntoPrimitive,
+    Serialize,
+    Deserialize,
+    Miniconf,
+)]
 pub enum StreamFormat {
     /// Reserved, unused format specifier.
     Unknown = 0,
 
     /// Streamed data contains ADC0, ADC1, DAC0, and DAC1 sequentially in little-endian format.
     ///
+    /// The channels are numbered ADC0 (0), ADC1 (1), DAC0 (2), and DAC1 (3) in the channel mask.
+    /// Channels not contained in the channel mask are omitted.
+    ///
     /// # Example
     /// With a batch size of 2, the serialization would take the following form:
     /// ```
     /// <ADC0[0]> <ADC0[1]> <ADC1[0]> <ADC1[1]> <DAC0[0]> <DAC0[1]> <DAC1[0]> <DAC1[1]>
     /// ```
+    ///
+    /// With a channel mask of `0b0101`, only ADC0 and DAC0 are streamed:
+    /// ```
+    /// <ADC0[0]> <ADC0[1]> <DAC0[0]> <DAC0[1]>
+    /// ```
     AdcDacData = 1,
 
     /// Streamed data in FLS (fiber length stabilization) format. See the FLS application for
     /// detailed definition.
     Fls = 2,
+
+    /// Streamed data contains the ADC and DAC channels as in [StreamFormat::AdcDacData],
+    /// followed by the IIR filter state of each channel at the end of the batch.
+    ///
+    /// The state of each IIR biquad is serialized as five little-endian `f32` values
+    /// `[x0, x1, y0, y1, y2]`, where `x` are the most recent filter inputs and `y` the most recent
+    /// filter outputs in ADC/DAC codes. The states of all cascaded biquads of channel 0 precede
+    /// those of channel 1.
+    ///
+    /// # Example
+    /// With a batch size of 2 and a single biquad per channel, the serialization would take the
+    /// following form:
+    /// ```
+    /// <ADC0[0]> <ADC0[1]> <ADC1[0]> <ADC1[1]> <DAC0[0]> <DAC0[1]> <DAC1[0]> <DAC1[1]>
+    /// <IIR0[0..5]> <IIR1[0..5]>
+    /// ```
+    IirState = 3,
+
+    /// Streamed data contains a one-shot capture of ADC0, ADC1, DAC0, and DAC1 in the layout of
+    /// [StreamFormat::AdcDacData] with all channels contained.
+    ///
+    /// # Note
+    /// The frames of a capture are streamed with consecutive sequence numbers. The first frame of
+    /// a capture starts with the first captured batch and the last frame ends with the last
+    /// captured batch.
+    Capture = 4,
+
+    /// Streamed data contains demodulated complex samples as pairs of little-endian `f32` values,
+    /// the in-phase (I) component followed by the quadrature (Q) component.
+    ///
+    /// The samples are channel-major: all samples of a batch of the first contained channel
+    /// precede those of the next contained channel. The values are in the machine units of the
+    /// demodulated channel, e.g. ADC codes. The channel numbering is application-defined.
+    ///
+    /// # Example
+    /// With a batch size of 2 and a channel mask of `0b11`, the serialization would take the
+    /// following form:
+    /// ```
+    /// <I0[0]> <Q0[0]> <I0[1]> <Q0[1]> <I1[0]> <Q1[0]> <I1[1]> <Q1[1]>
+    /// ```
+    IqData = 5,
 }
 
 impl From<StreamTarget> for SocketAddr {
@@ -118,12 +273,16 @@ impl From<StreamTarget> for SocketAddr {
 ///
 /// # Args
 /// * `stack` - A reference to the shared network stack.
+/// * `crc` - The CRC unit configured for CRC-32, used to checksum each frame.
+/// * `clock` - A `SystemTimer` implementing `Clock`, used to timestamp each frame.
 ///
 /// # Returns
 /// (generator, stream) where `generator` can be used to enqueue "batches" for transmission. The
 /// `stream` is the logically consumer (UDP transmitter) of the enqueued data.
 pub fn setup_streaming(
     stack: NetworkReference,
+    crc: Crc,
+    clock: SystemTimer,
 ) -> (FrameGenerator, DataStream) {
     // The queue needs to be at least as large as the frame count to ensure that every allocated
     // frame can potentially be enqueued for transmission.
@@ -134,13 +293,17 @@ pub fn setup_streaming(
 
     let frame_pool = cortex_m::singleton!(: Pool<Frame> = Pool::new()).unwrap();
 
+    // The format, batch size and channel mask configured by the generator, reported by the
+    // stream.
+    let config = cortex_m::singleton!(: AtomicU32 = AtomicU32::new(0)).unwrap();
+
     // Note(unsafe): We guarantee that FRAME_DATA is only accessed once in this function.
     let memory = unsafe { &mut FRAME_DATA };
     frame_pool.grow(memory);
 
-    let generator = FrameGenerator::new(producer, frame_pool);
+    let generator = FrameGenerator::new(producer, frame_pool, clock, config);
 
-    let stream = DataStream::new(stack, consumer, frame_pool);
+    let stream = DataStream::new(stack, consumer, frame_pool, crc, config);
 
     (generator, stream)
 }
@@ -149,22 +312,30 @@ pub fn setup_streaming(
 struct StreamFrame {
     buffer: Box<Frame, Init>,
     offset: usize,
+    finished: bool,
 }
 
 impl StreamFrame {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         buffer: Box<Frame, Uninit>,
         format_id: u8,
         batch_size: u8,
+        channel_mask: u8,
+        afe_gains: u8,
         sequence_number: u32,
+        frame_number: u32,
+        timestamp: u32,
     ) -> Self {
         let mut buffer = buffer.init([MaybeUninit::uninit(); FRAME_SIZE]);
 
         for (offset, byte) in MAGIC
             .to_le_bytes()
             .iter()
-            .chain(&[format_id, batch_size])
+            .chain(&[format_id, batch_size, channel_mask, afe_gains])
             .chain(sequence_number.to_le_bytes().iter())
+            .chain(frame_number.to_le_bytes().iter())
+            .chain(timestamp.to_le_bytes().iter())
             .enumerate()
         {
             buffer[offset].write(*byte);
@@ -173,6 +344,7 @@ impl StreamFrame {
         Self {
             buffer,
             offset: HEADER_SIZE,
+            finished: false,
         }
     }
 
@@ -189,8 +361,61 @@ impl StreamFrame {
         self.offset + T > self.buffer.len()
     }
 
-    pub fn finish(&self) -> &[MaybeUninit<u8>] {
-        &self.buffer[..self.offset]
+    /// Complete the frame header and get the frame data.
+    ///
+    /// # Note
+    /// The header is only completed once. A frame that is finished again, e.g. when retrying its
+    /// transmission, is not checksummed again.
+    ///
+    /// # Args
+    /// * `crc` - The CRC unit used to checksum the frame.
+    ///
+    /// # Returns
+    /// The serialized frame.
+    pub fn finish(&mut self, crc: &mut Crc) -> &[u8] {
+        if !self.finished {
+            self.complete_header(crc);
+        }
+
+        // Note(unsafe): All bytes up to the offset are initialized once the header is completed.
+        unsafe {
+            &*(&self.buffer[..self.offset] as *const [MaybeUninit<u8>]
+                as *const [u8])
+        }
+    }
+
+    // Write the length and checksum of the frame into the header.
+    fn complete_header(&mut self, crc: &mut Crc) {
+        let length = (self.offset - HEADER_SIZE) as u16;
+        for (byte, value) in self.buffer[LENGTH_OFFSET..CRC_OFFSET]
+            .iter_mut()
+            .zip(length.to_le_bytes())
+        {
+            byte.write(value);
+        }
+
+        // Note(unsafe): All bytes up to the offset are initialized, except for the CRC field,
+        // which is excluded from the checksum.
+        let (header, data) = self.buffer[..self.offset].split_at(HEADER_SIZE);
+        let (header, data) = unsafe {
+            (
+                &*(&header[..CRC_OFFSET] as *const [MaybeUninit<u8>]
+                    as *const [u8]),
+                &*(data as *const [MaybeUninit<u8>] as *const [u8]),
+            )
+        };
+        crc.update(header);
+        crc.update(data);
+        let checksum = crc.finish();
+
+        for (byte, value) in self.buffer[CRC_OFFSET..HEADER_SIZE]
+            .iter_mut()
+            .zip(checksum.to_le_bytes())
+        {
+            byte.write(value);
+        }
+
+        self.finished = true;
     }
 }
 
@@ -198,24 +423,40 @@ impl StreamFrame {
 pub struct FrameGenerator {
     queue: Producer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
     pool: &'static Pool<Frame>,
+    clock: SystemTimer,
     current_frame: Option<StreamFrame>,
     sequence_number: u32,
+    frame_number: u32,
+    dropped_batches: u32,
+    dropped_frames: u32,
     format: u8,
     batch_size: u8,
+    channel_mask: u8,
+    afe_gains: u8,
+    config: &'static AtomicU32,
 }
 
 impl FrameGenerator {
     fn new(
         queue: Producer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
         pool: &'static Pool<Frame>,
+        clock: SystemTimer,
+        config: &'static AtomicU32,
     ) -> Self {
         Self {
             queue,
             pool,
+            clock,
+            config,
             batch_size: 0,
+            channel_mask: 0,
+            afe_gains: 0,
             format: StreamFormat::Unknown.into(),
             current_frame: None,
             sequence_number: 0,
+            frame_number: 0,
+            dropped_batches: 0,
+            dropped_frames: 0,
         }
     }
 
@@ -228,10 +469,87 @@ impl FrameGenerator {
     /// * `format` - The desired format of the stream.
     /// * `batch_size` - The number of samples in each data batch. See
     /// [crate::hardware::design_parameters::SAMPLE_BUFFER_SIZE]
+    /// * `channel_mask` - The channels contained in each data batch. Bit `n` is set if channel
+    /// `n` of the format is streamed.
     #[doc(hidden)]
-    pub(crate) fn configure(&mut self, format: impl Into<u8>, batch_size: u8) {
+    pub(crate) fn configure(
+        &mut self,
+        format: impl Into<u8>,
+        batch_size: u8,
+        channel_mask: u8,
+    ) {
         self.format = format.into();
         self.batch_size = batch_size;
+        self.channel_mask = channel_mask;
+
+        self.config.store(
+            u32::from_le_bytes([self.format, batch_size, channel_mask, 0]),
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Reconfigure the format of the stream at run-time.
+    ///
+    /// # Note
+    /// Batches added before the reconfiguration are completed into a frame of the previous
+    /// format, such that every frame only contains batches of a single format. This should be
+    /// called on a batch boundary, i.e. from the context that adds batches to the stream.
+    ///
+    /// # Args
+    /// * `format` - The desired format of the stream.
+    /// * `batch_size` - The number of samples in each data batch.
+    /// * `channel_mask` - The channels contained in each data batch.
+    pub fn reconfigure(
+        &mut self,
+        format: impl Into<u8>,
+        batch_size: u8,
+        channel_mask: u8,
+    ) {
+        if let Some(frame) = self.current_frame.take() {
+            // Note(unwrap): The queue is designed to be at least as large as the frame buffer
+            // count, so this enqueue should always succeed.
+            self.queue.enqueue(frame).unwrap();
+        }
+
+        self.configure(format, batch_size, channel_mask);
+    }
+
+    /// Update the AFE gains reported in the frame header.
+    ///
+    /// # Note
+    /// If the gains change, batches added before are completed into a frame carrying the previous
+    /// gains, such that the gains in the header apply to every batch of the frame. This should be
+    /// called on a batch boundary, i.e. from the context that adds batches to the stream.
+    ///
+    /// # Args
+    /// * `gains` - The gains of the ADC0 and ADC1 analog front-ends.
+    pub fn set_afe_gains(&mut self, gains: [Gain; 2]) {
+        let afe_gains = gains[0] as u8 | (gains[1] as u8) << 2;
+        if afe_gains == self.afe_gains {
+            return;
+        }
+
+        if let Some(frame) = self.current_frame.take() {
+            // Note(unwrap): The queue is designed to be at least as large as the frame buffer
+            // count, so this enqueue should always succeed.
+            self.queue.enqueue(frame).unwrap();
+        }
+
+        self.afe_gains = afe_gains;
+    }
+
+    /// Get the current format of the stream.
+    pub fn format(&self) -> u8 {
+        self.format
+    }
+
+    /// Get the number of frames that could not be generated since no frame buffer was available.
+    ///
+    /// # Note
+    /// Frames are dropped if they are generated faster than they can be transmitted. The count
+    /// wraps around on overflow.
+    pub fn dropped_frames(&self) -> u32 {
+        self.dropped_frames
     }
 
     /// Add a batch to the current stream frame.
@@ -248,13 +566,32 @@ impl FrameGenerator {
 
         if self.current_frame.is_none() {
             if let Some(buffer) = self.pool.alloc() {
+                // Note(unwrap): The system timer never fails to provide the current time.
+                let timestamp = self
+                    .clock
+                    .try_now()
+                    .unwrap()
+                    .duration_since_epoch()
+                    .integer();
                 self.current_frame.replace(StreamFrame::new(
                     buffer,
                     self.format as u8,
                     self.batch_size,
+                    self.channel_mask,
+                    self.afe_gains,
                     sequence_number,
+                    self.frame_number,
+                    timestamp,
                 ));
+                self.frame_number = self.frame_number.wrapping_add(1);
+                self.dropped_batches = 0;
             } else {
+                // Count a dropped frame for every frame worth of consecutively dropped batches.
+                let batches_per_frame = ((FRAME_SIZE - HEADER_SIZE) / T) as u32;
+                if self.dropped_batches % batches_per_frame == 0 {
+                    self.dropped_frames = self.dropped_frames.wrapping_add(1);
+                }
+                self.dropped_batches = self.dropped_batches.wrapping_add(1);
                 return;
             }
         }
@@ -284,6 +621,10 @@ pub struct DataStream {
     queue: Consumer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
     frame_pool: &'static Pool<Frame>,
     remote: SocketAddr,
+    crc: Crc,
+    enabled: bool,
+    pending: Option<StreamFrame>,
+    config: &'static AtomicU32,
 }
 
 impl DataStream {
@@ -293,10 +634,14 @@ impl DataStream {
     /// * `stack` - A reference to the shared network stack.
     /// * `consumer` - The read side of the queue containing data to transmit.
     /// * `frame_pool` - The Pool to return stream frame objects into.
+    /// * `crc` - The CRC unit used to checksum frames.
+    /// * `config` - The stream format configuration shared with the generator.
     fn new(
         stack: NetworkReference,
         consumer: Consumer<'static, StreamFrame, FRAME_QUEUE_SIZE>,
         frame_pool: &'static Pool<Frame>,
+        crc: Crc,
+        config: &'static AtomicU32,
     ) -> Self {
         Self {
             stack,
@@ -304,12 +649,44 @@ impl DataStream {
             remote: StreamTarget::default().into(),
             queue: consumer,
             frame_pool,
+            crc,
+            enabled: true,
+            pending: None,
+            config,
+        }
+    }
+
+    /// Get the current configuration of the stream.
+    ///
+    /// # Note
+    /// The format, batch size and channel mask are those most recently configured on the
+    /// [FrameGenerator].
+    pub fn config(&self) -> StreamConfig {
+        let [format, batch_size, channel_mask, _] =
+            self.config.load(Ordering::Relaxed).to_le_bytes();
+
+        StreamConfig {
+            format,
+            batch_size,
+            channel_mask,
+            target: self.remote.into(),
+            enabled: self.enabled,
+        }
+    }
+
+    /// Return all buffered frames to the pool.
+    fn flush(&mut self) {
+        if let Some(frame) = self.pending.take() {
+            self.frame_pool.free(frame.buffer);
+        }
+        while let Some(frame) = self.queue.dequeue() {
+            self.frame_pool.free(frame.buffer);
         }
     }
 
     fn close(&mut self) {
         if let Some(socket) = self.socket.take() {
-            log::info!("Closing stream");
+            logging::info!("Closing stream");
             // Note(unwrap): We guarantee that the socket is available above.
             self.stack.close(socket).unwrap();
         }
@@ -323,7 +700,7 @@ impl DataStream {
             return Err(());
         }
 
-        log::info!("Opening stream");
+        logging::info!("Opening stream");
 
         let mut socket = self.stack.socket().or(Err(()))?;
 
@@ -348,30 +725,50 @@ impl DataStream {
         self.remote = remote;
     }
 
+    /// Pause or resume the transmission of stream data.
+    ///
+    /// # Note
+    /// While paused, generated frames are discarded. The remote endpoint is retained, such that
+    /// transmission resumes immediately.
+    ///
+    /// # Args
+    /// * `enabled` - Specifies whether stream data is transmitted.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
     /// Process any data for transmission.
     pub fn process(&mut self) {
+        if !self.enabled {
+            self.flush();
+            return;
+        }
+
         match self.socket.as_mut() {
             None => {
                 // If there's no socket available, try to connect to our remote.
                 if self.open().is_ok() {
                     // If we just successfully opened the socket, flush old data from queue.
-                    while let Some(frame) = self.queue.dequeue() {
-                        self.frame_pool.free(frame.buffer);
-                    }
+                    self.flush();
                 }
             }
             Some(handle) => {
-                if let Some(frame) = self.queue.dequeue() {
-                    // Transmit the frame and return it to the pool.
-                    let buf = frame.finish();
-                    let data = unsafe {
-                        core::slice::from_raw_parts(
-                            buf.as_ptr() as *const u8,
-                            buf.len() * core::mem::size_of::<MaybeUninit<u8>>(),
-                        )
-                    };
-                    self.stack.send(handle, data).ok();
-                    self.frame_pool.free(frame.buffer)
+                // A frame that could not be sent previously is transmitted before newer frames.
+                if let Some(mut frame) =
+                    self.pending.take().or_else(|| self.queue.dequeue())
+                {
+                    let data = frame.finish(&mut self.crc);
+                    match self.stack.send(handle, data) {
+                        // The socket buffer is full. Retain the finished frame until there is
+                        // space. New frames are dropped by the generator once all frame buffers
+                        // are in use.
+                        Err(nb::Error::WouldBlock)
+                        | Err(nb::Error::Other(NetworkError::WriteFailure)) => {
+                            self.pending.replace(frame);
+                        }
+                        // Return the frame to the pool.
+                        _ => self.frame_pool.free(frame.buffer),
+                    }
                 }
             }
         }
@@ -9,15 +9,20 @@ pub use heapless;
 pub use miniconf;
 pub use serde;
 
+pub mod backoff;
 pub mod data_stream;
 pub mod network_processor;
 pub mod telemetry;
 
-use crate::hardware::{EthernetPhy, NetworkManager, NetworkStack, SystemTimer};
-use data_stream::{DataStream, FrameGenerator};
+use crate::hardware::{
+    hal::crc::Crc, EthernetPhy, NetworkManager, NetworkStack, SystemTimer,
+};
+use crate::logging;
+use backoff::BackoffStack;
+use data_stream::{DataStream, FrameGenerator, StreamConfig};
 use minimq::embedded_nal::IpAddr;
 use network_processor::NetworkProcessor;
-use telemetry::TelemetryClient;
+use telemetry::{SettingsVersion, TelemetryClient};
 
 use core::fmt::Write;
 use heapless::String;
@@ -31,6 +36,35 @@ pub type NetworkReference =
 /// The default MQTT broker IP address if unspecified.
 pub const DEFAULT_MQTT_BROKER: [u8; 4] = [10, 34, 16, 10];
 
+/// The default root of the MQTT topics of all devices if unspecified.
+pub const DEFAULT_TOPIC_ROOT: &str = "dt/sinara";
+
+/// The default maximum delay between MQTT broker connection attempts in seconds.
+pub const DEFAULT_MQTT_BACKOFF_MAX: u32 = 60;
+
+/// The maximum delay between MQTT broker connection attempts in milliseconds. The delay may be
+/// specified in seconds using the `MQTT_BACKOFF_MAX` environment variable at build time.
+const MQTT_BACKOFF_MAX_MS: u32 = match option_env!("MQTT_BACKOFF_MAX") {
+    Some(max) => parse_build_setting(max, u32::MAX / 1_000) * 1_000,
+    None => DEFAULT_MQTT_BACKOFF_MAX * 1_000,
+};
+
+/// The default MQTT keep-alive interval in seconds.
+pub const DEFAULT_MQTT_KEEPALIVE: u16 = 60;
+
+/// The MQTT keep-alive interval of the telemetry client in seconds. The interval may be specified
+/// using the `MQTT_KEEPALIVE` environment variable at build time.
+pub const MQTT_KEEPALIVE: u16 = match option_env!("MQTT_KEEPALIVE") {
+    Some(keepalive) => parse_build_setting(keepalive, u16::MAX as u32) as u16,
+    None => DEFAULT_MQTT_KEEPALIVE,
+};
+
+/// The settings path that restores the default settings when set to `true`.
+///
+/// # Note
+/// Applications opting in must provide a boolean setting at this path.
+pub const RESET_TO_DEFAULT_PATH: &str = "reset_to_default";
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum UpdateState {
     NoChange,
@@ -45,11 +79,12 @@ pub enum NetworkState {
 
 /// A structure of Stabilizer's default network users.
 pub struct NetworkUsers<S: Default + Miniconf + Clone, T: Serialize> {
-    pub miniconf: miniconf::MqttClient<S, NetworkReference, SystemTimer, 512>,
+    pub miniconf: miniconf::MqttClient<S, BackoffStack, SystemTimer, 512>,
     pub processor: NetworkProcessor,
     stream: DataStream,
     generator: Option<FrameGenerator>,
     pub telemetry: TelemetryClient<T>,
+    settings_version: SettingsVersion,
 }
 
 impl<S, T> NetworkUsers<S, T>
@@ -62,20 +97,32 @@ where
     /// # Args
     /// * `stack` - The network stack that will be used to share with all network users.
     /// * `phy` - The ethernet PHY connecting the network.
+    /// * `crc` - The CRC unit used for checksumming stream frames.
     /// * `clock` - A `SystemTimer` implementing `Clock`.
     /// * `app` - The name of the application.
     /// * `mac` - The MAC address of the network.
     /// * `broker` - The IP address of the MQTT broker to use.
+    /// * `keepalive` - The MQTT keep-alive interval of the telemetry client in seconds. See
+    ///   [MQTT_KEEPALIVE].
+    ///
+    /// # Note
+    /// The broker may override the requested keep-alive interval. Miniconf does not allow
+    /// configuring the settings client, which always uses a keep-alive interval of 60 seconds.
+    /// The MQTT client does not allow configuring the session expiry, so the sessions of both
+    /// clients never expire.
     ///
     /// # Returns
     /// A new struct of network users.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         stack: NetworkStack,
         phy: EthernetPhy,
+        crc: Crc,
         clock: SystemTimer,
         app: &str,
         mac: smoltcp_nal::smoltcp::wire::EthernetAddress,
         broker: IpAddr,
+        keepalive: u16,
     ) -> Self {
         let stack_manager =
             cortex_m::singleton!(: NetworkManager = NetworkManager::new(stack))
@@ -87,7 +134,11 @@ where
         let prefix = get_device_prefix(app, mac);
 
         let settings = miniconf::MqttClient::new(
-            stack_manager.acquire_stack(),
+            BackoffStack::new(
+                stack_manager.acquire_stack(),
+                clock,
+                MQTT_BACKOFF_MAX_MS,
+            ),
             &get_client_id(app, "settings", mac),
             &prefix,
             broker,
@@ -97,15 +148,23 @@ where
         .unwrap();
 
         let telemetry = TelemetryClient::new(
-            stack_manager.acquire_stack(),
+            BackoffStack::new(
+                stack_manager.acquire_stack(),
+                clock,
+                MQTT_BACKOFF_MAX_MS,
+            ),
             clock,
             &get_client_id(app, "tlm", mac),
             &prefix,
             broker,
+            keepalive,
         );
 
-        let (generator, stream) =
-            data_stream::setup_streaming(stack_manager.acquire_stack());
+        let (generator, stream) = data_stream::setup_streaming(
+            stack_manager.acquire_stack(),
+            crc,
+            clock,
+        );
 
         NetworkUsers {
             miniconf: settings,
@@ -113,6 +172,7 @@ where
             telemetry,
             stream,
             generator: Some(generator),
+            settings_version: SettingsVersion::default(),
         }
     }
 
@@ -120,13 +180,16 @@ where
     ///
     /// # Args
     /// * `format` - A unique u8 code indicating the format of the data.
+    /// * `batch_size` - The number of samples in each data batch.
+    /// * `channel_mask` - The channels contained in each data batch, recorded in the frame header.
     pub fn configure_streaming(
         &mut self,
         format: impl Into<u8>,
         batch_size: u8,
+        channel_mask: u8,
     ) -> FrameGenerator {
         let mut generator = self.generator.take().unwrap();
-        generator.configure(format, batch_size);
+        generator.configure(format, batch_size, channel_mask);
         generator
     }
 
@@ -140,6 +203,24 @@ where
         }
     }
 
+    /// Pause or resume the data stream.
+    ///
+    /// # Args
+    /// * `enabled` - Specifies whether stream data is transmitted. See [DataStream::set_enabled].
+    pub fn set_stream_enabled(&mut self, enabled: bool) {
+        self.stream.set_enabled(enabled);
+    }
+
+    /// Get the current configuration and target of the data stream.
+    pub fn stream_config(&self) -> StreamConfig {
+        self.stream.config()
+    }
+
+    /// Get the number of settings changes applied since boot and the most recently changed path.
+    pub fn settings_version(&self) -> &SettingsVersion {
+        &self.settings_version
+    }
+
     /// Update and process all of the network users state.
     ///
     /// # Returns
@@ -161,12 +242,33 @@ where
         };
 
         let mut settings_path = String::new();
-        match self.miniconf.handled_update(|path, old, new| {
+        let mut reset = false;
+        let result = self.miniconf.handled_update(|path, old, new| {
             settings_path.push_str(path).unwrap();
-            *old = new.clone();
+
+            let mut value = [0; 5];
+            reset = path == RESET_TO_DEFAULT_PATH
+                && matches!(new.get(path, &mut value), Ok(4) if &value[..4] == b"true");
+
+            *old = if reset { S::default() } else { new.clone() };
             Result::<(), &'static str>::Ok(())
-        }) {
-            Ok(true) => NetworkState::SettingsChanged(settings_path),
+        });
+
+        // Publish the restored defaults so that clients observe the actual settings. The
+        // republished settings are not retained and thus do not replace any retained settings
+        // on the broker.
+        if reset {
+            logging::info!("Restoring default settings");
+            self.miniconf.force_republish();
+        }
+
+        match result {
+            Ok(true) => {
+                self.settings_version.version =
+                    self.settings_version.version.wrapping_add(1);
+                self.settings_version.path = settings_path.clone();
+                NetworkState::SettingsChanged(settings_path)
+            }
             _ => poll_result,
         }
     }
@@ -193,6 +295,10 @@ fn get_client_id(
 
 /// Get the MQTT prefix of a device.
 ///
+/// # Note
+/// The prefix is of the form `<root>/<app>/<mac>`. The root defaults to [DEFAULT_TOPIC_ROOT] and
+/// can be specified using the `TOPIC_ROOT` environment variable during the firmware build.
+///
 /// # Args
 /// * `app` - The name of the application that is executing.
 /// * `mac` - The ethernet MAC address of the device.
@@ -206,7 +312,51 @@ pub fn get_device_prefix(
     // Note(unwrap): The mac address + binary name must be short enough to fit into this string. If
     // they are defined too long, this will panic and the device will fail to boot.
     let mut prefix: String<128> = String::new();
-    write!(&mut prefix, "dt/sinara/{}/{}", app, mac).unwrap();
+    let root = option_env!("TOPIC_ROOT").unwrap_or(DEFAULT_TOPIC_ROOT);
+    write!(&mut prefix, "{}/{}/{}", root, app, mac).unwrap();
 
     prefix
 }
+
+/// Parse a decimal build setting at compile time.
+///
+/// # Note
+/// This panics on an empty, non-decimal or out-of-range value, which fails the build when
+/// evaluated in a const context.
+///
+/// # Args
+/// * `value` - The value of the build setting.
+/// * `max` - The maximum accepted value.
+///
+/// # Returns
+/// The parsed value.
+pub const fn parse_build_setting(value: &str, max: u32) -> u32 {
+    let digits = value.as_bytes();
+    if digits.is_empty() {
+        panic!("Build setting must not be empty");
+    }
+
+    let mut parsed: u32 = 0;
+    let mut i = 0;
+    while i < digits.len() {
+        let digit = digits[i];
+        if !digit.is_ascii_digit() {
+            panic!("Build setting must be a decimal number");
+        }
+
+        parsed = match parsed.checked_mul(10) {
+            Some(value) => match value.checked_add((digit - b'0') as u32) {
+                Some(value) => value,
+                None => panic!("Build setting is out of range"),
+            },
+            None => panic!("Build setting is out of range"),
+        };
+        i += 1;
+    }
+
+    if parsed > max {
+        panic!("Build setting is out of range");
+    }
+
+    parsed
+}
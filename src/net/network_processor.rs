@@ -4,13 +4,80 @@
 ///! The network processir is a small taks to regularly process incoming data over ethernet, handle
 ///! the ethernet PHY state, and reset the network as appropriate.
 use super::{NetworkReference, UpdateState};
-use crate::hardware::EthernetPhy;
+use crate::{hardware::EthernetPhy, logging};
+
+use miniconf::MiniconfAtomic;
+use serde::{Deserialize, Serialize};
+use smoltcp_nal::smoltcp::wire::{IpCidr, Ipv4Address, Ipv4Cidr};
+
+/// Represents a static IPv4 network configuration.
+///
+/// # Miniconf
+/// `{"ip": <addr>, "prefix_length": <prefix>, "gateway": <gateway>}`
+///
+/// * `<addr>` is an array of 4 bytes. E.g. `[192, 168, 0, 2]`. An unspecified address (all
+///   zeros) leaves the boot-time network configuration untouched.
+/// * `<prefix>` is the subnet prefix length in bits. E.g. `24` for a netmask of `255.255.255.0`.
+///   Prefix lengths above 32 are rejected.
+/// * `<gateway>` is an array of 4 bytes specifying the default gateway. An unspecified gateway
+///   removes the default route.
+///
+/// ## Example
+/// `{"ip": [192, 168, 0, 2], "prefix_length": 24, "gateway": [192, 168, 0, 1]}`
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    MiniconfAtomic,
+    Serialize,
+    Deserialize,
+)]
+pub struct StaticIpConfig {
+    pub ip: [u8; 4],
+    pub prefix_length: u8,
+    pub gateway: [u8; 4],
+}
+
+/// The state of the ethernet link as negotiated by the PHY.
+///
+/// # Note
+/// The PHY driver only reports an established link once 100BASE-TX full duplex has been
+/// negotiated with the link partner. Any other negotiation result is reported as a link down.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LinkState {
+    /// Specified true if the ethernet link is established.
+    pub up: bool,
+
+    /// The negotiated link speed in Mbps. Zero if the link is down.
+    pub speed: u16,
+
+    /// Specified true if the link operates in full duplex mode.
+    pub full_duplex: bool,
+}
+
+impl LinkState {
+    const DOWN: Self = Self {
+        up: false,
+        speed: 0,
+        full_duplex: false,
+    };
+
+    const UP_100BASE_FD: Self = Self {
+        up: true,
+        speed: 100,
+        full_duplex: true,
+    };
+}
 
 /// Processor for managing network hardware.
 pub struct NetworkProcessor {
     pub stack: NetworkReference,
     phy: EthernetPhy,
     network_was_reset: bool,
+    link: LinkState,
+    static_ip: StaticIpConfig,
 }
 
 impl NetworkProcessor {
@@ -27,7 +94,72 @@ impl NetworkProcessor {
             stack,
             phy,
             network_was_reset: false,
+            link: LinkState::DOWN,
+            static_ip: StaticIpConfig::default(),
+        }
+    }
+
+    /// Apply a static IPv4 configuration to the network interface.
+    ///
+    /// # Note
+    /// Changing the address closes all open sockets, so the MQTT clients will reconnect to the
+    /// broker using the new address. The configuration is only applied when it differs from the
+    /// currently applied one. An unspecified address is ignored and the boot-time configuration
+    /// (DHCP or `STATIC_IP`) is kept. A configuration with a prefix length above 32 is logged and
+    /// ignored.
+    ///
+    /// If the firmware was built without `STATIC_IP`, a later DHCP lease change may override the
+    /// static address again.
+    ///
+    /// # Args
+    /// * `config` - The desired static IPv4 configuration.
+    pub fn set_static_ip(&mut self, config: StaticIpConfig) {
+        if config == self.static_ip {
+            return;
+        }
+
+        let address = Ipv4Address::from_bytes(&config.ip);
+        if address.is_unspecified() {
+            return;
+        }
+
+        if config.prefix_length > 32 {
+            logging::error!(
+                "Invalid static IP prefix length: {}",
+                config.prefix_length
+            );
+            return;
         }
+
+        logging::info!(
+            "Applying static IP {}/{}",
+            address,
+            config.prefix_length
+        );
+
+        self.stack.lock(|stack| {
+            let interface = stack.interface_mut();
+            interface.update_ip_addrs(|addrs| {
+                addrs[0] =
+                    IpCidr::Ipv4(Ipv4Cidr::new(address, config.prefix_length));
+            });
+
+            let gateway = Ipv4Address::from_bytes(&config.gateway);
+            if gateway.is_unspecified() {
+                interface.routes_mut().remove_default_ipv4_route();
+            } else if interface
+                .routes_mut()
+                .add_default_ipv4_route(gateway)
+                .is_err()
+            {
+                logging::warn!("Failed to add default route");
+            }
+
+            // Existing connections were bound to the previous address.
+            stack.close_sockets();
+        });
+
+        self.static_ip = config;
     }
 
     /// Handle ethernet link connection status.
@@ -39,15 +171,21 @@ impl NetworkProcessor {
         // If the PHY indicates there's no more ethernet link, reset the DHCP server in the network
         // stack.
         let link_up = self.phy.poll_link();
+        self.link = if link_up {
+            LinkState::UP_100BASE_FD
+        } else {
+            LinkState::DOWN
+        };
+
         match (link_up, self.network_was_reset) {
             (true, true) => {
-                log::warn!("Network link UP");
+                logging::warn!("Network link UP");
                 self.network_was_reset = false;
             }
             // Only reset the network stack once per link reconnection. This prevents us from
             // sending an excessive number of DHCP requests.
             (false, false) => {
-                log::warn!("Network link DOWN");
+                logging::warn!("Network link DOWN");
                 self.network_was_reset = true;
                 self.stack.lock(|stack| stack.handle_link_reset());
             }
@@ -55,6 +193,15 @@ impl NetworkProcessor {
         };
     }
 
+    /// Get the most recent ethernet link state.
+    ///
+    /// # Note
+    /// The link state is cached from the last call to [NetworkProcessor::handle_link] and does not
+    /// communicate with the PHY.
+    pub fn link_status(&self) -> LinkState {
+        self.link
+    }
+
     /// Process and update the state of the network.
     ///
     /// # Note
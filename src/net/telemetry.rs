@@ -10,21 +10,92 @@
 ///! sampling frequency. Instead, the raw codes are stored and the telemetry is generated as
 ///! required immediately before transmission. This ensures that any slower computation required
 ///! for unit conversion can be off-loaded to lower priority tasks.
+///!
+///! # Versioning
+///! Every telemetry message carries a `format_version` (see [TELEMETRY_FORMAT_VERSION]). The
+///! version is incremented whenever a change to the telemetry structure breaks existing consumers,
+///! i.e. when a field is removed, renamed, or changes its type or unit. Adding a new field is not
+///! considered a breaking change.
+///!
+///! # Discovery
+///! Once connected to the broker, the telemetry client publishes a retained [Meta] payload to
+///! `<prefix>/meta`, describing the application and its configuration. Tooling can subscribe to
+///! `<root>/+/+/meta` to enumerate devices without probing them.
+use core::fmt::Write;
 use heapless::{String, Vec};
+use miniconf::Miniconf;
 use minimq::{QoS, Retain};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use super::NetworkReference;
-use crate::hardware::{adc::AdcCode, afe::Gain, dac::DacCode, SystemTimer};
+use super::{
+    backoff::BackoffStack, data_stream::StreamConfig,
+    network_processor::LinkState,
+};
+use crate::hardware::{
+    adc::AdcCode, afe::Gain, dac::DacCode, design_parameters,
+    pounder::PounderTelemetry, SystemTimer,
+};
+use crate::logging;
 use minimq::embedded_nal::IpAddr;
+use smoltcp_nal::smoltcp::wire::EthernetAddress;
+
+/// The version of the telemetry message format reported in [Telemetry::format_version].
+pub const TELEMETRY_FORMAT_VERSION: u8 = 1;
+
+// The maximum size of serialized telemetry in bytes. In the worst case, with every number at its
+// longest representation, a 64 character settings path, Pounder telemetry and the `profile`
+// feature enabled, the combined telemetry serializes to about 1020 bytes.
+const TELEMETRY_SIZE: usize = 1536;
+
+// The size of the MQTT message buffer in bytes. A publication holds the serialized telemetry, the
+// topic of at most 128 bytes and an MQTT packet header of at most 6 bytes.
+const MESSAGE_SIZE: usize = TELEMETRY_SIZE + 256;
+
+type MqttClient = minimq::Minimq<BackoffStack, SystemTimer, MESSAGE_SIZE, 1>;
 
 /// The telemetry client for reporting telemetry data over MQTT.
 pub struct TelemetryClient<T: Serialize> {
-    mqtt: minimq::Minimq<NetworkReference, SystemTimer, 512, 1>,
+    mqtt: MqttClient,
     telemetry_topic: String<128>,
+    meta_topic: String<128>,
+    meta: Option<Meta>,
+    meta_published: bool,
     _telemetry: core::marker::PhantomData<T>,
 }
 
+/// The publication periods of the telemetry sub-topics.
+///
+/// # Note
+/// In addition to the combined telemetry, individual metric groups can be published to their own
+/// sub-topic below `<prefix>/telemetry/`. Each sub-topic carries the same value as the
+/// corresponding field of the combined [Telemetry].
+///
+/// The `status` sub-topic carries a compact [Status] summary instead, which is not part of the
+/// combined telemetry.
+///
+/// # Miniconf
+/// `{"adc": <period>, "pounder": <period>, "temperature": <period>, "status": <period>}`
+///
+/// * `<period>` is the publication period of the sub-topic in seconds. Zero disables the
+///   sub-topic.
+///
+/// ## Example
+/// `{"adc": 1, "pounder": 0, "temperature": 60, "status": 10}`
+#[derive(Copy, Clone, Debug, Default, Miniconf, Serialize, Deserialize)]
+pub struct TelemetryTopicPeriods {
+    /// The period of the `telemetry/adc` sub-topic, carrying the input voltages.
+    pub adc: u16,
+
+    /// The period of the `telemetry/pounder` sub-topic, carrying the Pounder telemetry.
+    pub pounder: u16,
+
+    /// The period of the `telemetry/temperature` sub-topic, carrying the CPU temperature.
+    pub temperature: u16,
+
+    /// The period of the `telemetry/status` sub-topic, carrying the device [Status].
+    pub status: u16,
+}
+
 /// The telemetry buffer is used for storing sample values during execution.
 ///
 /// # Note
@@ -39,6 +110,179 @@ pub struct TelemetryBuffer {
     pub dacs: [DacCode; 2],
     /// The latest digital input states during processing.
     pub digital_inputs: [bool; 2],
+    /// The result of the most recent Pounder DDS self-test, if one was requested.
+    pub dds_self_test: Option<bool>,
+    /// The number of output samples clamped to the output limits on DAC0/DAC1.
+    pub output_clamps: [u32; 2],
+    /// The number of stream frames dropped due to a lack of frame buffers.
+    pub stream_dropped_frames: u32,
+    /// Specified true while the Pounder output attenuation is increased due to over-temperature.
+    pub pounder_throttled: bool,
+    /// Specified true if the most recent settings update was applied without rejecting a value.
+    pub settings_valid: bool,
+    /// Specified true if Pounder was detected.
+    pub pounder_present: bool,
+    /// The number of recovered SPI errors of ADC0, ADC1, DAC0 and DAC1.
+    pub spi_errors: [u32; 4],
+    /// The measured DSP processing time.
+    #[cfg(feature = "profile")]
+    pub processing_time: ProcessingTime,
+}
+
+/// The DSP processing time measured using the CPU cycle counter.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ProcessingTime {
+    /// The number of CPU cycles used to process the most recent batch.
+    pub last: u32,
+    /// The largest number of CPU cycles used to process a batch since boot.
+    pub max: u32,
+    /// The number of batches that used more than [ProcessingTime::NEAR_MISS_PERCENT] of the
+    /// batch period.
+    pub near_misses: u32,
+}
+
+impl ProcessingTime {
+    /// The percentage of the batch period above which processing is counted as a near miss.
+    pub const NEAR_MISS_PERCENT: u32 = 90;
+
+    /// Record the processing time of a batch.
+    ///
+    /// # Args
+    /// * `cycles` - The number of CPU cycles used to process the batch.
+    /// * `budget` - The number of CPU cycles in the batch period.
+    pub fn record(&mut self, cycles: u32, budget: u32) {
+        self.last = cycles;
+        self.max = self.max.max(cycles);
+        if cycles as u64 * 100 > budget as u64 * Self::NEAR_MISS_PERCENT as u64
+        {
+            self.near_misses = self.near_misses.wrapping_add(1);
+        }
+    }
+}
+
+/// The DSP processing time reported in telemetry.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct ProcessingTelemetry {
+    /// The time used to process the most recent batch in seconds.
+    pub last: f32,
+
+    /// The longest time used to process a batch since boot in seconds.
+    pub max: f32,
+
+    /// The number of batches whose processing took longer than 90% of the batch period. The count
+    /// wraps around on overflow.
+    pub near_misses: u32,
+}
+
+impl From<ProcessingTime> for ProcessingTelemetry {
+    fn from(time: ProcessingTime) -> Self {
+        let cycle_period = 1.0 / design_parameters::SYSCLK.to_Hz() as f32;
+        Self {
+            last: time.last as f32 * cycle_period,
+            max: time.max as f32 * cycle_period,
+            near_misses: time.near_misses,
+        }
+    }
+}
+
+/// A compact summary of the device health.
+///
+/// # Note
+/// The status is only received by subscribers while the telemetry MQTT client is connected to the
+/// broker, so the MQTT connection state is not reported separately. The AD9959 DDS on Pounder does
+/// not indicate whether its PLL is locked. The result of the DDS self-test is reported instead.
+#[derive(Serialize, Copy, Clone, Debug)]
+pub struct Status {
+    /// The ethernet link state.
+    pub link: LinkState,
+
+    /// Specified true if the most recent settings update was applied without rejecting a value.
+    /// Rejected values are logged and the previous value is retained.
+    pub settings_valid: bool,
+
+    /// Specified true if Pounder was detected. Pounder settings are not applied otherwise.
+    pub pounder_present: bool,
+
+    /// The result of the most recent Pounder DDS self-test. `null` if no self-test was requested.
+    pub dds_self_test: Option<bool>,
+
+    /// Specified true while the Pounder output attenuation is increased due to over-temperature.
+    pub pounder_throttled: bool,
+
+    /// The total number of output samples clamped to the output limits since boot. The counts
+    /// wrap around on overflow.
+    pub output_clamps: [u32; 2],
+
+    /// The total number of stream frames dropped before transmission. The count wraps around on
+    /// overflow.
+    pub stream_dropped_frames: u32,
+
+    /// The total number of recovered SPI errors of ADC0, ADC1, DAC0 and DAC1 since boot.
+    pub spi_errors: [u32; 4],
+}
+
+/// A self-describing summary of the device, published retained to `<prefix>/meta`.
+#[derive(Serialize, Clone, Debug)]
+pub struct Meta {
+    /// The name of the application.
+    pub app: String<32>,
+
+    /// The firmware version.
+    pub version: &'static str,
+
+    /// The MAC address of the device.
+    pub mac: String<17>,
+
+    /// Specified true if Pounder was detected.
+    pub pounder_present: bool,
+
+    /// The number of samples processed in each batch.
+    pub batch_size: usize,
+
+    /// The ADC/DAC sample period in seconds.
+    pub sample_period: f32,
+}
+
+impl Meta {
+    /// Construct the device summary.
+    ///
+    /// # Args
+    /// * `app` - The name of the application.
+    /// * `mac` - The ethernet MAC address of the device.
+    /// * `pounder_present` - Specified true if Pounder was detected.
+    /// * `batch_size` - The number of samples processed in each batch.
+    /// * `sample_period` - The ADC/DAC sample period in seconds.
+    pub fn new(
+        app: &str,
+        mac: EthernetAddress,
+        pounder_present: bool,
+        batch_size: usize,
+        sample_period: f32,
+    ) -> Self {
+        // Note(unwrap): The application name must be short enough to fit into the string. The
+        // MAC address always fits.
+        let mut mac_string = String::new();
+        write!(&mut mac_string, "{}", mac).unwrap();
+
+        Self {
+            app: String::from(app),
+            version: env!("CARGO_PKG_VERSION"),
+            mac: mac_string,
+            pounder_present,
+            batch_size,
+            sample_period,
+        }
+    }
+}
+
+/// The settings applied by the device.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SettingsVersion {
+    /// The number of settings changes applied since boot. The count wraps around on overflow.
+    pub version: u32,
+
+    /// The path of the most recently changed setting. Empty if no setting was changed since boot.
+    pub path: String<64>,
 }
 
 /// The telemetry structure is data that is ultimately reported as telemetry over MQTT.
@@ -48,6 +292,9 @@ pub struct TelemetryBuffer {
 /// overhead.
 #[derive(Serialize)]
 pub struct Telemetry {
+    /// The version of the telemetry format. See [TELEMETRY_FORMAT_VERSION].
+    pub format_version: u8,
+
     /// Most recent input voltage measurement.
     pub adcs: [f32; 2],
 
@@ -57,8 +304,54 @@ pub struct Telemetry {
     /// Most recent digital input assertion state.
     pub digital_inputs: [bool; 2],
 
+    /// The AFE gains commanded to the hardware, used for scaling the input voltages.
+    pub afe_gains: [Gain; 2],
+
     /// The CPU temperature in degrees Celsius.
     pub cpu_temp: f32,
+
+    /// The result of the most recent Pounder DDS self-test. `null` if no self-test was requested.
+    pub dds_self_test: Option<bool>,
+
+    /// The ethernet link state.
+    pub link: LinkState,
+
+    /// The total number of output samples clamped to the output limits since boot. The counts
+    /// wrap around on overflow.
+    pub output_clamps: [u32; 2],
+
+    /// The total number of stream frames dropped before transmission because they were generated
+    /// faster than they could be sent. The count wraps around on overflow.
+    pub stream_dropped_frames: u32,
+
+    /// The total number of recovered SPI errors of ADC0, ADC1, DAC0 and DAC1 since boot. Errors
+    /// are only recovered if the application is configured to do so. Repeated errors indicate a
+    /// hardware problem. The counts wrap around on overflow.
+    pub spi_errors: [u32; 4],
+
+    /// Pounder telemetry. `null` if Pounder is not detected.
+    pub pounder: Option<PounderTelemetry>,
+
+    /// Specified true if the Pounder temperature exceeded the derating threshold when last
+    /// sampled, such that the Pounder output attenuation was increased.
+    pub pounder_throttled: bool,
+
+    /// The ADC/DAC sample period in seconds. This is the timebase of the streamed data.
+    pub sample_period: f32,
+
+    /// The settings changes applied since boot. Monitoring can compare the version with the
+    /// number of changes pushed to confirm that the device runs the intended settings.
+    pub settings: SettingsVersion,
+
+    /// The time since boot in seconds. A decrease indicates that the device rebooted.
+    pub uptime_s: u64,
+
+    /// The current format and target of the data stream.
+    pub stream: StreamConfig,
+
+    /// The DSP processing time. Only reported if the `profile` feature is enabled.
+    #[cfg(feature = "profile")]
+    pub processing_time: ProcessingTelemetry,
 }
 
 impl Default for TelemetryBuffer {
@@ -67,6 +360,15 @@ impl Default for TelemetryBuffer {
             adcs: [AdcCode(0), AdcCode(0)],
             dacs: [DacCode(0), DacCode(0)],
             digital_inputs: [false, false],
+            dds_self_test: None,
+            output_clamps: [0, 0],
+            stream_dropped_frames: 0,
+            pounder_throttled: false,
+            settings_valid: true,
+            pounder_present: false,
+            spi_errors: [0; 4],
+            #[cfg(feature = "profile")]
+            processing_time: ProcessingTime::default(),
         }
     }
 }
@@ -78,18 +380,71 @@ impl TelemetryBuffer {
     /// * `afe0` - The current AFE configuration for channel 0.
     /// * `afe1` - The current AFE configuration for channel 1.
     /// * `cpu_temp` - The current CPU temperature.
+    /// * `link` - The current ethernet link state.
+    /// * `pounder` - The current Pounder telemetry, if Pounder is present.
+    /// * `sample_period` - The ADC/DAC sample period in seconds.
+    /// * `settings` - The settings changes applied since boot.
+    /// * `uptime_s` - The time since boot in seconds.
+    /// * `stream` - The current configuration of the data stream.
     ///
     /// # Returns
     /// The finalized telemetry structure that can be serialized and reported.
-    pub fn finalize(self, afe0: Gain, afe1: Gain, cpu_temp: f32) -> Telemetry {
+    #[allow(clippy::too_many_arguments)]
+    pub fn finalize(
+        self,
+        afe0: Gain,
+        afe1: Gain,
+        cpu_temp: f32,
+        link: LinkState,
+        pounder: Option<PounderTelemetry>,
+        sample_period: f32,
+        settings: SettingsVersion,
+        uptime_s: u64,
+        stream: StreamConfig,
+    ) -> Telemetry {
         let in0_volts = Into::<f32>::into(self.adcs[0]) / afe0.as_multiplier();
         let in1_volts = Into::<f32>::into(self.adcs[1]) / afe1.as_multiplier();
 
         Telemetry {
+            format_version: TELEMETRY_FORMAT_VERSION,
             cpu_temp,
+            afe_gains: [afe0, afe1],
             adcs: [in0_volts, in1_volts],
             dacs: [self.dacs[0].into(), self.dacs[1].into()],
             digital_inputs: self.digital_inputs,
+            dds_self_test: self.dds_self_test,
+            link,
+            output_clamps: self.output_clamps,
+            stream_dropped_frames: self.stream_dropped_frames,
+            spi_errors: self.spi_errors,
+            pounder,
+            pounder_throttled: self.pounder_throttled,
+            sample_period,
+            settings,
+            uptime_s,
+            stream,
+            #[cfg(feature = "profile")]
+            processing_time: self.processing_time.into(),
+        }
+    }
+
+    /// Summarize the device health.
+    ///
+    /// # Args
+    /// * `link` - The current ethernet link state.
+    ///
+    /// # Returns
+    /// The device status that can be serialized and reported.
+    pub fn status(&self, link: LinkState) -> Status {
+        Status {
+            link,
+            settings_valid: self.settings_valid,
+            pounder_present: self.pounder_present,
+            dds_self_test: self.dds_self_test,
+            pounder_throttled: self.pounder_throttled,
+            output_clamps: self.output_clamps,
+            stream_dropped_frames: self.stream_dropped_frames,
+            spi_errors: self.spi_errors,
         }
     }
 }
@@ -98,30 +453,42 @@ impl<T: Serialize> TelemetryClient<T> {
     /// Construct a new telemetry client.
     ///
     /// # Args
-    /// * `stack` - A reference to the (shared) underlying network stack.
+    /// * `stack` - The (shared) underlying network stack.
     /// * `clock` - A `SystemTimer` implementing `Clock`.
     /// * `client_id` - The MQTT client ID of the telemetry client.
     /// * `prefix` - The device prefix to use for MQTT telemetry reporting.
     /// * `broker` - The IP address of the MQTT broker to use.
+    /// * `keepalive` - The MQTT keep-alive interval in seconds.
     ///
     /// # Returns
     /// A new telemetry client.
     pub fn new(
-        stack: NetworkReference,
+        stack: BackoffStack,
         clock: SystemTimer,
         client_id: &str,
         prefix: &str,
         broker: IpAddr,
+        keepalive: u16,
     ) -> Self {
-        let mqtt =
+        let mut mqtt =
             minimq::Minimq::new(broker, client_id, stack, clock).unwrap();
 
+        // Note(unwrap): The client is not yet connected to the broker, so the keep-alive interval
+        // can be set.
+        mqtt.client.set_keepalive_interval(keepalive).unwrap();
+
         let mut telemetry_topic: String<128> = String::from(prefix);
         telemetry_topic.push_str("/telemetry").unwrap();
 
+        let mut meta_topic: String<128> = String::from(prefix);
+        meta_topic.push_str("/meta").unwrap();
+
         Self {
             mqtt,
             telemetry_topic,
+            meta_topic,
+            meta: None,
+            meta_published: false,
             _telemetry: core::marker::PhantomData::default(),
         }
     }
@@ -129,24 +496,126 @@ impl<T: Serialize> TelemetryClient<T> {
     /// Publish telemetry over MQTT
     ///
     /// # Note
-    /// Telemetry is reported in a "best-effort" fashion. Failure to transmit telemetry will cause
-    /// it to be silently dropped.
+    /// Telemetry is reported in a "best-effort" fashion. Failure to serialize or transmit
+    /// telemetry is logged and the telemetry is dropped.
     ///
     /// # Args
     /// * `telemetry` - The telemetry to report
     pub fn publish(&mut self, telemetry: &T) {
-        let telemetry: Vec<u8, 512> =
-            serde_json_core::to_vec(telemetry).unwrap();
-        self.mqtt
+        Self::publish_message(&mut self.mqtt, &self.telemetry_topic, telemetry);
+    }
+
+    /// Publish a telemetry sub-structure to a sub-topic over MQTT
+    ///
+    /// # Note
+    /// Telemetry is reported in a "best-effort" fashion. Failure to serialize or transmit
+    /// telemetry is logged and the telemetry is dropped.
+    ///
+    /// # Args
+    /// * `topic` - The name of the sub-topic below the telemetry topic, e.g. `temperature`.
+    /// * `value` - The telemetry sub-structure to report.
+    pub fn publish_topic<S: Serialize>(&mut self, topic: &str, value: &S) {
+        let mut sub_topic: String<128> = self.telemetry_topic.clone();
+        if sub_topic.push('/').is_err() || sub_topic.push_str(topic).is_err() {
+            logging::warn!("Telemetry topic too long: {}", topic);
+            return;
+        }
+
+        Self::publish_message(&mut self.mqtt, &sub_topic, value);
+    }
+
+    /// Serialize a value and publish it to a topic.
+    ///
+    /// # Args
+    /// * `mqtt` - The MQTT client to publish with.
+    /// * `topic` - The topic to publish to.
+    /// * `value` - The value to report.
+    fn publish_message<S: Serialize>(
+        mqtt: &mut MqttClient,
+        topic: &str,
+        value: &S,
+    ) {
+        let value: Vec<u8, TELEMETRY_SIZE> =
+            match serde_json_core::to_vec(value) {
+                Ok(value) => value,
+                Err(error) => {
+                    logging::warn!(
+                        "Failed to serialize telemetry for {}: {:?}",
+                        topic,
+                        error
+                    );
+                    return;
+                }
+            };
+
+        if let Err(error) = mqtt.client.publish(
+            topic,
+            &value,
+            QoS::AtMostOnce,
+            Retain::NotRetained,
+            &[],
+        ) {
+            logging::warn!(
+                "Failed to publish telemetry to {}: {:?}",
+                topic,
+                error
+            );
+        }
+    }
+
+    /// Set the device summary published to the `meta` topic.
+    ///
+    /// # Note
+    /// The summary is published retained whenever the client connects to the broker and again
+    /// whenever it changes.
+    ///
+    /// # Args
+    /// * `meta` - The device summary.
+    pub fn set_meta(&mut self, meta: Meta) {
+        self.meta.replace(meta);
+        self.meta_published = false;
+    }
+
+    /// Update the sample period of the device summary. See [TelemetryClient::set_meta].
+    ///
+    /// # Args
+    /// * `sample_period` - The ADC/DAC sample period in seconds.
+    pub fn set_meta_sample_period(&mut self, sample_period: f32) {
+        if let Some(meta) = self.meta.as_mut() {
+            meta.sample_period = sample_period;
+            self.meta_published = false;
+        }
+    }
+
+    /// Publish the device summary if it has not yet been published since connecting.
+    fn publish_meta(&mut self) {
+        if !self.mqtt.client.is_connected() {
+            self.meta_published = false;
+            return;
+        }
+
+        let meta = match &self.meta {
+            Some(meta) if !self.meta_published => meta,
+            _ => return,
+        };
+
+        let meta: Vec<u8, 256> = serde_json_core::to_vec(meta).unwrap();
+        self.meta_published = self
+            .mqtt
             .client
             .publish(
-                &self.telemetry_topic,
-                &telemetry,
+                &self.meta_topic,
+                &meta,
                 QoS::AtMostOnce,
-                Retain::NotRetained,
+                Retain::Retained,
                 &[],
             )
-            .ok();
+            .is_ok();
+    }
+
+    /// Check if the telemetry client is connected to the MQTT broker.
+    pub fn is_connected(&mut self) -> bool {
+        self.mqtt.client.is_connected()
     }
 
     /// Update the telemetry client
@@ -161,8 +630,10 @@ impl<T: Serialize> TelemetryClient<T> {
                 smoltcp_nal::NetworkError::NoIpAddress,
             )) => {}
 
-            Err(error) => log::info!("Unexpected error: {:?}", error),
+            Err(error) => logging::info!("Unexpected error: {:?}", error),
             _ => {}
         }
+
+        self.publish_meta();
     }
 }